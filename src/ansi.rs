@@ -0,0 +1,246 @@
+use pulldown_cmark_escape::escape_html;
+
+/// Names of the 8 basic terminal colours, used in `ansi-fg-*`/`ansi-bg-*` classes
+const COLOURS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Text attributes set by SGR escape sequences
+#[derive(Default, Clone, PartialEq)]
+struct Style {
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+    foreground: Option<Colour>,
+    background: Option<Colour>,
+}
+
+#[derive(Clone, PartialEq)]
+enum Colour {
+    /// One of the 16 named colours, `bright` for the 90-97/100-107 range
+    Named { index: usize, bright: bool },
+    /// 256-colour palette or true colour, rendered as an inline style
+    Rgb(u8, u8, u8),
+}
+
+impl Colour {
+    /// Class for named colours, CSS colour value for the rest
+    fn class(&self, layer: &str) -> Option<String> {
+        match self {
+            Colour::Named { index, bright } => Some(format!(
+                "ansi-{layer}-{}{}",
+                if *bright { "bright-" } else { "" },
+                COLOURS[*index]
+            )),
+            Colour::Rgb(..) => None,
+        }
+    }
+
+    fn css(&self) -> Option<String> {
+        match self {
+            Colour::Rgb(r, g, b) => Some(format!("#{r:02x}{g:02x}{b:02x}")),
+            Colour::Named { .. } => None,
+        }
+    }
+
+    /// Colour of the xterm 256-colour palette
+    fn palette(index: u8) -> Colour {
+        match index {
+            0..=7 => Colour::Named {
+                index: index as usize,
+                bright: false,
+            },
+            8..=15 => Colour::Named {
+                index: index as usize - 8,
+                bright: true,
+            },
+            16..=231 => {
+                let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+                let index = index - 16;
+                Colour::Rgb(level(index / 36), level(index / 6 % 6), level(index % 6))
+            }
+            232..=255 => {
+                let grey = 8 + (index - 232) * 10;
+                Colour::Rgb(grey, grey, grey)
+            }
+        }
+    }
+
+    /// Extended colour following a 38/48 code, consuming its parameters
+    fn extended(params: &mut impl Iterator<Item = u8>) -> Option<Colour> {
+        match params.next()? {
+            5 => params.next().map(Colour::palette),
+            2 => Some(Colour::Rgb(params.next()?, params.next()?, params.next()?)),
+            _ => None,
+        }
+    }
+}
+
+impl Style {
+    /// Apply the parameters of one `ESC[...m` sequence
+    fn apply(&mut self, params: &str) {
+        let mut params = params
+            .split(';')
+            .map(|param| param.parse::<u8>().unwrap_or(0));
+        while let Some(code) = params.next() {
+            match code {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => (self.bold, self.dim) = (false, false),
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => {
+                    self.foreground = Some(Colour::Named {
+                        index: (code - 30) as usize,
+                        bright: false,
+                    })
+                }
+                38 => self.foreground = Colour::extended(&mut params),
+                39 => self.foreground = None,
+                40..=47 => {
+                    self.background = Some(Colour::Named {
+                        index: (code - 40) as usize,
+                        bright: false,
+                    })
+                }
+                48 => self.background = Colour::extended(&mut params),
+                49 => self.background = None,
+                90..=97 => {
+                    self.foreground = Some(Colour::Named {
+                        index: (code - 90) as usize,
+                        bright: true,
+                    })
+                }
+                100..=107 => {
+                    self.background = Some(Colour::Named {
+                        index: (code - 100) as usize,
+                        bright: true,
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Opening `<span>` for the style, `None` when it is the default one
+    fn open(&self) -> Option<String> {
+        let mut classes = Vec::new();
+        for (set, class) in [
+            (self.bold, "ansi-bold"),
+            (self.dim, "ansi-dim"),
+            (self.italic, "ansi-italic"),
+            (self.underline, "ansi-underline"),
+        ] {
+            if set {
+                classes.push(class.to_owned());
+            }
+        }
+        classes.extend(self.foreground.as_ref().and_then(|c| c.class("fg")));
+        classes.extend(self.background.as_ref().and_then(|c| c.class("bg")));
+
+        let mut styles = Vec::new();
+        styles.extend(
+            self.foreground
+                .as_ref()
+                .and_then(Colour::css)
+                .map(|c| format!("color:{c}")),
+        );
+        styles.extend(
+            self.background
+                .as_ref()
+                .and_then(Colour::css)
+                .map(|c| format!("background-color:{c}")),
+        );
+
+        if classes.is_empty() && styles.is_empty() {
+            return None;
+        }
+        let mut span = String::from("<span");
+        if !classes.is_empty() {
+            span.push_str(&format!(" class=\"{}\"", classes.join(" ")));
+        }
+        if !styles.is_empty() {
+            span.push_str(&format!(" style=\"{}\"", styles.join(";")));
+        }
+        span.push('>');
+        Some(span)
+    }
+}
+
+/// Escaped text in `style`, switching spans only when the style of the text
+/// actually changes so runs of escape codes don't leave empty spans behind
+#[derive(Default)]
+struct Writer {
+    html: String,
+    /// Style of the currently open span
+    current: Style,
+    open: bool,
+}
+
+impl Writer {
+    fn text(&mut self, text: &str, style: &Style) {
+        if text.is_empty() {
+            return;
+        }
+        if *style != self.current {
+            if self.open {
+                self.html.push_str("</span>");
+            }
+            self.current = style.clone();
+            self.open = match style.open() {
+                Some(span) => {
+                    self.html.push_str(&span);
+                    true
+                }
+                None => false,
+            };
+        }
+        let _ = escape_html(&mut self.html, text);
+    }
+
+    fn finish(mut self) -> String {
+        if self.open {
+            self.html.push_str("</span>");
+        }
+        self.html
+    }
+}
+
+/// Convert terminal output with ANSI escape codes into escaped HTML, colours and
+/// text attributes becoming `<span>`s. Escape sequences other than SGR are dropped.
+pub(crate) fn to_html(output: &str) -> String {
+    let mut writer = Writer::default();
+    let mut style = Style::default();
+    let mut rest = output;
+
+    while let Some(start) = rest.find('\x1b') {
+        writer.text(&rest[..start], &style);
+        let sequence = &rest[start + 1..];
+        rest = match sequence.strip_prefix('[') {
+            // CSI: parameters and intermediates up to a final byte in `@`..=`~`
+            Some(csi) => match csi.find(|c: char| ('@'..='~').contains(&c)) {
+                Some(end) => {
+                    if csi[end..].starts_with('m') {
+                        style.apply(&csi[..end]);
+                    }
+                    &csi[end + 1..]
+                }
+                None => "",
+            },
+            // OSC (titles, hyperlinks): up to BEL or ST
+            None if sequence.starts_with(']') => match sequence.find(['\x07', '\x1b']) {
+                Some(end) if sequence[end..].starts_with("\x1b\\") => &sequence[end + 2..],
+                Some(end) => &sequence[end + 1..],
+                None => "",
+            },
+            // Two character escapes
+            None => sequence.get(1..).unwrap_or(""),
+        };
+    }
+    writer.text(rest, &style);
+    writer.finish()
+}
@@ -0,0 +1,116 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use flate2::{write::GzEncoder, Compression, GzBuilder};
+
+use crate::manifest;
+
+/// Files of the build in `dir` as its manifest lists them, and the manifest
+/// itself, in a stable order. Preserved paths and whatever else the build
+/// didn't write are left out.
+fn build_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = manifest::read(dir)?.into_keys().collect();
+    files.extend(
+        [manifest::FILE, manifest::SIGNATURE_FILE]
+            .into_iter()
+            .map(PathBuf::from)
+            .filter(|file| dir.join(file).is_file()),
+    );
+    files.sort();
+    Ok(files)
+}
+
+/// Append every file of the build in `dir` to the archive with normalised
+/// metadata so the same output always produces the same archive bytes
+fn append_all<W: Write>(builder: &mut tar::Builder<W>, dir: &Path) -> Result<()> {
+    for relative in build_files(dir)? {
+        let source = dir.join(&relative);
+        let contents = std::fs::read(&source)
+            .with_context(|| anyhow!("Unable to read output file [{}]", source.display()))?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder
+            .append_data(&mut header, &relative, contents.as_slice())
+            .with_context(|| anyhow!("Unable to add [{}] to the archive", relative.display()))?;
+    }
+    Ok(())
+}
+
+/// Pack the build in `dir` into a deterministic `.tar`, `.tar.gz` or `.tgz`
+/// archive at `destination`
+pub fn write(dir: &Path, destination: &Path) -> Result<()> {
+    let name = destination.to_string_lossy();
+    let file = File::create(destination)
+        .with_context(|| anyhow!("Unable to create archive [{}]", destination.display()))?;
+
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        // No file name or timestamp in the gzip header, to keep it reproducible
+        let encoder: GzEncoder<File> = GzBuilder::new()
+            .mtime(0)
+            .write(file, Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        append_all(&mut builder, dir)?;
+        builder.into_inner()?.finish()?;
+    } else if name.ends_with(".tar") {
+        let mut builder = tar::Builder::new(file);
+        append_all(&mut builder, dir)?;
+        builder.into_inner()?;
+    } else {
+        return Err(anyhow!(
+            "Unsupported archive format [{}], expected .tar, .tar.gz or .tgz",
+            destination.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_only_the_files_of_the_build() {
+        let dir = std::env::temp_dir().join(format!("mub-archive-{}", std::process::id()));
+        let output = dir.join("dist");
+        std::fs::create_dir_all(output.join("posts")).unwrap();
+        std::fs::create_dir_all(output.join(".git")).unwrap();
+        std::fs::write(output.join("index.html"), "index").unwrap();
+        std::fs::write(output.join("posts/post.html"), "post").unwrap();
+        std::fs::write(output.join("CNAME"), "example.com").unwrap();
+        std::fs::write(output.join(".git/HEAD"), "ref").unwrap();
+        std::fs::write(output.join("stale.html"), "stale").unwrap();
+        std::fs::write(
+            output.join(manifest::FILE),
+            r#"{"index.html": "a", "posts/post.html": "b"}"#,
+        )
+        .unwrap();
+
+        let archive = dir.join("site.tar");
+        write(&output, &archive).unwrap();
+        let mut packed = tar::Archive::new(File::open(&archive).unwrap());
+        let names: Vec<PathBuf> = packed
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                PathBuf::from(manifest::FILE),
+                PathBuf::from("index.html"),
+                PathBuf::from("posts/post.html"),
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
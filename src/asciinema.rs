@@ -0,0 +1,77 @@
+use std::path::Path;
+
+use pulldown_cmark_escape::escape_href;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{ansi, config::Config};
+
+fn default_script() -> String {
+    String::from("/asciinema-player.min.js")
+}
+
+fn default_stylesheet() -> String {
+    String::from("/asciinema-player.css")
+}
+
+/// Self-hosted asciinema player used for `asciinema` blocks
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AsciinemaConfig {
+    /// URL of the player script, shipped from the include directory
+    #[serde(default = "default_script")]
+    pub(crate) script: String,
+    /// URL of the player stylesheet
+    #[serde(default = "default_stylesheet")]
+    pub(crate) stylesheet: String,
+}
+
+/// Everything the cast prints, from the `"o"` events of an asciicast v2 file
+fn output(cast: &str) -> String {
+    cast.lines()
+        .skip(1)
+        .filter_map(|line| serde_json::from_str::<(f64, String, String)>(line).ok())
+        .filter(|(_, kind, _)| kind == "o")
+        .map(|(_, _, data)| data)
+        .collect::<String>()
+        .replace("\r\n", "\n")
+}
+
+/// Markup for a cast: its output as a static `<pre>` that the player, when
+/// configured, replaces once its script loads
+pub(crate) fn embed(
+    url: &str,
+    source: Option<&Path>,
+    player: Option<&AsciinemaConfig>,
+    config: &Config,
+    first: bool,
+) -> String {
+    let fallback = source
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|cast| ansi::to_html(output(&cast).trim_end()))
+        .unwrap_or_default();
+
+    let mut href = String::new();
+    let _ = escape_href(&mut href, url);
+    let mut html = format!(
+        "<div class=\"asciinema\" data-cast=\"{href}\"><pre class=\"terminal\"><code>{fallback}</code></pre></div>\n"
+    );
+    // The first cast of a page pulls in the player, which upgrades every cast
+    if let (Some(player), true) = (player, first) {
+        let local = |url: &str| match url.starts_with('/') && !url.starts_with("//") {
+            true => config.site_url(url),
+            false => url.to_owned(),
+        };
+        let (mut stylesheet, mut script) = (String::new(), String::new());
+        let _ = escape_href(&mut stylesheet, &local(&player.stylesheet));
+        let _ = escape_href(&mut script, &local(&player.script));
+        html.push_str(&format!(
+            "<link rel=\"stylesheet\" href=\"{stylesheet}\" />\n\
+             <script src=\"{script}\"></script>\n\
+             <script>addEventListener(\"DOMContentLoaded\", () => \
+             document.querySelectorAll(\"div.asciinema\").forEach((el) => {{ \
+             el.replaceChildren(); AsciinemaPlayer.create(el.dataset.cast, el); }}));</script>\n"
+        ));
+    }
+    html
+}
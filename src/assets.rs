@@ -0,0 +1,152 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::{config::Config, types::AvailableContent};
+
+/// Whether a reference points somewhere outside of the generated site
+pub(crate) fn is_external(reference: &str) -> bool {
+    reference.is_empty()
+        || reference.starts_with('#')
+        || reference.starts_with("//")
+        || reference.contains("://")
+        || reference.starts_with("mailto:")
+        || reference.starts_with("tel:")
+        || reference.starts_with("data:")
+}
+
+/// Whether a markdown link points at a local file rather than another page
+pub(crate) fn is_file_link(reference: &str) -> bool {
+    if is_external(reference) {
+        return false;
+    }
+    let path = strip_query(reference);
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(ext) => !matches!(ext, "html" | "htm" | "md"),
+        None => false,
+    }
+}
+
+fn strip_query(reference: &str) -> &str {
+    reference.split(['?', '#']).next().unwrap_or(reference)
+}
+
+/// Turn a reference found in a page at `page_url` into a path relative to the output root
+pub(crate) fn resolve(page_url: &Path, reference: &str) -> Option<PathBuf> {
+    if is_external(reference) {
+        return None;
+    }
+    let reference = strip_query(reference);
+    let joined = match reference.strip_prefix('/') {
+        Some(absolute) => PathBuf::from(absolute),
+        None => page_url.parent().unwrap_or(Path::new("")).join(reference),
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(part) => resolved.push(part),
+            _ => {}
+        }
+    }
+    Some(resolved)
+}
+
+/// Alt text waiving the need for a description on decorative images, as in `![-](border.png)`
+pub(crate) const DECORATIVE_ALT: &str = "-";
+
+/// Title waiving the need for alt text on decorative images, as in
+/// `![](border.png "decorative")`
+pub(crate) const DECORATIVE_TITLE: &str = "decorative";
+
+/// Images of a markdown post without alt text that aren't waived as decorative
+pub(crate) fn images_without_alt(markdown: &str) -> Vec<String> {
+    use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+    let mut missing = Vec::new();
+    let mut image = None;
+    let mut alt = String::new();
+    for event in Parser::new_ext(markdown, pulldown_cmark::Options::ENABLE_FOOTNOTES) {
+        match event {
+            Event::Start(Tag::Image {
+                dest_url, title, ..
+            }) => {
+                image = (title.as_ref() != DECORATIVE_TITLE).then_some(dest_url);
+                alt.clear();
+            }
+            Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+            Event::End(TagEnd::Image) => {
+                if let Some(dest_url) = image.take() {
+                    if alt.trim().is_empty() {
+                        missing.push(dest_url.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    missing
+}
+
+/// Fail on images of published posts without alt text when
+/// `require_alt_text` is set, reporting all of them at once
+pub(crate) fn check_alt_text(content: &AvailableContent, config: &Config) -> Result<()> {
+    if !config.require_alt_text {
+        return Ok(());
+    }
+    let missing: Vec<String> = content
+        .content
+        .iter()
+        .chain(&content.home)
+        .filter(|content| content.publish)
+        .flat_map(|content| {
+            images_without_alt(&content.post.raw)
+                .into_iter()
+                .map(|image| format!("  [{}] image [{image}]", content.location.src.display()))
+        })
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "Images need alt text, or `{DECORATIVE_ALT}` as alt text when they are decorative:\n{}",
+        missing.join("\n")
+    ))
+}
+
+/// Verify that every asset referenced by a published post will be copied into
+/// the output, reporting all missing assets at once.
+pub(crate) fn check_assets(content: &AvailableContent, config: &Config) -> Result<()> {
+    let include_dirs = config.layered_dirs("include");
+    let missing: Vec<String> = content
+        .content
+        .iter()
+        .chain(&content.home)
+        .filter(|content| content.publish)
+        .flat_map(|content| {
+            content.post.assets.iter().filter_map(|asset| {
+                let resolved = resolve(&content.location.path, asset)?;
+                let found = include_dirs.iter().any(|dir| dir.join(&resolved).exists());
+                (!found).then(|| {
+                    format!(
+                        "  [{}] references missing asset [{asset}] (expected at [{}])",
+                        content.location.src.display(),
+                        include_dirs[0].join(&resolved).display()
+                    )
+                })
+            })
+        })
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "Posts reference assets that will not be present in the output:\n{}",
+        missing.join("\n")
+    ))
+}
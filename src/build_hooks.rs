@@ -0,0 +1,78 @@
+use std::{
+    fmt,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+
+use crate::{config::Config, types::Post, BuildReport};
+
+/// Code of library users run at points of the build, passed in with
+/// `Config::with_hooks`. Every method does nothing unless implemented, and an
+/// error from any of them fails the build.
+pub trait BuildHooks: Send + Sync {
+    /// Before the build reads anything
+    fn pre_build(&self, _config: &Config) -> Result<()> {
+        Ok(())
+    }
+
+    /// Content files that only exist in the hook, by their path relative to
+    /// `content/`, with what they would hold. They are parsed like any other,
+    /// front matter included.
+    fn content(&self, _config: &Config) -> Result<Vec<(PathBuf, String)>> {
+        Ok(Vec::new())
+    }
+
+    /// The body of the content file at `path`, after plugins ran on it and
+    /// before it is converted
+    fn pre_content(&self, _path: &Path, _body: &mut String) -> Result<()> {
+        Ok(())
+    }
+
+    /// The post of the content file at `path`, once its body is converted and
+    /// before anything else reads it
+    fn post_content(&self, _path: &Path, _post: &mut Post) -> Result<()> {
+        Ok(())
+    }
+
+    /// After every output of the build is written, to deploy it for example
+    fn post_build(&self, _config: &Config, _report: &BuildReport) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The hooks of a config, ones doing nothing unless set
+#[derive(Clone)]
+pub(crate) struct Hooks(Arc<dyn BuildHooks>);
+
+struct NoHooks;
+
+impl BuildHooks for NoHooks {}
+
+impl Hooks {
+    pub(crate) fn new(hooks: impl BuildHooks + 'static) -> Self {
+        Self(Arc::new(hooks))
+    }
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self::new(NoHooks)
+    }
+}
+
+impl Deref for Hooks {
+    type Target = dyn BuildHooks;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hooks")
+    }
+}
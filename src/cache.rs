@@ -0,0 +1,94 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context, Result};
+use glob::glob;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, serve::SHARED_DRAFTS_DIR, templates::PACKAGES_DIR};
+
+/// Cache directory inside the input directory, unless configured elsewhere
+pub(crate) const DEFAULT_DIR: &str = ".mub-cache";
+
+/// Work kept between builds, each kind of it in its own subdirectory
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Where the cache lives, `.mub-cache` in the input directory by default
+    #[serde(default)]
+    pub(crate) dir: Option<PathBuf>,
+    /// Size in megabytes the cache is trimmed to after every build, least
+    /// recently used entries first. Unbounded when not set.
+    #[serde(default)]
+    pub(crate) max_size: Option<u64>,
+}
+
+/// Cache directory of the site
+pub(crate) fn dir(config: &Config) -> PathBuf {
+    config
+        .cache
+        .dir
+        .clone()
+        .unwrap_or_else(|| config.input.join(DEFAULT_DIR))
+}
+
+/// Mark a cache entry as used now, so garbage collection keeps it longer
+pub(crate) fn touch(entry: &Path) {
+    if let Ok(file) = std::fs::File::options().write(true).open(entry) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+/// Remove the least recently used entries until the cache fits `max_size`
+pub(crate) fn collect_garbage(config: &Config) -> Result<()> {
+    let Some(max_size) = config.cache.max_size else {
+        return Ok(());
+    };
+    let dir = dir(config);
+    let pattern = format!("{}/**/*", dir.display());
+    // Cloned template packages and shared drafts are kept whole. Glob drops a
+    // leading `./` from the paths it yields
+    let kept: Vec<PathBuf> = [PACKAGES_DIR, SHARED_DRAFTS_DIR]
+        .into_iter()
+        .map(|name| {
+            let kept = dir.join(name);
+            kept.strip_prefix(".")
+                .map(Path::to_path_buf)
+                .unwrap_or(kept)
+        })
+        .collect();
+    let mut entries: Vec<(SystemTime, u64, PathBuf)> = glob(&pattern)
+        .with_context(|| anyhow!("Unable to glob cache directory: [{pattern}]"))?
+        .filter_map(Result::ok)
+        .filter(|path| !kept.iter().any(|kept| path.starts_with(kept)))
+        .filter_map(|path| {
+            let metadata = path.metadata().ok().filter(|metadata| metadata.is_file())?;
+            Some((metadata.modified().ok()?, metadata.len(), path))
+        })
+        .collect();
+    entries.sort();
+
+    let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    let limit = max_size * 1024 * 1024;
+    for (_, len, path) in entries {
+        if size <= limit {
+            break;
+        }
+        std::fs::remove_file(&path)
+            .with_context(|| anyhow!("Unable to remove cache entry [{path:?}]"))?;
+        size -= len;
+    }
+    Ok(())
+}
+
+/// Remove the whole cache
+pub fn clean(config: &Config) -> Result<()> {
+    let dir = dir(config);
+    if !dir.exists() {
+        return Ok(());
+    }
+    std::fs::remove_dir_all(&dir).with_context(|| anyhow!("Unable to remove cache [{dir:?}]"))
+}
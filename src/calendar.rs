@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Days, NaiveDate, Utc};
+use minijinja::{context, Environment};
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    output::Output,
+    types::{AvailableContent, BuildInfo, Content, PageUrl},
+};
+
+const CALENDAR_TEMPLATE: &str = "calendar.html";
+const CALENDAR_JSON: &str = "calendar.json";
+
+/// Empty weeks filled in before the last post at most, about ten years, so a
+/// post misdated to year 1 doesn't add a hundred thousand of them
+const MAX_FILLED_WEEKS: u64 = 520;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Published,
+    /// Dated in the future
+    Scheduled,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct Entry {
+    name: String,
+    title: String,
+    date: NaiveDate,
+    url: String,
+    status: Status,
+}
+
+#[derive(Debug, Serialize)]
+struct Month {
+    /// `YYYY-MM`
+    month: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize)]
+struct Week {
+    year: i32,
+    week: u32,
+    /// Monday of the week
+    start: NaiveDate,
+    entries: Vec<Entry>,
+    /// Nothing published or scheduled this week
+    gap: bool,
+}
+
+/// Published and scheduled posts grouped by month and by ISO week, with every
+/// week between the first and last post present so empty ones stand out
+#[derive(Debug, Serialize)]
+struct Calendar {
+    months: Vec<Month>,
+    weeks: Vec<Week>,
+}
+
+fn entry(content: &Content, today: NaiveDate) -> Option<Entry> {
+    let metadata = &content.post.metadata;
+    // Drafts and posts not meant to be published stay out of the public calendar
+    if !metadata.publish || content.draft {
+        return None;
+    }
+    let date = metadata.date.day();
+    let status = if date > today {
+        Status::Scheduled
+    } else if content.publish {
+        Status::Published
+    } else {
+        return None;
+    };
+    Some(Entry {
+        name: metadata.name.clone(),
+        title: metadata.title.clone(),
+        date,
+        url: content.location.url.clone(),
+        status,
+    })
+}
+
+impl Calendar {
+    fn build(content: &AvailableContent, today: NaiveDate) -> Self {
+        let mut entries: Vec<Entry> = content
+            .content
+            .iter()
+            .filter_map(|content| entry(content, today))
+            .collect();
+        entries.sort_by_key(|entry| entry.date);
+
+        let mut months: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+        let mut weeks: BTreeMap<NaiveDate, Vec<Entry>> = BTreeMap::new();
+        for entry in &entries {
+            months
+                .entry(entry.date.format("%Y-%m").to_string())
+                .or_default()
+                .push(entry.clone());
+            weeks
+                .entry(monday(entry.date))
+                .or_default()
+                .push(entry.clone());
+        }
+
+        // Fill in the weeks nothing landed in
+        if let (Some(first), Some(last)) = (entries.first(), entries.last()) {
+            let earliest = monday(last.date)
+                .checked_sub_days(Days::new(7 * MAX_FILLED_WEEKS))
+                .unwrap_or(NaiveDate::MIN);
+            let mut week = monday(first.date).max(earliest);
+            while week <= last.date {
+                weeks.entry(week).or_default();
+                week = week + Days::new(7);
+            }
+        }
+
+        Self {
+            months: months
+                .into_iter()
+                .map(|(month, entries)| Month { month, entries })
+                .collect(),
+            weeks: weeks
+                .into_iter()
+                .map(|(start, entries)| Week {
+                    year: start.iso_week().year(),
+                    week: start.iso_week().week(),
+                    start,
+                    gap: entries.is_empty(),
+                    entries,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn monday(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday().into())
+}
+
+/// Queue `calendar.json` and the page rendered from the `calendar.html` template
+pub(crate) fn render(
+    content: &AvailableContent,
+    templates: &Environment,
+    config: &Config,
+    build: &BuildInfo,
+    output: &Output,
+) -> Result<()> {
+    let calendar = Calendar::build(content, Utc::now().date_naive());
+    output.add(
+        config.output.join(CALENDAR_JSON),
+        serde_json::to_vec(&calendar)?,
+    )?;
+
+    let page = PageUrl::new(&config.site_url(CALENDAR_TEMPLATE), config);
+    let context = context!(calendar => calendar, page => page, build => build, ..context!(config));
+    let rendered = templates
+        .get_template(CALENDAR_TEMPLATE)
+        .with_context(|| {
+            anyhow!("Calendar is enabled but there is no [{CALENDAR_TEMPLATE}] template")
+        })?
+        .render(context)
+        .with_context(|| anyhow!("Unable to render the calendar"))?;
+    tracing::debug!(template = CALENDAR_TEMPLATE, "Rendered calendar");
+    output.add(config.output.join(CALENDAR_TEMPLATE), rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 1).unwrap()
+    }
+
+    #[test]
+    fn scheduled_posts_are_only_those_meant_to_be_published() {
+        let scheduled = Content::stub("scheduled", "2024-07-01", true);
+        let unpublished = Content::stub("unpublished", "2024-07-01", false);
+        let mut draft = Content::stub("draft", "2024-07-01", true);
+        draft.draft = true;
+
+        let status = entry(&scheduled, today()).map(|entry| entry.status);
+        assert_eq!(status, Some(Status::Scheduled));
+        assert!(entry(&unpublished, today()).is_none());
+        assert!(entry(&draft, today()).is_none());
+    }
+
+    #[test]
+    fn past_posts_are_published_unless_held_back() {
+        let published = Content::stub("published", "2024-05-01", true);
+        let mut held = Content::stub("held", "2024-05-01", true);
+        held.publish = false;
+
+        let status = entry(&published, today()).map(|entry| entry.status);
+        assert_eq!(status, Some(Status::Published));
+        assert!(entry(&held, today()).is_none());
+    }
+
+    #[test]
+    fn weeks_between_posts_are_filled_in() {
+        let content = AvailableContent {
+            content: vec![
+                Content::stub("first", "2024-05-06", true),
+                Content::stub("last", "2024-05-27", true),
+            ],
+            ..Default::default()
+        };
+        let calendar = Calendar::build(&content, today());
+        let gaps: Vec<bool> = calendar.weeks.iter().map(|week| week.gap).collect();
+        assert_eq!(gaps, [false, true, true, false]);
+    }
+
+    #[test]
+    fn filled_in_weeks_are_bounded() {
+        let content = AvailableContent {
+            content: vec![
+                Content::stub("misdated", "0001-01-01", true),
+                Content::stub("last", "2024-05-27", true),
+            ],
+            ..Default::default()
+        };
+        let calendar = Calendar::build(&content, today());
+        assert_eq!(calendar.weeks.len() as u64, MAX_FILLED_WEEKS + 2);
+        assert_eq!(calendar.weeks[0].start.year(), 1);
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    io::Read,
+    process::{Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// How often a running command is checked on for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Stops a build in flight, when `cancel` is called on any clone of it or once
+/// its timeout runs out. Builds look at it between stages and between the
+/// files they work on, and kill the commands of include rules they are
+/// waiting on, so a command that hangs can't hang the build with it.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl Cancel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that cancels the build on its own once `timeout` has passed
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            deadline: Some((Instant::now() + timeout, timeout)),
+        }
+    }
+
+    /// Stop the build at the next point it looks
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|(deadline, _)| Instant::now() >= deadline)
+    }
+
+    /// An error once the build should stop, saying why
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            bail!("Build cancelled");
+        }
+        match self.deadline {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                bail!("Build timed out after {timeout:?}")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Run `command` to completion like `Command::output`, killing it when the
+    /// build is cancelled meanwhile or when it runs for longer than `timeout`
+    pub(crate) fn output(
+        &self,
+        command: &mut Command,
+        timeout: Option<Duration>,
+    ) -> Result<Output> {
+        let started = Instant::now();
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Read along so a chatty command never blocks on a full pipe
+        let stdout = child.stdout.take().map(drain);
+        let stderr = child.stderr.take().map(drain);
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            let stop = match timeout {
+                Some(timeout) if started.elapsed() >= timeout => {
+                    Err(anyhow!("Command timed out after {timeout:?}"))
+                }
+                _ => self.check(),
+            };
+            if let Err(e) = stop {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(e);
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        let collect = |reader: Option<thread::JoinHandle<std::io::Result<Vec<u8>>>>| {
+            reader.map_or(Ok(Vec::new()), |reader| {
+                reader
+                    .join()
+                    .map_err(|_| anyhow!("Unable to read the output of a command"))?
+                    .context("Unable to read the output of a command")
+            })
+        };
+        Ok(Output {
+            status,
+            stdout: collect(stdout)?,
+            stderr: collect(stderr)?,
+        })
+    }
+}
+
+fn drain<R: Read + Send + 'static>(mut reader: R) -> thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read)?;
+        Ok(read)
+    })
+}
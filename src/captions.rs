@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::{assets, config::Config};
+
+/// A timed piece of a WebVTT or SRT file: a transcript line or a chapter
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct Cue {
+    /// Seconds from the start of the media
+    pub(crate) start: f64,
+    pub(crate) end: f64,
+    /// Cue text with markup like `<v Speaker>` removed
+    pub(crate) text: String,
+}
+
+/// Seconds of a `hh:mm:ss.mmm`, `mm:ss.mmm` or SRT `hh:mm:ss,mmm` timestamp
+fn seconds(timestamp: &str) -> Option<f64> {
+    timestamp
+        .trim()
+        .replace(',', ".")
+        .split(':')
+        .try_fold(0.0, |total, part| {
+            Some(total * 60.0 + part.parse::<f64>().ok()?)
+        })
+}
+
+/// Text of a cue without its tags
+pub(crate) fn strip_tags(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+}
+
+/// Cues of a WebVTT or SRT file. Both are blocks separated by blank lines with a
+/// `start --> end` timing line, optionally preceded by an identifier; blocks
+/// without timing (the header, `NOTE`s, styles) are skipped.
+pub(crate) fn parse(source: &str) -> Vec<Cue> {
+    source
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+            let (start, end) = lines.next()?.split_once("-->")?;
+            // WebVTT cue settings follow the end time
+            let end = end.split_whitespace().next()?;
+            let text = lines.map(strip_tags).collect::<Vec<_>>().join(" ");
+            Some(Cue {
+                start: seconds(start)?,
+                end: seconds(end)?,
+                text,
+            })
+        })
+        .collect()
+}
+
+/// Cues of a sidecar file referenced from the page at `page`
+pub(crate) fn load(reference: &str, page: &Path, config: &Config) -> Result<Vec<Cue>> {
+    let path = assets::resolve(page, reference)
+        .map(|path| config.input.join("include").join(path))
+        .with_context(|| anyhow!("Captions must be a local file [{reference}]"))?;
+    let source = std::fs::read_to_string(&path)
+        .with_context(|| anyhow!("Unable to read captions file [{path:?}]"))?;
+    Ok(parse(&source))
+}
+
+/// A sidecar of a media reference, `episode.mp3` having `episode.vtt` or
+/// `episode.srt` as transcript and `episode.chapters.vtt` as chapters, if it exists
+pub(crate) fn sidecar(
+    media: &str,
+    kind: Option<&str>,
+    page: &Path,
+    config: &Config,
+) -> Option<String> {
+    let (stem, _) = media.rsplit_once('.')?;
+    let stem = match kind {
+        Some(kind) => format!("{stem}.{kind}"),
+        None => stem.to_owned(),
+    };
+    ["vtt", "srt"]
+        .iter()
+        .map(|ext| format!("{stem}.{ext}"))
+        .find(|reference| {
+            assets::resolve(page, reference)
+                .is_some_and(|path| config.input.join("include").join(path).is_file())
+        })
+}
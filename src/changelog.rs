@@ -0,0 +1,187 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{config::Config, output::Output, types::AvailableContent};
+
+const CHANGELOG_FEED: &str = "changelog.json";
+
+fn default_limit() -> usize {
+    20
+}
+
+/// Settings for the list of recently changed posts
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChangelogConfig {
+    /// How many changes to keep
+    #[serde(default = "default_limit")]
+    pub(crate) limit: usize,
+    /// Also write the changes as a JSON Feed into `changelog.json`
+    #[serde(default)]
+    pub(crate) feed: bool,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Change {
+    Added,
+    Updated,
+}
+
+/// A post that changed, taken from the latest commit touching it
+#[derive(Debug, Serialize)]
+pub(crate) struct ChangelogEntry {
+    name: String,
+    title: String,
+    /// Root-relative URL of the post, including the deployment prefix
+    url: String,
+    change: Change,
+    date: DateTime<FixedOffset>,
+    commit: String,
+    message: String,
+}
+
+/// Latest commit touching each content file, newest first, as
+/// `(path relative to the input directory, change, date, commit, message)`
+fn history(input: &Path) -> Vec<(PathBuf, Change, DateTime<FixedOffset>, String, String)> {
+    let Some(log) = Command::new("git")
+        .arg("-C")
+        .arg(input)
+        .args([
+            "log",
+            "--relative",
+            "--diff-filter=AM",
+            "--name-status",
+            "--format=%x1e%H%x09%aI%x09%s",
+            "--",
+            "content",
+        ])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+    else {
+        return Vec::new();
+    };
+
+    let mut seen = HashSet::new();
+    let mut changes = Vec::new();
+    for commit in log.split('\x1e').filter(|c| !c.trim().is_empty()) {
+        let mut lines = commit.lines();
+        let Some(mut header) = lines.next().map(|h| h.splitn(3, '\t')) else {
+            continue;
+        };
+        let (Some(hash), Some(date), message) = (header.next(), header.next(), header.next())
+        else {
+            continue;
+        };
+        let Ok(date) = DateTime::parse_from_rfc3339(date) else {
+            continue;
+        };
+
+        for line in lines {
+            let Some((status, path)) = line.split_once('\t') else {
+                continue;
+            };
+            let path = PathBuf::from(path);
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            let change = if status == "A" {
+                Change::Added
+            } else {
+                Change::Updated
+            };
+            changes.push((
+                path,
+                change,
+                date,
+                hash.to_owned(),
+                message.unwrap_or_default().to_owned(),
+            ));
+        }
+    }
+    changes
+}
+
+/// When each content file last changed according to the git history of the
+/// site, by its path relative to the input directory
+pub(crate) fn last_changes(input: &Path) -> HashMap<PathBuf, DateTime<FixedOffset>> {
+    history(input)
+        .into_iter()
+        .map(|(path, _, date, _, _)| (path, date))
+        .collect()
+}
+
+/// Recently added or updated published posts, according to the git history of
+/// the site. Empty when the input is not a git checkout.
+pub(crate) fn collect(
+    content: &AvailableContent,
+    config: &Config,
+    limit: usize,
+) -> Vec<ChangelogEntry> {
+    history(&config.input)
+        .into_iter()
+        .filter_map(|(path, change, date, commit, message)| {
+            let content = content.content.iter().find(|content| {
+                content.publish
+                    && !content.draft
+                    && content
+                        .location
+                        .src
+                        .strip_prefix(&config.input)
+                        .is_ok_and(|src| src == path)
+            })?;
+            Some(ChangelogEntry {
+                name: content.post.metadata.name.clone(),
+                title: content.post.metadata.title.clone(),
+                url: content.location.href.clone(),
+                change,
+                date,
+                commit,
+                message,
+            })
+        })
+        .take(limit)
+        .collect()
+}
+
+/// Queue the changelog as a JSON Feed (https://jsonfeed.org/version/1.1)
+pub(crate) fn write_feed(
+    entries: &[ChangelogEntry],
+    config: &Config,
+    output: &Output,
+) -> Result<()> {
+    let items: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let url = config.absolute_url(&entry.url);
+            json!({
+                "id": format!("{url}#{}", entry.commit),
+                "url": url,
+                "title": entry.title,
+                "content_text": format!("{:?}: {}", entry.change, entry.message),
+                "date_modified": entry.date.to_rfc3339(),
+            })
+        })
+        .collect();
+    let feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": config.site.get("title").and_then(|t| t.as_str()).unwrap_or("Changelog"),
+        "feed_url": config.absolute_url(&config.site_url(CHANGELOG_FEED)),
+        "items": items,
+    });
+    output.add(
+        config.output.join(CHANGELOG_FEED),
+        serde_json::to_vec_pretty(&feed)?,
+    )
+}
@@ -0,0 +1,370 @@
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{TimeDelta, Utc};
+
+use crate::{
+    assets, changelog, collect_content,
+    config::Config,
+    tags,
+    types::{Content, PostDate, PostStats},
+    verify,
+};
+
+/// Heading levels at or beyond this are flagged as too deeply nested
+const DEEP_HEADING_LEVEL: usize = 5;
+
+/// Titles longer than this get truncated in search results
+const MAX_TITLE_LENGTH: usize = 60;
+
+/// Which optional audits to run on top of the default checks
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Audit search engine metadata
+    pub seo: bool,
+    /// List published posts not updated for this long
+    pub stale: Option<TimeDelta>,
+}
+
+/// Read an age such as `90d`, `6w`, `3m` or `2y`, months being 30 days and
+/// years 365
+pub fn parse_age(text: &str) -> Result<TimeDelta> {
+    let Some(unit) = text.chars().last() else {
+        bail!("Empty age, expected e.g. `2y`");
+    };
+    let count: i64 = text[..text.len() - unit.len_utf8()]
+        .parse()
+        .with_context(|| anyhow!("Unable to read the age [{text}], expected e.g. `2y`"))?;
+    let days = match unit {
+        'd' => 1,
+        'w' => 7,
+        'm' => 30,
+        'y' => 365,
+        _ => bail!("Unknown unit of the age [{text}], expected one of `d`, `w`, `m` or `y`"),
+    };
+    Ok(TimeDelta::days(count * days))
+}
+
+/// A single finding about a source file
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub(crate) source: PathBuf,
+    pub(crate) message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.source.display(), self.message)
+    }
+}
+
+/// Everything `mub check` found about the site
+#[derive(Debug, Default)]
+pub struct Report {
+    pub(crate) stats: Vec<(PathBuf, PostStats)>,
+    pub(crate) warnings: Vec<Diagnostic>,
+    /// Problems that should fail the check
+    pub(crate) errors: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:>8} {:>8} {:>8} {:>8} {:>9}  post",
+            "headings", "internal", "external", "images", "footnotes"
+        )?;
+        for (source, stats) in &self.stats {
+            writeln!(
+                f,
+                "{:>8} {:>8} {:>8} {:>8} {:>9}  {}",
+                stats.headings,
+                stats.internal_links,
+                stats.external_links,
+                stats.images,
+                stats.footnotes,
+                source.display()
+            )?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "warning: {warning}")?;
+        }
+        for error in &self.errors {
+            writeln!(f, "error: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Inspect the published content of a site without rendering it
+pub fn check(config: &Config, options: &Options) -> Result<Report> {
+    let content = collect_content(config)?;
+    let mut report = Report::default();
+
+    let mut published: Vec<_> = content.content.iter().filter(|c| c.publish).collect();
+    published.sort_by(|a, b| a.location.src.cmp(&b.location.src));
+
+    for content in &published {
+        let source = content.location.src.clone();
+        let stats = &content.post.stats;
+        if stats.internal_links == 0 {
+            report.warnings.push(Diagnostic {
+                source: source.clone(),
+                message: String::from("no links to other pages of the site"),
+            });
+        }
+        if stats.max_heading_level >= DEEP_HEADING_LEVEL {
+            report.warnings.push(Diagnostic {
+                source: source.clone(),
+                message: format!("headings nested down to level {}", stats.max_heading_level),
+            });
+        }
+        for image in assets::images_without_alt(&content.post.raw) {
+            report.warnings.push(Diagnostic {
+                source: source.clone(),
+                message: format!("image [{image}] has no alt text"),
+            });
+        }
+        for message in heading_outline(content) {
+            report.warnings.push(Diagnostic {
+                source: source.clone(),
+                message,
+            });
+        }
+        report.stats.push((source, stats.clone()));
+
+        if !config.verify.is_empty() {
+            report
+                .errors
+                .extend(verify::verify(content, &config.verify)?);
+        }
+    }
+
+    near_duplicate_tags(&published, &mut report);
+
+    if options.seo {
+        seo(config, &published, &mut report);
+    }
+
+    if let Some(age) = options.stale {
+        stale(config, &published, age, &mut report);
+    }
+
+    root_relative_links(config, &mut report)?;
+
+    Ok(report)
+}
+
+/// Problems with the heading hierarchy of a markdown post: more than one H1,
+/// skipped levels, and a first heading repeating the front matter title, as
+/// the template usually renders the title already
+fn heading_outline(content: &Content) -> Vec<String> {
+    use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+    if content.post.stats.headings == 0 {
+        return Vec::new();
+    }
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for event in Parser::new_ext(&content.post.raw, pulldown_cmark::Options::ENABLE_FOOTNOTES) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some((level as usize, String::new()))
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading)) = &mut current {
+                    heading.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => headings.extend(current.take()),
+            _ => {}
+        }
+    }
+
+    let mut messages = Vec::new();
+    if let Some((_, first)) = headings.first() {
+        if first
+            .trim()
+            .eq_ignore_ascii_case(content.post.metadata.title.trim())
+        {
+            messages.push(format!(
+                "first heading duplicates the title: [{}]",
+                first.trim()
+            ));
+        }
+    }
+    let h1s = headings.iter().filter(|(level, _)| *level == 1).count();
+    if h1s > 1 {
+        messages.push(format!("{h1s} level 1 headings"));
+    }
+    let mut previous = 0;
+    for (level, heading) in &headings {
+        if previous > 0 && *level > previous + 1 {
+            messages.push(format!(
+                "heading skips from level {previous} to {level}: [{}]",
+                heading.trim()
+            ));
+        }
+        previous = *level;
+    }
+    messages
+}
+
+/// Warn about tags that are likely another tag written differently, at the
+/// first post using the less common one
+fn near_duplicate_tags(published: &[&Content], report: &mut Report) {
+    for ((common, uses), (rare, rare_uses)) in tags::near_duplicates(published) {
+        let Some(content) = published
+            .iter()
+            .find(|content| tags::of(content).contains(&rare))
+        else {
+            continue;
+        };
+        report.warnings.push(Diagnostic {
+            source: content.location.src.clone(),
+            message: format!(
+                "tag [{rare}] of {rare_uses} post(s) looks like [{common}] of {uses}, merge them with `tags.synonyms`"
+            ),
+        });
+    }
+}
+
+/// Warn about links in templates that point at the root of the domain when the
+/// site is deployed under a path, as they skip the prefix `site_url()` adds
+fn root_relative_links(config: &Config, report: &mut Report) -> Result<()> {
+    let prefix = config.path_prefix();
+    if prefix.is_empty() {
+        return Ok(());
+    }
+    let mut templates = Vec::new();
+    for dir in config.layered_dirs("templates") {
+        let pattern = format!("{}/**/*", dir.display());
+        let mut found: Vec<_> = glob::glob(&pattern)
+            .with_context(|| anyhow!("Unable to glob templates directory: [{pattern}]"))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .collect();
+        found.sort();
+        templates.extend(found);
+    }
+
+    for template in templates {
+        let source = std::fs::read_to_string(&template)
+            .with_context(|| anyhow!("Unable to read template [{template:?}]"))?;
+        for (number, line) in source.lines().enumerate() {
+            for attribute in ["href=", "src=", "action="] {
+                for (start, _) in line.match_indices(attribute) {
+                    let value = line[start + attribute.len()..].trim_start_matches(['"', '\'']);
+                    if value.starts_with('/') && !value.starts_with("//") {
+                        let link: String = value
+                            .chars()
+                            .take_while(|c| !matches!(c, '"' | '\'' | ' ' | '>'))
+                            .collect();
+                        report.warnings.push(Diagnostic {
+                            source: template.clone(),
+                            message: format!(
+                                "line {}: root-relative link [{link}] misses the [{prefix}] prefix, use `site_url()`",
+                                number + 1
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Warn about published posts whose latest update, from the git history, the
+/// `updated` front matter or their date, is older than `age`
+fn stale(config: &Config, published: &[&Content], age: TimeDelta, report: &mut Report) {
+    let changes = changelog::last_changes(&config.input);
+    let cutoff = Utc::now().date_naive() - age;
+    for content in published.iter().filter(|content| !content.bare) {
+        let metadata = &content.post.metadata;
+        let committed = content
+            .location
+            .src
+            .strip_prefix(&config.input)
+            .ok()
+            .and_then(|path| changes.get(path))
+            .map(|date| (date.date_naive(), "git"));
+        let updated = metadata
+            .extra_str("updated")
+            .and_then(|updated| PostDate::parse(updated, &config.date_formats).ok())
+            .map(|date| (date.day(), "updated"));
+        let dated = Some((metadata.date.day(), "date"));
+
+        let Some((last, from)) = [committed, updated, dated].into_iter().flatten().max() else {
+            continue;
+        };
+        if last < cutoff {
+            report.warnings.push(Diagnostic {
+                source: content.location.src.clone(),
+                message: format!("stale, last updated on {last} ({from})"),
+            });
+        }
+    }
+}
+
+fn seo(config: &Config, published: &[&Content], report: &mut Report) {
+    if config.base_url.is_none() {
+        report.warnings.push(Diagnostic {
+            source: config.input.clone(),
+            message: String::from("no `base_url` configured, canonical links cannot be built"),
+        });
+    }
+
+    // Ordered so the report reads the same from run to run
+    let mut titles: BTreeMap<&str, Vec<&Content>> = BTreeMap::new();
+    for content in published {
+        let metadata = &content.post.metadata;
+        let source = content.location.src.clone();
+        titles.entry(&metadata.title).or_default().push(content);
+
+        if metadata.extra_str("description").is_none_or(str::is_empty) {
+            report.warnings.push(Diagnostic {
+                source: source.clone(),
+                message: String::from("missing `description` in front matter"),
+            });
+        }
+        if metadata.title.chars().count() > MAX_TITLE_LENGTH {
+            report.warnings.push(Diagnostic {
+                source: source.clone(),
+                message: format!(
+                    "title is longer than {MAX_TITLE_LENGTH} characters [{}]",
+                    metadata.title
+                ),
+            });
+        }
+        if metadata.extra_bool("sitemap") == Some(false) {
+            report.warnings.push(Diagnostic {
+                source,
+                message: String::from("published but excluded from the sitemap"),
+            });
+        }
+    }
+
+    for (title, mut contents) in titles.into_iter().filter(|(_, c)| c.len() > 1) {
+        contents.sort_by(|a, b| a.location.src.cmp(&b.location.src));
+        for content in &contents {
+            report.warnings.push(Diagnostic {
+                source: content.location.src.clone(),
+                message: format!(
+                    "title [{title}] is shared with {} other page(s)",
+                    contents.len() - 1
+                ),
+            });
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use pulldown_cmark_escape::{escape_href, escape_html};
+use serde::Deserialize;
+
+use crate::config::Config;
+
+/// A bibliography entry, reduced to what citations and reference lists show
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Reference {
+    id: String,
+    /// `(family, given)` names
+    authors: Vec<(String, String)>,
+    title: String,
+    year: Option<String>,
+    container: Option<String>,
+    url: Option<String>,
+}
+
+/// References by citation key
+#[derive(Debug, Default)]
+pub(crate) struct Bibliography {
+    references: HashMap<String, Reference>,
+}
+
+#[derive(Deserialize)]
+struct CslName {
+    #[serde(default)]
+    family: String,
+    #[serde(default)]
+    given: String,
+    #[serde(default)]
+    literal: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CslDate {
+    #[serde(rename = "date-parts", default)]
+    date_parts: Vec<Vec<serde_json::Value>>,
+}
+
+#[derive(Deserialize)]
+struct CslItem {
+    id: String,
+    #[serde(default)]
+    author: Vec<CslName>,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    issued: Option<CslDate>,
+    #[serde(rename = "container-title", default)]
+    container_title: Option<String>,
+    #[serde(rename = "URL", default)]
+    url: Option<String>,
+    #[serde(rename = "DOI", default)]
+    doi: Option<String>,
+}
+
+impl From<CslItem> for Reference {
+    fn from(item: CslItem) -> Self {
+        Self {
+            id: item.id,
+            authors: item
+                .author
+                .into_iter()
+                .map(|name| match name.literal {
+                    Some(literal) => (literal, String::new()),
+                    None => (name.family, name.given),
+                })
+                .collect(),
+            title: item.title,
+            year: item
+                .issued
+                .and_then(|date| date.date_parts.first()?.first().cloned())
+                .map(|year| year.to_string().trim_matches('"').to_owned()),
+            container: item.container_title,
+            url: item
+                .url
+                .or_else(|| item.doi.map(|doi| format!("https://doi.org/{doi}"))),
+        }
+    }
+}
+
+/// Minimal BibTeX reader: `@type{key, field = {value} | "value" | number, ...}`
+fn parse_bibtex(source: &str) -> Result<Vec<Reference>> {
+    let mut references = Vec::new();
+    for entry in source.split('@').skip(1) {
+        let Some((_, body)) = entry.split_once('{') else {
+            continue;
+        };
+        let Some((key, mut rest)) = body.split_once(',') else {
+            continue;
+        };
+        let mut fields = HashMap::new();
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+            let Some((name, value)) = rest.split_once('=') else {
+                break;
+            };
+            let value = value.trim_start();
+            let (field, remainder) = if let Some(braced) = value.strip_prefix('{') {
+                let mut depth = 1;
+                let end = braced
+                    .char_indices()
+                    .find(|(_, c)| {
+                        match c {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                        depth == 0
+                    })
+                    .map(|(i, _)| i)
+                    .with_context(|| anyhow!("Unbalanced braces in BibTeX entry [{key}]"))?;
+                (&braced[..end], &braced[end + 1..])
+            } else if let Some(quoted) = value.strip_prefix('"') {
+                let end = quoted
+                    .find('"')
+                    .with_context(|| anyhow!("Unterminated string in BibTeX entry [{key}]"))?;
+                (&quoted[..end], &quoted[end + 1..])
+            } else {
+                let end = value.find([',', '}']).unwrap_or(value.len());
+                (value[..end].trim(), &value[end..])
+            };
+            fields.insert(name.trim().to_lowercase(), field.replace(['{', '}'], ""));
+            rest = remainder;
+        }
+
+        let authors = fields
+            .get("author")
+            .map(|authors| {
+                authors
+                    .split(" and ")
+                    .map(|name| match name.split_once(',') {
+                        Some((family, given)) => {
+                            (family.trim().to_owned(), given.trim().to_owned())
+                        }
+                        None => match name.trim().rsplit_once(' ') {
+                            Some((given, family)) => (family.to_owned(), given.to_owned()),
+                            None => (name.trim().to_owned(), String::new()),
+                        },
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        references.push(Reference {
+            id: key.trim().to_owned(),
+            authors,
+            title: fields.get("title").cloned().unwrap_or_default(),
+            year: fields.get("year").cloned(),
+            container: fields
+                .get("journal")
+                .or_else(|| fields.get("booktitle"))
+                .cloned(),
+            url: fields.get("url").cloned().or_else(|| {
+                fields
+                    .get("doi")
+                    .map(|doi| format!("https://doi.org/{doi}"))
+            }),
+        });
+    }
+    Ok(references)
+}
+
+impl Bibliography {
+    /// Load the configured bibliography, empty when none is configured
+    pub(crate) fn load(config: &Config) -> Result<Self> {
+        let Some(path) = &config.bibliography else {
+            return Ok(Self::default());
+        };
+        let path = config.input.join(path);
+        let source = std::fs::read_to_string(&path)
+            .with_context(|| anyhow!("Unable to read bibliography [{}]", path.display()))?;
+        let references = match path.extension().and_then(|e| e.to_str()) {
+            Some("bib") => parse_bibtex(&source)?,
+            _ => serde_json::from_str::<Vec<CslItem>>(&source)
+                .with_context(|| {
+                    anyhow!("Bibliography is not valid CSL-JSON [{}]", path.display())
+                })?
+                .into_iter()
+                .map(Reference::from)
+                .collect(),
+        };
+        Ok(Self {
+            references: references
+                .into_iter()
+                .map(|reference| (reference.id.clone(), reference))
+                .collect(),
+        })
+    }
+}
+
+impl Reference {
+    /// `Family Year`, `A & B Year` or `A et al. Year`
+    fn label(&self) -> String {
+        let names = match self.authors.as_slice() {
+            [] => self.title.clone(),
+            [(one, _)] => one.clone(),
+            [(first, _), (second, _)] => format!("{first} & {second}"),
+            [(first, _), ..] => format!("{first} et al."),
+        };
+        match &self.year {
+            Some(year) => format!("{names} {year}"),
+            None => names,
+        }
+    }
+
+    fn to_html(&self) -> String {
+        let mut html = format!("<li id=\"ref-{}\">", self.id);
+        let authors = self
+            .authors
+            .iter()
+            .map(|(family, given)| match given.is_empty() {
+                true => family.clone(),
+                false => format!("{family}, {given}"),
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        let _ = escape_html(&mut html, &authors);
+        if let Some(year) = &self.year {
+            let _ = escape_html(&mut html, &format!(" ({year})"));
+        }
+        html.push_str(". <cite>");
+        let _ = escape_html(&mut html, &self.title);
+        html.push_str("</cite>.");
+        if let Some(container) = &self.container {
+            html.push(' ');
+            let _ = escape_html(&mut html, container);
+            html.push('.');
+        }
+        if let Some(url) = &self.url {
+            html.push_str(" <a href=\"");
+            let _ = escape_href(&mut html, url);
+            html.push_str("\">");
+            let _ = escape_html(&mut html, url);
+            html.push_str("</a>");
+        }
+        html.push_str("</li>");
+        html
+    }
+}
+
+/// Replace `[@key]` and `[@a; @b]` citations outside of fenced code with links to
+/// a references section appended to the content
+pub(crate) fn cite(content: &str, bibliography: &Bibliography, source: &Path) -> Result<String> {
+    if bibliography.references.is_empty() {
+        return Ok(content.to_owned());
+    }
+    let mut cited: Vec<&Reference> = Vec::new();
+    let mut out = String::with_capacity(content.len());
+    let mut in_fence = false;
+
+    for line in content.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence {
+            out.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find("[@") {
+            let Some(end) = rest[start..].find(']').map(|end| start + end) else {
+                break;
+            };
+            out.push_str(&rest[..start]);
+            let labels = rest[start + 1..end]
+                .split(';')
+                .map(|key| {
+                    let key = key.trim().trim_start_matches('@');
+                    let reference = bibliography.references.get(key).with_context(|| {
+                        anyhow!("Unknown citation key [{key}] in [{}]", source.display())
+                    })?;
+                    if !cited.iter().any(|c| c.id == reference.id) {
+                        cited.push(reference);
+                    }
+                    let mut label = String::new();
+                    let _ = escape_html(&mut label, &reference.label());
+                    Ok(format!("<a href=\"#ref-{}\">{label}</a>", reference.id))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            out.push_str(&format!(
+                "<span class=\"citation\">({})</span>",
+                labels.join("; ")
+            ));
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+    }
+
+    if !cited.is_empty() {
+        out.push_str("\n\n<section class=\"references\">\n<h2>References</h2>\n<ol>\n");
+        for reference in cited {
+            out.push_str(&reference.to_html());
+            out.push('\n');
+        }
+        out.push_str("</ol>\n</section>\n");
+    }
+    Ok(out)
+}
@@ -0,0 +1,113 @@
+use std::{cmp::Ordering, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use icu_collator::{Collator, CollatorOptions, Strength};
+use icu_provider::DataLocale;
+use minijinja::{context, Error, Value};
+
+/// Order of names and titles in the locale set by `collation`, so `Ähre` sorts
+/// next to `Ahorn` on a German site instead of after `Zebra`. Without a locale
+/// strings keep their plain code point order.
+#[derive(Clone, Default)]
+pub(crate) struct Collation(Option<Arc<Collators>>);
+
+struct Collators {
+    /// Full order, telling accents and case apart
+    order: Collator,
+    /// Base letters only, for grouping `Ä` with `A`
+    letters: Collator,
+}
+
+impl Collation {
+    pub(crate) fn new(locale: Option<&str>) -> Result<Self> {
+        let Some(locale) = locale else {
+            return Ok(Self::default());
+        };
+        let data: DataLocale = locale
+            .parse()
+            .map_err(|e| anyhow!("Invalid collation locale [{locale}]: {e}"))?;
+        let collator = |strength| {
+            let mut options = CollatorOptions::new();
+            options.strength = Some(strength);
+            Collator::try_new(&data, options)
+                .map_err(|e| anyhow!("Unable to collate in the locale [{locale}]: {e}"))
+        };
+        Ok(Self(Some(Arc::new(Collators {
+            order: collator(Strength::Tertiary)?,
+            letters: collator(Strength::Primary)?,
+        }))))
+    }
+
+    pub(crate) fn compare(&self, a: &str, b: &str) -> Ordering {
+        match &self.0 {
+            Some(collators) => collators.order.compare(a, b).then_with(|| a.cmp(b)),
+            None => a.cmp(b),
+        }
+    }
+
+    /// Whether both strings start with the same letter, its accents and case aside
+    fn same_initial(&self, a: &str, b: &str) -> bool {
+        let (a, b) = (initial(a), initial(b));
+        match &self.0 {
+            Some(collators) => collators.letters.compare(a, b) == Ordering::Equal,
+            None => a.to_uppercase() == b.to_uppercase(),
+        }
+    }
+
+    /// Items of a sequence in order of themselves or of the attribute at the
+    /// dotted `attribute` path, as the `collate` filter
+    pub(crate) fn sort(
+        &self,
+        values: &Value,
+        attribute: Option<&str>,
+        reverse: bool,
+    ) -> Result<Vec<Value>, Error> {
+        let mut keyed = values
+            .try_iter()?
+            .map(|value| Ok((key(&value, attribute)?, value)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        keyed.sort_by(|(a, _), (b, _)| self.compare(a, b));
+        if reverse {
+            keyed.reverse();
+        }
+        Ok(keyed.into_iter().map(|(_, value)| value).collect())
+    }
+
+    /// Sorted items in groups by their first letter, as `{grouper, list}` like
+    /// minijinja's `groupby`, for A to Z indexes as the `groupbyinitial` filter
+    pub(crate) fn group(
+        &self,
+        values: &Value,
+        attribute: Option<&str>,
+    ) -> Result<Vec<Value>, Error> {
+        let mut groups: Vec<(String, Vec<Value>)> = Vec::new();
+        for value in self.sort(values, attribute, false)? {
+            let key = key(&value, attribute)?;
+            match groups.last_mut() {
+                Some((first, list)) if self.same_initial(first, &key) => list.push(value),
+                _ => groups.push((key, vec![value])),
+            }
+        }
+        Ok(groups
+            .into_iter()
+            .map(|(first, list)| context!(grouper => initial(&first).to_uppercase(), list => list))
+            .collect())
+    }
+}
+
+/// The first character of a string, empty for an empty one
+fn initial(text: &str) -> &str {
+    text.chars().next().map_or("", |c| &text[..c.len_utf8()])
+}
+
+/// The string an item is sorted by, empty when it has nothing there
+fn key(value: &Value, attribute: Option<&str>) -> Result<String, Error> {
+    let mut value = value.clone();
+    for part in attribute.into_iter().flat_map(|path| path.split('.')) {
+        value = value.get_attr(part)?;
+    }
+    match value.is_undefined() || value.is_none() {
+        true => Ok(String::new()),
+        false => Ok(value.to_string()),
+    }
+}
@@ -1,22 +1,250 @@
 use std::{collections::HashMap, fmt::Display, fs::File, io::BufReader, path::{Path, PathBuf}};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::{Error, asciinema::AsciinemaConfig, build_hooks::{BuildHooks, Hooks}, cache::CacheConfig, cancel::Cancel, changelog::ChangelogConfig, daemon::DaemonConfig, data::RenderEntry, data_uri::DataUriConfig, feed::FeedConfig, hooks::HooksConfig, decrypt::Decrypt, icons::IconsConfig, manifest::SigningConfig, media::MediaConfig, output::Fsync, plugins::PluginConfig, process::AnchorConfig, podcast::PodcastConfig, scripts::ScriptConfig, search::SearchConfig, tags::TagsConfig, sections::SectionConfig, templates::{TemplateConfig, TemplatePackage}, transform::IncludeRule, types::{LinkStyle, RepositoryConfig}, typography::TypographyConfig, verify::VerifyCommand};
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Another config file this one builds on, relative to this file.
+    /// Consumed while layering, kept here so the schema documents it.
+    #[allow(dead_code)]
+    #[serde(default, skip_serializing)]
+    pub(crate) extends: Option<PathBuf>,
     /// Input directory
     pub(crate) input: PathBuf,
     /// Output directory
     pub(crate) output: PathBuf,
-    /// Which templates to render 
-    pub(crate) render: Vec<String>,
-    /// Generate search index:
+    /// Paths in the output directory that builds leave alone, as glob patterns
+    /// relative to it. Anything else no build wrote is removed.
+    #[serde(default = "default_preserve")]
+    pub(crate) preserve: Vec<String>,
+    /// Which templates to render, each optionally paired with a data file
+    #[serde(default = "default_render")]
+    pub(crate) render: Vec<RenderEntry>,
+    /// Generate `search-index.json` of the published posts. Posts with
+    /// `search: false` front matter are left out
+    #[serde(default)]
     pub(crate) search: bool,
+    /// Fields, text length and formatting of the search index
+    #[serde(default)]
+    pub(crate) search_index: SearchConfig,
+    /// Theme directory, relative to the input directory. Its `templates/` and
+    /// `include/` are used for whatever the site's own don't have
+    #[serde(default)]
+    pub(crate) theme: Option<PathBuf>,
+    /// Whitespace handling and delimiters of the template engine
+    #[serde(default)]
+    pub(crate) templates: TemplateConfig,
+    /// Templates shared between sites, each loaded under its own prefix
+    #[serde(default)]
+    pub(crate) template_packages: Vec<TemplatePackage>,
+    /// Whether page links end in `.html`, `/` or `/index.html`
+    #[serde(default)]
+    pub(crate) link_style: LinkStyle,
+    /// Where posts are written, e.g. `:year/:month/:slug/`, from `:year`,
+    /// `:month`, `:day`, `:section` and `:slug`, the `slug` front matter or the
+    /// file name. A trailing `/` makes the post the index of its own directory.
+    /// `:section/:slug` by default, with `posts` as the section of top level posts
+    #[serde(default)]
+    pub(crate) permalink: Option<String>,
+    /// Write pages as `name/index.html` and link them as `name/`, same as the
+    /// `directory` link style, whatever `link_style` says
+    #[serde(default)]
+    pub(crate) pretty_urls: bool,
+    /// Public URL the site is served from, e.g. `https://example.com/`. A path in
+    /// it, as in `https://example.com/blog/`, prefixes every generated URL
+    #[serde(default)]
+    pub(crate) base_url: Option<String>,
+    /// Repository of the site sources, gives pages an `edit_url`
+    #[serde(default)]
+    pub(crate) repository: Option<RepositoryConfig>,
+    /// Generate `sitemap.xml` of the published pages, which needs `base_url`.
+    /// Posts with `sitemap: false` front matter are left out
+    #[serde(default)]
+    pub(crate) sitemap: bool,
+    /// Generate `calendar.html` and `calendar.json` planning pages
+    #[serde(default)]
+    pub(crate) calendar: bool,
+    /// Expose recently changed posts from git history as `changelog`
+    #[serde(default)]
+    pub(crate) changelog: Option<ChangelogConfig>,
+    /// Combine a directory of svg icons into a sprite used by `icon()`
+    #[serde(default)]
+    pub(crate) icons: Option<IconsConfig>,
+    /// Smart punctuation and widow prevention for markdown content
+    #[serde(default)]
+    pub(crate) typography: TypographyConfig,
+    /// Site-wide abbreviations wrapped in `<abbr>` tags, e.g. `{"HTML": "HyperText Markup Language"}`
+    #[serde(default)]
+    pub(crate) abbreviations: HashMap<String, String>,
+    /// Also render footnotes as margin notes next to where they are referenced
+    #[serde(default)]
+    pub(crate) sidenotes: bool,
+    /// Fail the build when an image of a published post has no alt text. Images
+    /// with `-` as alt text or a `decorative` title are left out and get an empty alt
+    #[serde(default)]
+    pub(crate) require_alt_text: bool,
+    /// Write `mailto:` and `tel:` links of posts as HTML character references,
+    /// hiding them from harvesters that scan the output for plain addresses
+    #[serde(default)]
+    pub(crate) obfuscate_contacts: bool,
+    /// Append a permalink to every heading of a post
+    #[serde(default)]
+    pub(crate) heading_anchors: Option<AnchorConfig>,
+    /// CSL-JSON (`.json`) or BibTeX (`.bib`) file for `[@key]` citations, relative to the input directory
+    #[serde(default)]
+    pub(crate) bibliography: Option<PathBuf>,
+    /// Atom and RSS feeds of the latest posts
+    #[serde(default)]
+    pub(crate) feed: Option<FeedConfig>,
+    /// Podcast RSS feed of the posts with an `audio` file
+    #[serde(default)]
+    pub(crate) podcast: Option<PodcastConfig>,
+    /// Output directories, e.g. `files`, that get an `index.html` listing their
+    /// files, like a web server's autoindex
+    #[serde(default)]
+    pub(crate) listings: Vec<PathBuf>,
+    /// Rules applied to include files as they are copied, the first matching one wins
+    #[serde(default)]
+    pub(crate) include_rules: Vec<IncludeRule>,
+    /// Serve media from the include directory under content hash paths, see `asset_url()`
+    #[serde(default)]
+    pub(crate) media: Option<MediaConfig>,
+    /// Player for `asciinema` blocks, without it casts only show their static output
+    #[serde(default)]
+    pub(crate) asciinema: Option<AsciinemaConfig>,
+    /// Commands `mub check` runs code blocks flagged `verify` through, by language
+    #[serde(default)]
+    pub(crate) verify: HashMap<String, VerifyCommand>,
+    /// Leave posts dated after the day of the build unpublished until a later
+    /// build, see `mub daemon`
+    #[serde(default)]
+    pub(crate) hold_future_posts: bool,
+    /// Build unpublished and held back posts too, flagged `draft` for templates
+    /// to badge, so they can be reviewed. Drafts stay out of feeds.
+    #[serde(default)]
+    pub(crate) drafts: bool,
+    /// Rebuild schedule of `mub daemon`
+    #[serde(default)]
+    pub(crate) daemon: Option<DaemonConfig>,
+    /// Rebuild hook of `mub serve` and `mub daemon`
+    #[serde(default)]
+    pub(crate) hooks: Option<HooksConfig>,
+    /// Sign the build manifest with an ed25519 key, writing `.mub-manifest.sig`
+    #[serde(default)]
+    pub(crate) signing: Option<SigningConfig>,
+    /// Title and templates of content subdirectories, by their path below
+    /// `content/`. Configured sections get an index page.
+    #[serde(default)]
+    pub(crate) sections: HashMap<String, SectionConfig>,
+    /// Where work is kept between builds, and how much of it
+    #[serde(default)]
+    pub(crate) cache: CacheConfig,
+    /// Experimental WebAssembly plugins transforming posts and adding template functions
+    #[serde(default)]
+    pub(crate) plugins: Vec<PluginConfig>,
+    /// `strftime` style formats post dates are read in, besides RFC 3339. Formats
+    /// with a time of day give posts one.
+    #[serde(default = "default_date_formats")]
+    pub(crate) date_formats: Vec<String>,
+    /// Locale names and titles are sorted in, as `de` or `sv`, by the order of
+    /// posts and sections and by the `collate` and `groupbyinitial` filters.
+    /// Plain code point order when not set.
+    #[serde(default)]
+    pub(crate) collation: Option<String>,
+    /// Words of its text a post's `summary_html` falls back to without a
+    /// `<!-- more -->` marker. Only marked posts have a summary when not set.
+    #[serde(default)]
+    pub(crate) summary_words: Option<usize>,
+    /// Case folding and synonyms the `tags` front matter of posts is cleaned
+    /// up with, see `mub check` for tags likely meaning the same
+    #[serde(default)]
+    pub(crate) tags: TagsConfig,
+    /// Rhai scripts adding template filters and changing post front matter
+    #[serde(default)]
+    pub(crate) scripts: Vec<ScriptConfig>,
+    /// Extract big inline `data:` URIs of rendered posts into files
+    #[serde(default)]
+    pub(crate) data_uris: Option<DataUriConfig>,
+    /// Other builds of the same content into their own output, by name, e.g. a
+    /// mirror under another domain
+    #[serde(default)]
+    pub(crate) variants: HashMap<String, VariantConfig>,
+    /// Name of the build profile, exposed to templates as `build.profile`
+    #[serde(default)]
+    pub(crate) profile: Option<String>,
+    /// Only render the posts that changed since the last build into the same
+    /// output. Unchanged pages keep the build information they were rendered
+    /// with.
+    #[serde(default)]
+    pub(crate) incremental: bool,
+    /// Append a comment with the build information to every html page
+    #[serde(default)]
+    pub(crate) stamp: bool,
+    /// When to flush written files to disk
+    #[serde(default)]
+    pub(crate) fsync: Fsync,
+    /// Keys for encrypted content files
+    #[serde(default)]
+    pub(crate) decrypt: Decrypt,
     /// Site global metadata
+    #[serde(default)]
+    pub(crate) site: HashMap<String, serde_json::Value>,
+    /// Directory of the input only whose posts are converted, the others get
+    /// stub pages. Set with `with_overrides` rather than in the file
+    #[serde(skip)]
+    pub(crate) root: Option<PathBuf>,
+    /// Stops builds of this config, set with `with_cancel` rather than in the file
+    #[serde(skip)]
+    pub(crate) cancel: Cancel,
+    /// Code run during builds of this config, set with `with_hooks`
+    #[serde(skip)]
+    pub(crate) build_hooks: Hooks,
+}
+
+/// A build of the site into another output with its own site settings
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VariantConfig {
+    /// Output directory, relative to the one holding the main output, `<output>-<name>` by default
+    #[serde(default)]
+    pub(crate) output: Option<PathBuf>,
+    /// Public URL of the variant, the main `base_url` by default
+    #[serde(default)]
+    pub(crate) base_url: Option<String>,
+    /// Site metadata merged over the main `site`
+    #[serde(default)]
     pub(crate) site: HashMap<String, serde_json::Value>,
 }
 
+/// Settings given on the command line, taking precedence over the config file
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    /// Generate into this directory instead of the configured one
+    pub output: Option<PathBuf>,
+    /// Build drafts, whatever the config says
+    pub drafts: bool,
+    /// Only convert the posts under this directory of the input, as
+    /// `content/notes`, giving the others stub pages for links to land on
+    pub root: Option<PathBuf>,
+}
+
+fn default_date_formats() -> Vec<String> {
+    ["%Y-%m-%d", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"].map(String::from).to_vec()
+}
+
+fn default_preserve() -> Vec<String> {
+    [".git", "CNAME"].map(String::from).to_vec()
+}
+
+fn default_render() -> Vec<RenderEntry> {
+    vec![RenderEntry::Template(String::from("index.html"))]
+}
+
 impl Display for Config {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", serde_json::to_string_pretty(self).expect("Failed to Serialize a serializable config into a json object"))
@@ -24,8 +252,417 @@ impl Display for Config {
 }
 
 impl Config {
-    pub fn try_load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = File::open(path).context("Unable open the config file")?;
-        serde_json::from_reader(BufReader::new(file)).context("Unable to deserialize config")
+    /// Directory the site is generated into
+    pub fn output(&self) -> &Path {
+        &self.output
+    }
+
+    /// The config with command line overrides applied
+    pub fn with_overrides(mut self, overrides: &Overrides) -> Self {
+        if let Some(output) = &overrides.output {
+            self.output = output.clone();
+        }
+        self.drafts |= overrides.drafts;
+        if let Some(root) = &overrides.root {
+            self.root = Some(self.input.join(root));
+        }
+        self
+    }
+
+    /// The config with its builds stopped by `cancel`
+    pub fn with_cancel(mut self, cancel: Cancel) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// The config with `hooks` run during its builds
+    pub fn with_hooks(mut self, hooks: impl BuildHooks + 'static) -> Self {
+        self.build_hooks = Hooks::new(hooks);
+        self
+    }
+
+    /// Directories holding `name`, `templates` or `include`, the site's before the theme's
+    pub(crate) fn layered_dirs(&self, name: &str) -> Vec<PathBuf> {
+        let mut dirs = vec![self.input.join(name)];
+        if let Some(theme) = &self.theme {
+            dirs.push(self.input.join(theme).join(name));
+        }
+        dirs
+    }
+
+    /// The config building the variant `name` of the site
+    pub(crate) fn variant(&self, name: &str, variant: &VariantConfig) -> Result<Config> {
+        let output = variant.output.clone().unwrap_or_else(|| {
+            let mut dir = self.output.file_name().unwrap_or_default().to_os_string();
+            dir.push(format!("-{name}"));
+            PathBuf::from(dir)
+        });
+        let output = self.output.parent().unwrap_or(Path::new("")).join(output);
+
+        let mut value = serde_json::to_value(self)?;
+        merge(&mut value, serde_json::json!({ "output": output, "site": variant.site }));
+        if let Some(base_url) = &variant.base_url {
+            value["base_url"] = serde_json::Value::from(base_url.as_str());
+        }
+        value["variants"] = serde_json::json!({});
+        let mut config: Config = serde_json::from_value(value).with_context(|| anyhow!("Unable to configure the site variant [{name}]"))?;
+        // Runtime settings aren't serialized, the variant builds with the same ones
+        config.root = self.root.clone();
+        config.build_hooks = self.build_hooks.clone();
+        Ok(config.with_cancel(self.cancel.clone()))
+    }
+
+    /// How page links and output files are shaped, `pretty_urls` included
+    pub(crate) fn link_style(&self) -> LinkStyle {
+        match self.pretty_urls {
+            true => LinkStyle::Directory,
+            false => self.link_style,
+        }
+    }
+
+    /// Path the site is deployed under, taken from `base_url`, without a trailing
+    /// slash so it is empty for sites served from the root
+    pub(crate) fn path_prefix(&self) -> &str {
+        let base = self.base_url.as_deref().unwrap_or("");
+        let path = match base.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+            None => base,
+        };
+        path.trim_end_matches('/')
+    }
+
+    /// Root-relative URL of a path in the output, with the deployment prefix
+    pub(crate) fn site_url(&self, path: &str) -> String {
+        format!("{}/{}", self.path_prefix(), path.trim_start_matches('/'))
+    }
+
+    /// Absolute URL of a root-relative one, using the origin of `base_url`
+    pub(crate) fn absolute_url(&self, href: &str) -> String {
+        let base = self.base_url.as_deref().unwrap_or("");
+        let origin = match base.split_once("://") {
+            Some((scheme, rest)) => format!("{scheme}://{}", rest.split('/').next().unwrap_or(rest)),
+            None => String::new(),
+        };
+        format!("{origin}{href}")
+    }
+
+    pub fn try_load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let value = load(path).map_err(Error::Config)?;
+        serde_json::from_value(value).with_context(|| anyhow!("Unable to deserialize config [{path:?}]")).map_err(Error::Config)
+    }
+
+    /// JSON Schema describing the config file format
+    pub fn schema() -> serde_json::Value {
+        schemars::schema_for!(Config).to_value()
+    }
+
+    /// Validate a config file against the schema, returning every problem found
+    /// at the file of the `extends` chain and the line it comes from. An empty
+    /// list means the config is valid.
+    pub fn check<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+        let path = path.as_ref();
+        let value = load(path)?;
+        let layers = layer_files(path)?;
+
+        let schema = Self::schema();
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| anyhow!("Unable to build the config schema validator: {e}"))?;
+
+        Ok(validator
+            .iter_errors(&value)
+            .map(|error| {
+                let location = error.instance_path().to_string();
+                let location = if location.is_empty() { "/" } else { &location };
+                let mut keys: Vec<String> = error
+                    .instance_path()
+                    .iter()
+                    .map(|segment| match segment {
+                        jsonschema::paths::LocationSegment::Property(key) => key.into_owned(),
+                        jsonschema::paths::LocationSegment::Index(index) => index.to_string(),
+                    })
+                    .collect();
+                // Point at the key that isn't allowed rather than the table holding it
+                if let jsonschema::error::ValidationErrorKind::AdditionalProperties { unexpected } = error.kind() {
+                    keys.extend(unexpected.first().cloned());
+                }
+                match source_of(&layers, &keys) {
+                    (file, Some(line)) => format!("{}:{line}: [{location}] {error}", file.display()),
+                    (file, None) => format!("{}: [{location}] {error}", file.display()),
+                }
+            })
+            .collect())
+    }
+}
+
+/// Read a single config file, picking the format from its extension
+fn load_file(path: &Path) -> Result<serde_json::Value> {
+    let file = File::open(path).with_context(|| anyhow!("Unable open the config file [{path:?}]"))?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => {
+            let raw = std::io::read_to_string(file).with_context(|| anyhow!("Unable to read config file [{path:?}]"))?;
+            toml::from_str(&raw).with_context(|| anyhow!("Config file is not valid TOML [{path:?}]"))
+        }
+        _ => serde_json::from_reader(BufReader::new(file)).with_context(|| anyhow!("Config file is not valid JSON [{path:?}]")),
+    }
+}
+
+/// Keys holding directories that are resolved relative to the config file
+const PATH_KEYS: [&str; 2] = ["input", "output"];
+
+/// Maximum depth of `${...}` references pointing at other references
+const MAX_INTERPOLATION_DEPTH: usize = 16;
+
+/// Load a config file with all its layers merged and references interpolated
+fn load(path: &Path) -> Result<serde_json::Value> {
+    let mut value = load_layered(path, &mut Vec::new())?;
+    interpolate(&mut value).with_context(|| anyhow!("Unable to interpolate config values [{path:?}]"))?;
+    Ok(value)
+}
+
+/// Replace `${dotted.key}` references in every string value with the value found
+/// at that key, itself with its references replaced first
+fn interpolate(root: &mut serde_json::Value) -> Result<()> {
+    let snapshot = root.clone();
+    let mut interpolation = Interpolation { root: &snapshot, resolved: HashMap::new(), resolving: Vec::new() };
+    interpolation.replace_all(root)
+}
+
+/// References of the config being replaced, with the values already worked out
+struct Interpolation<'a> {
+    root: &'a serde_json::Value,
+    resolved: HashMap<String, String>,
+    /// Keys whose references are being worked out, the outermost first
+    resolving: Vec<String>,
+}
+
+impl Interpolation<'_> {
+    fn replace_all(&mut self, value: &mut serde_json::Value) -> Result<()> {
+        match value {
+            serde_json::Value::String(text) if text.contains("${") => {
+                *text = self.expand(text)?;
+                Ok(())
+            }
+            serde_json::Value::Array(values) => values.iter_mut().try_for_each(|v| self.replace_all(v)),
+            serde_json::Value::Object(values) => values.values_mut().try_for_each(|v| self.replace_all(v)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The text with each of its references replaced, in a single pass that
+    /// never looks at the replacements again
+    fn expand(&mut self, text: &str) -> Result<String> {
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            let length = rest[start..].find('}').with_context(|| anyhow!("Unterminated reference in config value [{text}]"))?;
+            expanded.push_str(&rest[..start]);
+            expanded.push_str(&self.lookup(&rest[start + 2..start + length])?);
+            rest = &rest[start + length + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    /// The value at a dotted key, with its own references replaced
+    fn lookup(&mut self, key: &str) -> Result<String> {
+        if let Some(value) = self.resolved.get(key) {
+            return Ok(value.clone());
+        }
+        if let Some(first) = self.resolving.iter().position(|resolving| resolving == key) {
+            let cycle = self.resolving[first..].join(" -> ");
+            return Err(anyhow!("Config references form a cycle [{cycle} -> {key}]"));
+        }
+        if self.resolving.len() >= MAX_INTERPOLATION_DEPTH {
+            return Err(anyhow!("Config references are nested more than {MAX_INTERPOLATION_DEPTH} deep at [{key}]"));
+        }
+        let pointer = format!("/{}", key.replace('.', "/"));
+        let value = match self.root.pointer(&pointer) {
+            Some(serde_json::Value::String(text)) => {
+                self.resolving.push(key.to_owned());
+                let value = self.expand(text);
+                self.resolving.pop();
+                value?
+            }
+            Some(v @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_))) => v.to_string(),
+            Some(_) => return Err(anyhow!("Config reference [{key}] must point at a string, number or boolean")),
+            None => return Err(anyhow!("Config reference [{key}] does not exist")),
+        };
+        self.resolved.insert(key.to_owned(), value.clone());
+        Ok(value)
+    }
+}
+
+/// A config file and every file it `extends`, each with its text and values,
+/// the file itself first
+fn layer_files(path: &Path) -> Result<Vec<(PathBuf, String, serde_json::Value)>> {
+    let mut layers = Vec::new();
+    let mut next = Some(path.to_path_buf());
+    while let Some(path) = next.take() {
+        let text = std::fs::read_to_string(&path).with_context(|| anyhow!("Unable to read config file [{path:?}]"))?;
+        let value = load_file(&path)?;
+        if let Some(parent) = value.get("extends").and_then(|v| v.as_str()) {
+            next = Some(path.parent().unwrap_or(Path::new("")).join(parent));
+        }
+        layers.push((path, text, value));
+    }
+    Ok(layers)
+}
+
+/// The file of the `extends` chain a value at `keys` comes from, the latest one
+/// setting it or the closest key above it, with the line the value is on when
+/// it can be found in the text
+fn source_of<'a>(layers: &'a [(PathBuf, String, serde_json::Value)], keys: &[String]) -> (&'a Path, Option<usize>) {
+    for depth in (0..=keys.len()).rev() {
+        let pointer: String = keys[..depth].iter().map(|key| format!("/{}", key.replace('~', "~0").replace('/', "~1"))).collect();
+        if let Some((file, text, _)) = layers.iter().find(|(_, _, value)| value.pointer(&pointer).is_some()) {
+            return (file, line_of(text, &keys[..depth]));
+        }
+    }
+    (&layers[0].0, None)
+}
+
+/// Line of the last of `keys` in a config file's text, found by looking for each
+/// key in turn after the one before it. Array indices are skipped.
+fn line_of(text: &str, keys: &[String]) -> Option<usize> {
+    let is_key_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut position = 0;
+    for key in keys.iter().filter(|key| key.parse::<usize>().is_err()) {
+        let found = text[position..].match_indices(key.as_str()).find(|(start, _)| {
+            let start = position + start;
+            let before = text[..start].chars().next_back();
+            let after = text[start + key.len()..].chars().next();
+            !before.is_some_and(is_key_char) && !after.is_some_and(is_key_char)
+        })?;
+        position += found.0;
+    }
+    (position > 0).then(|| text[..position].matches('\n').count() + 1)
+}
+
+/// Load a config file and everything it `extends`, merging the layers so the
+/// file furthest down the chain wins.
+fn load_layered(path: &Path, seen: &mut Vec<PathBuf>) -> Result<serde_json::Value> {
+    let canonical = path.canonicalize().with_context(|| anyhow!("Unable to find config file [{path:?}]"))?;
+    if seen.contains(&canonical) {
+        return Err(anyhow!("Config files extend each other in a cycle [{path:?}]"));
+    }
+    seen.push(canonical);
+
+    let mut value = load_file(path)?;
+    let object = value
+        .as_object_mut()
+        .with_context(|| anyhow!("Config file must contain a table/object at the top level [{path:?}]"))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    // Relative paths are relative to the config file they appear in, not the working directory.
+    // Paths starting with a reference, as `${input}/../dist`, are where the reference puts them
+    for key in PATH_KEYS {
+        if let Some(serde_json::Value::String(dir)) = object.get(key).filter(|v| v.as_str().is_some_and(|v| !v.starts_with("${"))) {
+            let resolved = base.join(dir).to_string_lossy().to_string();
+            object.insert(key.to_owned(), serde_json::Value::String(resolved));
+        }
+    }
+
+    match object.remove("extends") {
+        Some(serde_json::Value::String(parent)) => {
+            let mut merged = load_layered(&base.join(parent), seen)?;
+            merge(&mut merged, value);
+            Ok(merged)
+        }
+        Some(other) => Err(anyhow!("`extends` must be a path to another config file, found [{other}] in [{path:?}]")),
+        None => Ok(value),
+    }
+}
+
+/// Deep merge `overlay` into `base`. Objects are merged key by key, anything
+/// else is replaced wholesale.
+fn merge(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn interpolated(mut value: serde_json::Value) -> Result<serde_json::Value> {
+        interpolate(&mut value)?;
+        Ok(value)
+    }
+
+    #[test]
+    fn interpolates_references_to_references() {
+        let value = interpolated(json!({
+            "site": { "domain": "example.com", "port": 8080 },
+            "host": "${site.domain}:${site.port}",
+            "base_url": "https://${host}/",
+        }))
+        .unwrap();
+        assert_eq!(value["host"], "example.com:8080");
+        assert_eq!(value["base_url"], "https://example.com:8080/");
+    }
+
+    #[test]
+    fn variants_keep_the_runtime_settings() {
+        let mut config: Config = serde_json::from_value(json!({ "input": "src", "output": "dist" })).unwrap();
+        config.root = Some(PathBuf::from("src/notes"));
+        let config = config.with_cancel(Cancel::new());
+        let variant = VariantConfig { output: None, base_url: None, site: HashMap::new() };
+        let variant = config.variant("print", &variant).unwrap();
+        assert_eq!(variant.output, Path::new("dist-print"));
+        assert_eq!(variant.root, config.root);
+        config.cancel.cancel();
+        assert!(variant.cancel.is_cancelled());
+    }
+
+    #[test]
+    fn rejects_cycles_instead_of_looping() {
+        let error = interpolated(json!({ "a": "${b}", "b": "${a}" })).unwrap_err();
+        assert!(error.to_string().contains("cycle"), "{error}");
+
+        let error = interpolated(json!({ "a": "x${a}" })).unwrap_err();
+        assert!(error.to_string().contains("[a -> a]"), "{error}");
+    }
+
+    #[test]
+    fn rejects_missing_and_unterminated_references() {
+        assert!(interpolated(json!({ "a": "${nope}" })).is_err());
+        assert!(interpolated(json!({ "a": "${b", "b": "x" })).is_err());
+        assert!(interpolated(json!({ "a": "${b}", "b": ["x"] })).is_err());
+    }
+
+    #[test]
+    fn joins_paths_with_the_config_directory_once() {
+        let dir = std::env::temp_dir().join(format!("mub-config-{}", std::process::id()));
+        let site = dir.join("sites/a");
+        std::fs::create_dir_all(&site).unwrap();
+        let path = site.join("d.json");
+        std::fs::write(&path, r#"{"input": "src", "output": "${input}/../dist", "name": "x"}"#).unwrap();
+
+        let value = load(&path).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(value["input"], site.join("src").to_string_lossy().as_ref());
+        assert_eq!(value["output"], format!("{}/../dist", site.join("src").display()));
+    }
+
+    #[test]
+    fn finds_the_line_of_a_key() {
+        let text = "{\n  \"feed\": {\n    \"title\": \"x\",\n    \"limit\": \"ten\"\n  }\n}\n";
+        let keys = [String::from("feed"), String::from("limit")];
+        assert_eq!(line_of(text, &keys), Some(4));
+        assert_eq!(line_of(text, &[String::from("missing")]), None);
     }
 }
@@ -0,0 +1,151 @@
+use std::{
+    path::Path,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Response, Server};
+
+use crate::{
+    cancel::Cancel,
+    config::Overrides,
+    hooks::{self, HooksConfig},
+    serve,
+};
+
+/// How long the daemon waits when nothing is scheduled, before it reloads the
+/// config to look for a schedule again
+const IDLE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// When `mub daemon` rebuilds the site
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Rebuild every this many minutes
+    #[serde(default)]
+    pub(crate) every: Option<u64>,
+    /// Rebuild daily at these `HH:MM` times, UTC
+    #[serde(default)]
+    pub(crate) at: Vec<String>,
+}
+
+impl DaemonConfig {
+    /// The daily rebuild times
+    fn times(&self) -> Result<Vec<NaiveTime>> {
+        self.at
+            .iter()
+            .map(|time| {
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .with_context(|| anyhow!("Invalid daemon time, expected `HH:MM`: [{time}]"))
+            })
+            .collect()
+    }
+
+    /// First rebuild due after `now`, for a site last built at `built`
+    fn next(&self, built: DateTime<Utc>, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>> {
+        let every = self
+            .every
+            .map(|minutes| built + TimeDelta::minutes(minutes.max(1) as i64));
+        let daily = self.times()?.into_iter().map(|time| {
+            let today = now.date_naive().and_time(time).and_utc();
+            match today > now {
+                true => today,
+                false => today + TimeDelta::days(1),
+            }
+        });
+        Ok(every.into_iter().chain(daily).min())
+    }
+}
+
+/// Take rebuild hook calls on `listen` for as long as the daemon runs
+fn listen(hooks: &HooksConfig, rebuild: Sender<()>) -> Result<()> {
+    let secret: Arc<str> = hooks.secret()?.into();
+    let server = Server::http(&hooks.listen)
+        .map_err(|e| anyhow!("Unable to listen on [{}]: {e}", hooks.listen))?;
+    tracing::info!("Taking hooks on http://{}/", hooks.listen);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match hooks::is_hook(&request) {
+                true => hooks::handle(request, &secret, &rebuild),
+                false => request
+                    .respond(Response::from_string("Not found").with_status_code(404))
+                    .map_err(Into::into),
+            };
+            if let Err(e) = response {
+                tracing::warn!("Unable to respond: {e:#}");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Build the site, then keep rebuilding it on the schedule in its config, so
+/// posts held back by `hold_future_posts` go out on their day, and whenever
+/// the rebuild hook is called. The config is reloaded on every build,
+/// schedule included.
+pub fn daemon(config_path: &Path, overrides: &Overrides) -> Result<()> {
+    let site = serve::build(config_path, overrides, &Cancel::new())
+        .context("Unable to load the config to build")?;
+    if let Some(schedule) = &site.schedule {
+        schedule.next(Utc::now(), Utc::now())?;
+    }
+    let (rebuild, requested) = mpsc::channel();
+    match &site.hooks {
+        Some(hooks) => listen(hooks, rebuild)?,
+        None if site.schedule.is_none() => {
+            bail!("No `daemon` schedule or `hooks` in config [{config_path:?}]")
+        }
+        None => drop(rebuild),
+    }
+
+    let mut schedule = site.schedule;
+    let mut built = Utc::now();
+    loop {
+        let now = Utc::now();
+        let next = match schedule.as_ref().map(|schedule| schedule.next(built, now)) {
+            Some(Ok(next)) => next,
+            Some(Err(e)) => {
+                tracing::error!("Invalid daemon schedule: {e:#}");
+                None
+            }
+            None => None,
+        };
+        let wait = match next {
+            Some(next) => {
+                tracing::info!("Next build at {}", next.format("%Y-%m-%d %H:%M UTC"));
+                (next - now).to_std().unwrap_or_default()
+            }
+            None => IDLE_INTERVAL,
+        };
+        match requested.recv_timeout(wait) {
+            Ok(()) => tracing::info!("Rebuild hook called"),
+            // Without hooks there is no sender and this returns at once
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(wait),
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        // Hook calls made before this build are all served by it
+        while requested.try_recv().is_ok() {}
+
+        built = Utc::now();
+        // Hook calls while building start the build over, with whatever they
+        // were called for
+        let hooked = || {
+            let mut hooked = false;
+            while requested.try_recv().is_ok() {
+                hooked = true;
+            }
+            hooked
+        };
+        if let Some(site) = serve::build_fresh(config_path, overrides, hooked) {
+            schedule = site.schedule;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, types::PageUrl};
+
+/// Extensions of the data files looked up next to a render template, in order
+pub(crate) const DATA_EXTENSIONS: [&str; 4] = ["yaml", "yml", "json", "toml"];
+
+/// A template rendered into the output root, optionally with its own data
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum RenderEntry {
+    /// Template name, paired with `data/<stem>.{yaml,yml,json,toml}` when present
+    Template(String),
+    /// Template with an explicit data file
+    WithData {
+        template: String,
+        /// Data file exposed to the template as `page`, relative to the input directory
+        data: PathBuf,
+    },
+}
+
+impl RenderEntry {
+    pub(crate) fn template(&self) -> &str {
+        match self {
+            RenderEntry::Template(template) | RenderEntry::WithData { template, .. } => template,
+        }
+    }
+
+    /// Root-relative URL the template is served at, its directory for `index.html`
+    pub(crate) fn href(&self, config: &Config) -> String {
+        let template = self.template();
+        config.site_url(template.strip_suffix("index.html").unwrap_or(template))
+    }
+
+    /// What the template sees as `page`: its data with `url` and `permalink`
+    /// added, unless the data has its own. Data that is not a mapping is left
+    /// as it is.
+    pub(crate) fn page(&self, config: &Config) -> Result<serde_json::Value> {
+        let urls = PageUrl::new(&self.href(config), config);
+        Ok(match self.data(config)? {
+            serde_json::Value::Null => serde_json::to_value(urls)?,
+            serde_json::Value::Object(mut data) => {
+                data.entry("url").or_insert(urls.url.into());
+                data.entry("permalink").or_insert(urls.permalink.into());
+                serde_json::Value::Object(data)
+            }
+            data => data,
+        })
+    }
+
+    /// Data file for the template, if it has one
+    fn data_file(&self, config: &Config) -> Option<PathBuf> {
+        match self {
+            RenderEntry::WithData { data, .. } => Some(config.input.join(data)),
+            RenderEntry::Template(template) => {
+                let stem = Path::new(template).file_stem()?;
+                let base = config.input.join("data").join(stem);
+                DATA_EXTENSIONS
+                    .iter()
+                    .map(|ext| base.with_extension(ext))
+                    .find(|path| path.is_file())
+            }
+        }
+    }
+
+    /// Load the data of the template, `Null` when it has none
+    pub(crate) fn data(&self, config: &Config) -> Result<serde_json::Value> {
+        match self.data_file(config) {
+            Some(path) => load(&path),
+            None => Ok(serde_json::Value::Null),
+        }
+    }
+}
+
+/// Read a YAML, JSON or TOML data file, picking the format from its extension
+pub(crate) fn load(path: &Path) -> Result<serde_json::Value> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| anyhow!("Unable to read data file [{path:?}]"))?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(&raw)
+            .with_context(|| anyhow!("Data file is not valid YAML [{path:?}]")),
+        Some("toml") => {
+            toml::from_str(&raw).with_context(|| anyhow!("Data file is not valid TOML [{path:?}]"))
+        }
+        _ => serde_json::from_str(&raw)
+            .with_context(|| anyhow!("Data file is not valid JSON [{path:?}]")),
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, manifest, output::Output};
+
+/// Hex digits of the content hash kept in extracted file names
+const HASH_LENGTH: usize = 20;
+
+fn default_threshold() -> usize {
+    16 * 1024
+}
+
+fn default_dir() -> String {
+    String::from("inline")
+}
+
+/// Move big base64 `data:` URIs out of rendered pages into files of their own,
+/// as notebook exports embed every plot that way
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DataUriConfig {
+    /// Size in bytes of the encoded data from which a URI is extracted
+    #[serde(default = "default_threshold")]
+    pub(crate) threshold: usize,
+    /// Output directory the extracted files are written to
+    #[serde(default = "default_dir")]
+    pub(crate) dir: String,
+}
+
+/// File extension for a media type
+fn extension(mime: &str) -> &str {
+    match mime {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        "image/svg+xml" => "svg",
+        "application/pdf" => "pdf",
+        "audio/mpeg" => "mp3",
+        "video/mp4" => "mp4",
+        _ => "bin",
+    }
+}
+
+/// The page with every big enough `data:` URI replaced by the URL of a file
+/// holding its decoded contents, the files queued into the output and added
+/// to `files`
+pub(crate) fn extract(
+    html: String,
+    config: &Config,
+    output: &Output,
+    files: &mut Vec<PathBuf>,
+) -> Result<String> {
+    let Some(settings) = &config.data_uris else {
+        return Ok(html);
+    };
+    if !html.contains(";base64,") {
+        return Ok(html);
+    }
+
+    let mut extracted = String::with_capacity(html.len());
+    let mut rest = html.as_str();
+    while let Some(start) = rest.find("data:") {
+        let (before, uri) = rest.split_at(start);
+        extracted.push_str(before);
+
+        let header_end = uri
+            .find(";base64,")
+            .filter(|end| !uri[5..*end].contains(['"', '\'', ' ', '>', '(']));
+        let Some(header_end) = header_end else {
+            extracted.push_str("data:");
+            rest = &uri[5..];
+            continue;
+        };
+        let payload_start = header_end + ";base64,".len();
+        let payload_len = uri[payload_start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=')))
+            .unwrap_or(uri.len() - payload_start);
+        let end = payload_start + payload_len;
+        if payload_len < settings.threshold {
+            extracted.push_str(&uri[..end]);
+            rest = &uri[end..];
+            continue;
+        }
+
+        // Media type parameters like `;charset=` come before `;base64`
+        let mime = uri[5..header_end].split(';').next().unwrap_or_default();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&uri[payload_start..end])
+            .with_context(|| anyhow!("Invalid base64 in a [{mime}] data URI"))?;
+        let hash = manifest::digest(&bytes);
+        let path = PathBuf::from(&settings.dir)
+            .join(&hash[..HASH_LENGTH])
+            .with_extension(extension(mime));
+        extracted.push_str(&config.site_url(&path.to_string_lossy()));
+        let file = config.output.join(path);
+        output.add(file.clone(), bytes)?;
+        files.push(file);
+        rest = &uri[end..];
+    }
+    extracted.push_str(rest);
+    Ok(extracted)
+}
@@ -0,0 +1,129 @@
+use std::{
+    ffi::OsStr,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Environment variable overriding `decrypt.identity`
+const AGE_IDENTITY_VAR: &str = "MUB_AGE_IDENTITY";
+/// Environment variable overriding `decrypt.passphrase_file`
+const GPG_PASSPHRASE_VAR: &str = "MUB_GPG_PASSPHRASE_FILE";
+
+/// Keys for content files kept encrypted in the repository
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Decrypt {
+    /// age identity file used for `.age` content
+    #[serde(default)]
+    pub(crate) identity: Option<PathBuf>,
+    /// File with the passphrase for symmetrically encrypted `.gpg` content.
+    /// Without it gpg falls back to the user's keyring and agent.
+    #[serde(default)]
+    pub(crate) passphrase_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Encryption {
+    Age,
+    Gpg,
+}
+
+impl Encryption {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("age") => Some(Self::Age),
+            Some("gpg" | "asc") => Some(Self::Gpg),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the content file is encrypted, so nothing read from it is to be
+/// kept in plain text
+pub(crate) fn is_encrypted(path: &Path) -> bool {
+    Encryption::of(path).is_some()
+}
+
+/// The path a content file would have if it was not encrypted, so
+/// `post.md.age` is treated as `post.md`
+pub(crate) fn plain_path(path: &Path) -> PathBuf {
+    match Encryption::of(path) {
+        Some(_) => path.with_extension(""),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Key configured in the environment, falling back to the config file
+fn key(var: &str, configured: &Option<PathBuf>) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .or_else(|| configured.clone())
+}
+
+/// Read a content file, decrypting it first if it is encrypted
+pub(crate) fn read_content(path: &Path, config: &Config) -> Result<String> {
+    let Some(encryption) = Encryption::of(path) else {
+        return std::fs::read_to_string(path)
+            .with_context(|| anyhow!("Unable to read content of a file to string [{path:?}]"));
+    };
+
+    let mut command = match encryption {
+        Encryption::Age => {
+            let identity = key(AGE_IDENTITY_VAR, &config.decrypt.identity).with_context(|| {
+                anyhow!("Encrypted content [{path:?}] needs an age identity, set `decrypt.identity` or {AGE_IDENTITY_VAR}")
+            })?;
+            let mut command = Command::new("age");
+            command.arg("--decrypt").arg("--identity").arg(identity);
+            command
+        }
+        Encryption::Gpg => {
+            let mut command = Command::new("gpg");
+            command.args(["--batch", "--quiet", "--decrypt"]);
+            if let Some(passphrase) = key(GPG_PASSPHRASE_VAR, &config.decrypt.passphrase_file) {
+                command
+                    .args(["--pinentry-mode", "loopback", "--passphrase-file"])
+                    .arg(passphrase);
+            }
+            command
+        }
+    };
+
+    let encrypted = std::fs::read(path)
+        .with_context(|| anyhow!("Unable to read encrypted content [{path:?}]"))?;
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| {
+            anyhow!("Unable to run {encryption:?} to decrypt [{path:?}], is it installed?")
+        })?;
+    // Feed stdin from a thread so a large file cannot deadlock against stdout
+    let mut stdin = child
+        .stdin
+        .take()
+        .context("Decryption process has no stdin")?;
+    let writer = std::thread::spawn(move || stdin.write_all(&encrypted));
+    let output = child
+        .wait_with_output()
+        .with_context(|| anyhow!("Decryption of [{path:?}] failed"))?;
+    writer
+        .join()
+        .map_err(|_| anyhow!("Unable to pass [{path:?}] to the decryption process"))??;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Unable to decrypt [{path:?}]: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout)
+        .with_context(|| anyhow!("Decrypted content of [{path:?}] is not valid UTF-8"))
+}
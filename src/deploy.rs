@@ -0,0 +1,193 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::manifest;
+
+/// What deploying the output over a copy of an earlier build changes, from
+/// the build manifests of both. Files of the copy outside of its manifest,
+/// as preserved paths, are left alone.
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// Files that are new or changed since the deployed build
+    pub upload: Vec<PathBuf>,
+    /// Files of the deployed build the output no longer has
+    pub delete: Vec<PathBuf>,
+    /// Files the same in both
+    pub skip: usize,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.upload.is_empty() && self.delete.is_empty()
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (label, paths) in [("upload", &self.upload), ("delete", &self.delete)] {
+            for path in paths {
+                writeln!(f, "{label:<10} {}", path.display())?;
+            }
+        }
+        writeln!(
+            f,
+            "{} to upload, {} to delete, {} unchanged",
+            self.upload.len(),
+            self.delete.len(),
+            self.skip
+        )
+    }
+}
+
+/// The manifest read from `source`, refusing paths leading out of the
+/// directory it describes, as a tampered remote manifest could hold
+fn checked(
+    manifest: BTreeMap<PathBuf, String>,
+    source: &Path,
+) -> Result<BTreeMap<PathBuf, String>> {
+    for path in manifest.keys() {
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            bail!("Manifest [{source:?}] lists [{path:?}], which leads out of the site");
+        }
+    }
+    Ok(manifest)
+}
+
+/// Plan going from the `deployed` manifest to the `built` one
+fn diff(built: &BTreeMap<PathBuf, String>, deployed: &BTreeMap<PathBuf, String>) -> Plan {
+    let mut plan = Plan::default();
+    for (path, hash) in built {
+        match deployed.get(path) {
+            Some(deployed) if deployed == hash => plan.skip += 1,
+            _ => plan.upload.push(path.clone()),
+        }
+    }
+    plan.delete = deployed
+        .keys()
+        .filter(|path| !built.contains_key(*path))
+        .cloned()
+        .collect();
+    plan
+}
+
+/// Plan deploying the built `output` into the local directory `target`,
+/// against the manifest `remote` when given, as one downloaded from where
+/// `target` is synced to, or the one in `target`. Without any, every file is
+/// uploaded. Remote storage isn't listed, it is deployed to through a local
+/// directory synced to it.
+pub fn plan(output: &Path, target: &Path, remote: Option<&Path>) -> Result<Plan> {
+    let built = checked(manifest::read(output)?, output)?;
+    let deployed = match remote {
+        Some(remote) => {
+            let bytes = std::fs::read(remote)
+                .with_context(|| anyhow!("Unable to read remote manifest [{remote:?}]"))?;
+            let deployed = serde_json::from_slice(&bytes)
+                .with_context(|| anyhow!("Invalid remote manifest [{remote:?}]"))?;
+            checked(deployed, remote)?
+        }
+        None if target.join(manifest::FILE).is_file() => checked(manifest::read(target)?, target)?,
+        None => BTreeMap::new(),
+    };
+    Ok(diff(&built, &deployed))
+}
+
+/// Carry out the plan, copying the manifest and its signature last so an
+/// interrupted deploy is planned again in full
+pub fn apply(plan: &Plan, output: &Path, target: &Path) -> Result<()> {
+    for path in &plan.upload {
+        let (from, to) = (output.join(path), target.join(path));
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| anyhow!("Unable to create directory [{parent:?}]"))?;
+        }
+        std::fs::copy(&from, &to)
+            .with_context(|| anyhow!("Unable to upload [{from:?}] to [{to:?}]"))?;
+    }
+    for path in &plan.delete {
+        let file = target.join(path);
+        match std::fs::remove_file(&file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| anyhow!("Unable to delete [{file:?}]"));
+            }
+            _ => {}
+        }
+    }
+    for name in [manifest::FILE, manifest::SIGNATURE_FILE] {
+        let (from, to) = (output.join(name), target.join(name));
+        if from.is_file() {
+            std::fs::copy(&from, &to)
+                .with_context(|| anyhow!("Unable to upload [{from:?}] to [{to:?}]"))?;
+        } else if to.is_file() {
+            std::fs::remove_file(&to).with_context(|| anyhow!("Unable to delete [{to:?}]"))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(files: &[(&str, &str)]) -> BTreeMap<PathBuf, String> {
+        files
+            .iter()
+            .map(|(path, hash)| (PathBuf::from(path), hash.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn plans_new_changed_and_gone_files() {
+        let built = manifest(&[("index.html", "a"), ("new.html", "b"), ("same.css", "c")]);
+        let deployed = manifest(&[("index.html", "z"), ("old.html", "y"), ("same.css", "c")]);
+        let plan = diff(&built, &deployed);
+        assert_eq!(
+            plan.upload,
+            [PathBuf::from("index.html"), PathBuf::from("new.html")]
+        );
+        assert_eq!(plan.delete, [PathBuf::from("old.html")]);
+        assert_eq!(plan.skip, 1);
+    }
+
+    #[test]
+    fn refuses_manifests_leading_out_of_the_site() {
+        let source = Path::new("remote.json");
+        for path in ["../../home/u/.bashrc", "/etc/x", "posts/../../x", "./x"] {
+            let error = checked(manifest(&[(path, "a")]), source).unwrap_err();
+            assert!(
+                error.to_string().contains("leads out of the site"),
+                "{error}"
+            );
+        }
+        assert!(checked(manifest(&[("posts/a.html", "a")]), source).is_ok());
+    }
+
+    #[test]
+    fn applies_the_plan_leaving_other_files_alone() {
+        let dir = std::env::temp_dir().join(format!("mub-deploy-{}", std::process::id()));
+        let (output, target) = (dir.join("dist"), dir.join("target"));
+        std::fs::create_dir_all(output.join("posts")).unwrap();
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(output.join("posts/new.html"), "new").unwrap();
+        std::fs::write(output.join(manifest::FILE), r#"{"posts/new.html": "b"}"#).unwrap();
+        std::fs::write(target.join("old.html"), "old").unwrap();
+        std::fs::write(target.join("CNAME"), "example.com").unwrap();
+        std::fs::write(target.join(manifest::FILE), r#"{"old.html": "y"}"#).unwrap();
+
+        let plan = plan(&output, &target, None).unwrap();
+        apply(&plan, &output, &target).unwrap();
+        let read = |path: &str| std::fs::read_to_string(target.join(path)).ok();
+        assert_eq!(read("posts/new.html").as_deref(), Some("new"));
+        assert_eq!(read("old.html"), None);
+        assert_eq!(read("CNAME").as_deref(), Some("example.com"));
+        assert!(super::plan(&output, &target, None).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+/// Why the library failed, for callers that want to tell a broken config from
+/// broken content or a failed write. Every variant keeps the whole chain of
+/// context that led to it, `{:#}` prints it on one line.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The config file can't be read or doesn't describe a site
+    Config(anyhow::Error),
+    /// A content file can't be parsed, or points at something that isn't there
+    Content(anyhow::Error),
+    /// Rendering the site or writing it to the output failed
+    Output(anyhow::Error),
+    /// The build was cancelled or ran out of time before it was done
+    Cancelled(anyhow::Error),
+    /// The build hooks given with the config failed before or after the build
+    Hook(anyhow::Error),
+}
+
+impl Error {
+    fn inner(&self) -> &anyhow::Error {
+        match self {
+            Error::Config(e)
+            | Error::Content(e)
+            | Error::Output(e)
+            | Error::Cancelled(e)
+            | Error::Hook(e) => e,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> anyhow::Error {
+        match self {
+            Error::Config(e)
+            | Error::Content(e)
+            | Error::Output(e)
+            | Error::Cancelled(e)
+            | Error::Hook(e) => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match f.alternate() {
+            true => write!(f, "{:#}", self.inner()),
+            false => write!(f, "{}", self.inner()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner().source()
+    }
+}
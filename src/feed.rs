@@ -0,0 +1,258 @@
+use anyhow::{bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    output::Output,
+    types::{AvailableContent, Content, PostDate},
+};
+
+/// Characters of post text kept in summaries built from the body
+const SUMMARY_LENGTH: usize = 280;
+
+fn default_formats() -> Vec<FeedFormat> {
+    vec![FeedFormat::Atom]
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    /// `atom.xml`
+    Atom,
+    /// `rss.xml`
+    Rss,
+}
+
+/// Feeds of the latest published posts
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FeedConfig {
+    /// Feed title, `site.title` when not set
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default = "default_formats")]
+    pub(crate) formats: Vec<FeedFormat>,
+    /// How many of the latest posts the feeds carry
+    #[serde(default = "default_limit")]
+    pub(crate) limit: usize,
+    /// Carry the whole post instead of its `description`, its `summary_html` or
+    /// the start of its text
+    #[serde(default)]
+    pub(crate) full_content: bool,
+}
+
+/// Text escaped for XML
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The first `length` characters of text with its whitespace collapsed, `…`
+/// marking where it was cut
+pub(crate) fn summary(text: &str, length: usize) -> String {
+    let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match words.char_indices().nth(length) {
+        Some((end, _)) => format!("{}…", &words[..end]),
+        None => words,
+    }
+}
+
+/// RFC 2822 date of a post, as RSS wants it
+pub(crate) fn rfc2822(date: &PostDate) -> String {
+    date.utc().to_rfc2822()
+}
+
+/// RFC 3339 date of a post, as Atom wants it
+fn rfc3339(date: &PostDate) -> String {
+    date.utc().to_rfc3339()
+}
+
+/// A post as a feed item
+struct Item<'a> {
+    content: &'a Content,
+    link: String,
+    /// HTML of the whole post or of its summary, or a plain text summary
+    body: String,
+    /// Whether `body` is HTML
+    html: bool,
+}
+
+impl<'a> Item<'a> {
+    fn new(content: &'a Content, feed: &FeedConfig) -> Self {
+        let post = &content.post;
+        let (body, html) = match (
+            feed.full_content,
+            post.metadata.extra_str("description"),
+            &post.summary_html,
+        ) {
+            (true, _, _) => (post.html.clone(), true),
+            (false, Some(description), _) => (description.to_owned(), false),
+            (false, None, Some(summary_html)) => (summary_html.clone(), true),
+            (false, None, None) => (
+                summary(post.text.as_deref().unwrap_or(&post.raw), SUMMARY_LENGTH),
+                false,
+            ),
+        };
+        Self {
+            content,
+            link: content.location.canonical.clone(),
+            body,
+            html,
+        }
+    }
+
+    fn rss(&self, config: &Config) -> String {
+        let metadata = &self.content.post.metadata;
+        let link = escape_xml(&self.link);
+        let mut item = format!(
+            "<item><title>{}</title><link>{link}</link><guid isPermaLink=\"true\">{link}</guid>",
+            escape_xml(&metadata.title)
+        );
+        item.push_str(&format!("<pubDate>{}</pubDate>", rfc2822(&metadata.date)));
+        item.push_str(&format!(
+            "<description>{}</description>",
+            escape_xml(&self.body)
+        ));
+        if let Some(enclosure) = &self.content.post.enclosure {
+            item.push_str(&format!(
+                "<enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>",
+                escape_xml(&config.absolute_url(&enclosure.href)),
+                enclosure.length,
+                enclosure.mime
+            ));
+        }
+        item.push_str("</item>\n");
+        item
+    }
+
+    fn atom(&self, feed: &FeedConfig, config: &Config) -> String {
+        let metadata = &self.content.post.metadata;
+        let link = escape_xml(&self.link);
+        let mut entry = format!(
+            "<entry><title>{}</title><link href=\"{link}\"/><id>{link}</id>",
+            escape_xml(&metadata.title)
+        );
+        entry.push_str(&format!("<updated>{}</updated>", rfc3339(&metadata.date)));
+        let kind = match self.html {
+            true => " type=\"html\"",
+            false => "",
+        };
+        match feed.full_content {
+            true => entry.push_str(&format!(
+                "<content{kind}>{}</content>",
+                escape_xml(&self.body)
+            )),
+            false => entry.push_str(&format!(
+                "<summary{kind}>{}</summary>",
+                escape_xml(&self.body)
+            )),
+        }
+        if let Some(enclosure) = &self.content.post.enclosure {
+            entry.push_str(&format!(
+                "<link rel=\"enclosure\" href=\"{}\" length=\"{}\" type=\"{}\"/>",
+                escape_xml(&config.absolute_url(&enclosure.href)),
+                enclosure.length,
+                enclosure.mime
+            ));
+        }
+        entry.push_str("</entry>\n");
+        entry
+    }
+}
+
+/// Queue the configured feeds of the latest published posts
+pub(crate) fn write(content: &AvailableContent, config: &Config, output: &Output) -> Result<()> {
+    let Some(feed) = &config.feed else {
+        return Ok(());
+    };
+    let Some(base_url) = &config.base_url else {
+        bail!("Feeds need `base_url` to link to posts with absolute URLs");
+    };
+
+    let mut posts: Vec<_> = content
+        .content
+        .iter()
+        .filter(|content| content.publish && !content.draft && !content.bare)
+        .collect();
+    posts.truncate(feed.limit);
+    let items: Vec<_> = posts
+        .into_iter()
+        .map(|content| Item::new(content, feed))
+        .collect();
+
+    let title = feed
+        .title
+        .as_deref()
+        .or_else(|| config.site.get("title").and_then(|title| title.as_str()))
+        .unwrap_or("Feed");
+    let site = escape_xml(base_url);
+
+    for format in &feed.formats {
+        let (file, xml) = match format {
+            FeedFormat::Rss => {
+                let feed_url = escape_xml(&config.absolute_url(&config.site_url("rss.xml")));
+                let mut channel = format!(
+                    "<title>{}</title><link>{site}</link><description>{}</description>\
+                     <atom:link href=\"{feed_url}\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+                    escape_xml(title),
+                    escape_xml(title)
+                );
+                for item in &items {
+                    channel.push_str(&item.rss(config));
+                }
+                (
+                    "rss.xml",
+                    format!(
+                        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                         <rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\"><channel>{channel}</channel></rss>\n"
+                    ),
+                )
+            }
+            FeedFormat::Atom => {
+                let feed_url = escape_xml(&config.absolute_url(&config.site_url("atom.xml")));
+                let updated = items
+                    .first()
+                    .map(|item| rfc3339(&item.content.post.metadata.date))
+                    .unwrap_or_else(|| content.at.to_rfc3339());
+                let mut entries = format!(
+                    "<title>{}</title><id>{site}</id><link href=\"{site}\"/>\
+                     <link rel=\"self\" href=\"{feed_url}\"/><updated>{updated}</updated>\n",
+                    escape_xml(title)
+                );
+                if let Some(author) = config.site.get("author").and_then(|author| author.as_str()) {
+                    entries.push_str(&format!(
+                        "<author><name>{}</name></author>\n",
+                        escape_xml(author)
+                    ));
+                }
+                for item in &items {
+                    entries.push_str(&item.atom(feed, config));
+                }
+                (
+                    "atom.xml",
+                    format!(
+                        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                         <feed xmlns=\"http://www.w3.org/2005/Atom\">{entries}</feed>\n"
+                    ),
+                )
+            }
+        };
+        output.add(config.output.join(file), xml)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use minijinja::{value::Kwargs, Environment, Error, ErrorKind, Value};
+
+use crate::{captions, collation::Collation, config::Config, types::PostDate};
+
+/// Formats post dates are displayed in, with and without a time of day, so
+/// dates templates get from posts read back whatever formats are configured
+const DISPLAY_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d"];
+
+/// Lowercase words joined by dashes, as used for post names
+pub(crate) fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match c.is_alphanumeric() {
+            true => slug.push(c),
+            false if !slug.is_empty() && !slug.ends_with('-') => slug.push('-'),
+            false => {}
+        }
+    }
+    slug.trim_end_matches('-').to_owned()
+}
+
+/// The first `count` words of the text, with `end` appended when any were cut
+pub(crate) fn truncate_words(text: &str, count: usize, end: &str) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.len() <= count {
+        return words.join(" ");
+    }
+    format!("{}{end}", words[..count].join(" "))
+}
+
+/// A date as templates have it written in `format`, reading it as post dates
+/// are displayed before trying the configured `date_formats`
+fn format_date(date: &str, format: &str, date_formats: &[String]) -> Result<String, Error> {
+    PostDate::parse(date, date_formats)
+        .and_then(|date| date.format(format))
+        .map_err(|e| Error::new(ErrorKind::InvalidOperation, format!("{e:#}")))
+}
+
+/// Markdown rendered to HTML, without mub's post processing
+fn markdown(text: &str) -> Value {
+    let mut html = String::new();
+    let parser = pulldown_cmark::Parser::new_ext(text, pulldown_cmark::Options::ENABLE_FOOTNOTES);
+    pulldown_cmark::html::push_html(&mut html, parser);
+    Value::from_safe_string(html)
+}
+
+/// Register the filters themes need for values straight from the front matter
+pub(crate) fn register(env: &mut Environment, config: &Config, collation: Collation) {
+    env.add_filter("slugify", |text: String| slugify(&text));
+    env.add_filter("markdown", |text: String| markdown(&text));
+    env.add_filter(
+        "truncatewords",
+        |text: String, count: Option<usize>, end: Option<String>| {
+            truncate_words(&text, count.unwrap_or(30), end.as_deref().unwrap_or("…"))
+        },
+    );
+    env.add_filter("striptags", |text: String| {
+        captions::strip_tags(&text)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    env.add_filter("jsonencode", minijinja::filters::tojson);
+
+    // Sorting and A to Z grouping in the site's locale, by a dotted attribute
+    // path given first or as `attribute`, like the builtin `sort`
+    let sorting = collation.clone();
+    env.add_filter(
+        "collate",
+        move |values: Value, attribute: Option<String>, kwargs: Kwargs| {
+            let attribute = attribute.or(kwargs.get("attribute")?);
+            let reverse = kwargs.get::<Option<bool>>("reverse")?.unwrap_or(false);
+            kwargs.assert_all_used()?;
+            sorting.sort(&values, attribute.as_deref(), reverse)
+        },
+    );
+    env.add_filter(
+        "groupbyinitial",
+        move |values: Value, attribute: Option<String>, kwargs: Kwargs| {
+            let attribute = attribute.or(kwargs.get("attribute")?);
+            kwargs.assert_all_used()?;
+            collation.group(&values, attribute.as_deref())
+        },
+    );
+
+    // Post dates and build times, written with a `strftime` style format
+    let date_formats: Vec<String> = DISPLAY_FORMATS
+        .into_iter()
+        .map(String::from)
+        .chain(config.date_formats.iter().cloned())
+        .collect();
+    let date_format =
+        move |date: String, format: String| format_date(&date, &format, &date_formats);
+    env.add_filter("date_format", date_format.clone());
+    env.add_filter("dateformat", date_format);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(custom: &[&str]) -> Vec<String> {
+        DISPLAY_FORMATS
+            .into_iter()
+            .chain(custom.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn formats_dates_as_posts_display_them_whatever_is_configured() {
+        let formats = formats(&["%d/%m/%Y"]);
+        let format = |date| format_date(date, "%B %-d, %Y", &formats).unwrap();
+        assert_eq!(format("2024-03-05"), "March 5, 2024");
+        assert_eq!(format("2024-03-05T10:30:00"), "March 5, 2024");
+        assert_eq!(format("05/03/2024"), "March 5, 2024");
+        assert_eq!(format("2024-03-05T10:30:00+00:00"), "March 5, 2024");
+    }
+
+    #[test]
+    fn keeps_the_time_of_timed_dates() {
+        let formats = formats(&[]);
+        let time = format_date("2024-03-05T10:30:00", "%H:%M", &formats).unwrap();
+        assert_eq!(time, "10:30");
+    }
+
+    #[test]
+    fn fails_on_dates_in_no_known_format() {
+        assert!(format_date("March 5th", "%Y", &formats(&[])).is_err());
+    }
+
+    #[test]
+    fn truncates_at_words() {
+        assert_eq!(truncate_words("one two  three", 2, "…"), "one two…");
+        assert_eq!(truncate_words("one two", 2, "…"), "one two");
+    }
+}
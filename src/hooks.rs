@@ -0,0 +1,153 @@
+use std::{io::Read, sync::mpsc::Sender};
+
+use anyhow::{anyhow, bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tiny_http::{Method, Request, Response};
+
+/// Environment variable overriding `hooks.secret`
+const SECRET_VAR: &str = "MUB_HOOK_SECRET";
+
+/// Path the rebuild hook is served at
+const REBUILD_PATH: &str = "/hooks/rebuild";
+
+/// Largest hook body read, anything bigger is refused unread
+const MAX_BODY: usize = 64 * 1024;
+
+/// Block size of SHA-256, for HMAC
+const BLOCK_SIZE: usize = 64;
+
+fn default_listen() -> String {
+    String::from("127.0.0.1:8001")
+}
+
+/// Rebuild hook of `mub serve` and `mub daemon`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Secret callers authenticate with, either as an `Authorization: Bearer`
+    /// token or as the key of a GitHub style `X-Hub-Signature-256`
+    #[serde(default)]
+    pub(crate) secret: Option<String>,
+    /// Address `mub daemon` listens on for hooks, `mub serve` takes them on its own port
+    #[serde(default = "default_listen")]
+    pub(crate) listen: String,
+}
+
+impl HooksConfig {
+    /// Secret configured in the environment, falling back to the config file
+    pub(crate) fn secret(&self) -> Result<String> {
+        match std::env::var(SECRET_VAR)
+            .ok()
+            .or_else(|| self.secret.clone())
+        {
+            Some(secret) if !secret.is_empty() => Ok(secret),
+            _ => bail!("Hooks need a secret, set `hooks.secret` or {SECRET_VAR}"),
+        }
+    }
+}
+
+/// Whether a request is for a hook rather than a file
+pub(crate) fn is_hook(request: &Request) -> bool {
+    request.url().split('?').next() == Some(REBUILD_PATH)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// HMAC-SHA256 of `message`, hex encoded
+fn hmac(key: &[u8], message: &[u8]) -> String {
+    let mut block = [0u8; BLOCK_SIZE];
+    match key.len() > BLOCK_SIZE {
+        true => block[..32].copy_from_slice(&Sha256::digest(key)),
+        false => block[..key.len()].copy_from_slice(key),
+    }
+    let pad = |byte: u8| block.iter().map(|k| k ^ byte).collect::<Vec<_>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    let outer = Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize();
+    hex(&outer)
+}
+
+/// Comparison taking the same time wherever the inputs differ
+pub(crate) fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The `Authorization: Bearer` token of the request, if any
+fn bearer(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+}
+
+/// Whether the request carries the secret, or a signature of its body made with it
+fn authorized(request: &Request, body: &[u8], secret: &str) -> bool {
+    if bearer(request).is_some_and(|token| same(token, secret)) {
+        return true;
+    }
+    request
+        .headers()
+        .iter()
+        .filter(|header| header.field.equiv("X-Hub-Signature-256"))
+        .any(|header| {
+            header
+                .value
+                .as_str()
+                .strip_prefix("sha256=")
+                .is_some_and(|signature| same(signature, &hmac(secret.as_bytes(), body)))
+        })
+}
+
+/// Answer a hook request, asking for a rebuild through `rebuild` when it is
+/// an authorized `POST`. A wrong token is refused before the body is read,
+/// and a body over [`MAX_BODY`] is never read whole.
+pub(crate) fn handle(mut request: Request, secret: &str, rebuild: &Sender<()>) -> Result<()> {
+    if *request.method() != Method::Post {
+        request.respond(Response::from_string("Method not allowed").with_status_code(405))?;
+        return Ok(());
+    }
+    if bearer(&request).is_some_and(|token| !same(token, secret)) {
+        request.respond(Response::from_string("Unauthorized").with_status_code(401))?;
+        return Ok(());
+    }
+    let too_large = || Response::from_string("Payload too large").with_status_code(413);
+    if request
+        .body_length()
+        .is_some_and(|length| length > MAX_BODY)
+    {
+        request.respond(too_large())?;
+        return Ok(());
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY as u64 + 1)
+        .read_to_end(&mut body)?;
+    if body.len() > MAX_BODY {
+        request.respond(too_large())?;
+        return Ok(());
+    }
+    if !authorized(&request, &body, secret) {
+        request.respond(Response::from_string("Unauthorized").with_status_code(401))?;
+        return Ok(());
+    }
+    rebuild
+        .send(())
+        .map_err(|_| anyhow!("The build loop has stopped"))?;
+    request.respond(Response::from_string("Rebuild queued").with_status_code(202))?;
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, output::Output};
+
+fn default_sprite() -> String {
+    String::from("icons.svg")
+}
+
+/// SVG icons combined into a single sprite
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IconsConfig {
+    /// Directory with one `<name>.svg` per icon, relative to the input directory
+    pub(crate) dir: PathBuf,
+    /// Where the sprite is written in the output
+    #[serde(default = "default_sprite")]
+    pub(crate) sprite: String,
+}
+
+/// Icon files by name, sorted so the sprite is stable
+fn icon_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut icons = std::fs::read_dir(dir)
+        .with_context(|| anyhow!("Unable to read icons directory [{}]", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "svg"))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            Some((name, path))
+        })
+        .collect::<Vec<_>>();
+    icons.sort();
+    Ok(icons)
+}
+
+/// Names of every icon available to `icon()`
+pub(crate) fn names(config: &Config) -> Result<Vec<String>> {
+    match &config.icons {
+        Some(icons) => Ok(icon_files(&config.input.join(&icons.dir))?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Turn a standalone svg document into a `<symbol>` keeping its viewBox
+fn symbol(name: &str, svg: &str) -> Option<String> {
+    let open = svg.find("<svg")?;
+    let open_end = open + svg[open..].find('>')?;
+    let close = svg.rfind("</svg>")?;
+    let attributes = &svg[open..open_end];
+    let view_box = attributes
+        .split_once("viewBox=\"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map(|(view_box, _)| format!(" viewBox=\"{view_box}\""))
+        .unwrap_or_default();
+    Some(format!(
+        "<symbol id=\"{name}\"{view_box}>{}</symbol>",
+        svg[open_end + 1..close].trim()
+    ))
+}
+
+/// Queue the sprite with every icon as a symbol
+pub(crate) fn write_sprite(config: &Config, output: &Output) -> Result<()> {
+    let Some(icons) = &config.icons else {
+        return Ok(());
+    };
+    let mut sprite =
+        String::from("<svg xmlns=\"http://www.w3.org/2000/svg\" style=\"display:none\">");
+    for (name, path) in icon_files(&config.input.join(&icons.dir))? {
+        let svg = std::fs::read_to_string(&path)
+            .with_context(|| anyhow!("Unable to read icon [{}]", path.display()))?;
+        sprite.push_str(
+            &symbol(&name, &svg)
+                .with_context(|| anyhow!("Icon is not an svg document [{}]", path.display()))?,
+        );
+    }
+    sprite.push_str("</svg>");
+    output.add(config.output.join(&icons.sprite), sprite)
+}
+
+/// Markup referencing an icon in the sprite
+pub(crate) fn reference(config: &Config, name: &str) -> Option<String> {
+    let icons = config.icons.as_ref()?;
+    Some(format!(
+        "<svg class=\"icon icon-{name}\" aria-hidden=\"true\"><use href=\"{}#{name}\"></use></svg>",
+        config.site_url(&icons.sprite)
+    ))
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache,
+    config::Config,
+    decrypt,
+    manifest::digest,
+    serve::fingerprint,
+    types::{Content, LocationData, Metadata, PostStats, Reading, TocEntry},
+};
+
+/// Directory of the build records, inside the cache directory
+const BUILDS_DIR: &str = "builds";
+
+/// Directory of the markdown conversions, inside the cache directory
+const CONVERTED_DIR: &str = "converted";
+
+/// Hash of what every post and page shares: the config, templates, include
+/// files and bibliography
+fn site_hash(config: &Config) -> Result<String> {
+    let mut files = Vec::new();
+    for dir in ["templates", "include"]
+        .into_iter()
+        .flat_map(|name| config.layered_dirs(name))
+    {
+        fingerprint(&dir, &[], &mut files);
+    }
+    if let Some(bibliography) = &config.bibliography {
+        let path = config.input.join(bibliography);
+        if let Ok(metadata) = path.metadata() {
+            if let Ok(modified) = metadata.modified() {
+                files.push((path, modified, metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    let mut site = canonical_json(config)?;
+    // Pages are stubs or not depending on the root, which isn't in the config file
+    site.extend(format!("{}{files:?}{:?}", env!("CARGO_PKG_VERSION"), config.root).bytes());
+    Ok(digest(&site))
+}
+
+/// A page in the build record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Page {
+    /// Hash of everything the page was rendered from
+    hash: String,
+    /// Files taken out of the page, as its big `data:` URIs, written with it
+    extracted: Vec<PathBuf>,
+}
+
+/// Whether the page of a post has to be rendered again
+pub(crate) enum Freshness {
+    /// The page is in the output as it would be rendered now, along with the
+    /// files extracted from it
+    Fresh(Vec<PathBuf>),
+    /// The page has to be rendered, then recorded with this hash
+    Stale(String),
+}
+
+/// Pages written by the previous build into the same output, each with a hash
+/// of everything it was rendered from. A page whose hash is unchanged and
+/// whose files are still there is not rendered again.
+pub(crate) struct BuildRecord {
+    path: PathBuf,
+    /// Hash of what every page shares: the config, templates and include files
+    site: String,
+    previous: BTreeMap<PathBuf, Page>,
+    current: Mutex<BTreeMap<PathBuf, Page>>,
+}
+
+impl BuildRecord {
+    /// Record of the last build into the configured output, none unless
+    /// builds are incremental
+    pub(crate) fn load(config: &Config) -> Result<Option<Self>> {
+        if !config.incremental {
+            return Ok(None);
+        }
+        let output = config.output.to_string_lossy();
+        let path = cache::dir(config)
+            .join(BUILDS_DIR)
+            .join(format!("{}.json", &digest(output.as_bytes())[..16]));
+        let previous = match path.exists() {
+            true => {
+                let bytes = std::fs::read(&path)
+                    .with_context(|| anyhow!("Unable to read the build record [{path:?}]"))?;
+                // A record written by an older version only costs a full render
+                serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                    tracing::debug!(record = %path.display(), "Dropping unreadable build record: {e}");
+                    BTreeMap::new()
+                })
+            }
+            false => BTreeMap::new(),
+        };
+
+        Ok(Some(Self {
+            path,
+            site: site_hash(config)?,
+            previous,
+            current: Mutex::default(),
+        }))
+    }
+
+    /// Whether the page of the post is already in the output as it would be
+    /// rendered now, remembering it for the next build when it is
+    pub(crate) fn check(&self, content: &Content) -> Result<Freshness> {
+        let mut source = self.site.clone().into_bytes();
+        source.extend(canonical_json(content)?);
+        let hash = digest(&source);
+
+        let dst = &content.location.dst;
+        match self.previous.get(dst) {
+            Some(page)
+                if page.hash == hash
+                    && dst.exists()
+                    && page.extracted.iter().all(|file| file.exists()) =>
+            {
+                self.remember(dst, page.clone())?;
+                Ok(Freshness::Fresh(page.extracted.clone()))
+            }
+            _ => Ok(Freshness::Stale(hash)),
+        }
+    }
+
+    /// Remember the page of the post as rendered with the `hash` from `check`,
+    /// the files in `extracted` taken out of it
+    pub(crate) fn rendered(
+        &self,
+        content: &Content,
+        hash: String,
+        extracted: Vec<PathBuf>,
+    ) -> Result<()> {
+        self.remember(&content.location.dst, Page { hash, extracted })
+    }
+
+    fn remember(&self, dst: &Path, page: Page) -> Result<()> {
+        self.current
+            .lock()
+            .map_err(|_| anyhow!("Build record lock is poisoned"))?
+            .insert(dst.to_path_buf(), page);
+        Ok(())
+    }
+
+    /// Save the record for the next build
+    pub(crate) fn finish(self) -> Result<()> {
+        let current = self
+            .current
+            .into_inner()
+            .map_err(|_| anyhow!("Build record lock is poisoned"))?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| anyhow!("Unable to create the cache directory [{parent:?}]"))?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec(&current)?)
+            .with_context(|| anyhow!("Unable to write the build record [{:?}]", self.path))
+    }
+}
+
+/// What converting the markdown of a post makes of it
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Converted {
+    pub(crate) html: String,
+    /// HTML up to the `<!-- more -->` marker, if there is one
+    pub(crate) summary_html: Option<String>,
+    /// Plain text of the post, none for HTML posts
+    pub(crate) text: Option<String>,
+    pub(crate) assets: Vec<String>,
+    pub(crate) stats: PostStats,
+    pub(crate) reading: Reading,
+    pub(crate) toc: Vec<TocEntry>,
+}
+
+/// Markdown of posts converted by earlier builds, by a hash of everything the
+/// conversion reads, so posts that didn't change aren't converted again
+pub(crate) struct Conversions {
+    dir: PathBuf,
+    site: String,
+}
+
+impl Conversions {
+    /// Conversions of earlier builds, none unless builds are incremental
+    pub(crate) fn load(config: &Config) -> Result<Option<Self>> {
+        if !config.incremental {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            dir: cache::dir(config).join(CONVERTED_DIR),
+            site: site_hash(config)?,
+        }))
+    }
+
+    /// Key of the conversion of the markdown `raw` of the post read from
+    /// `source`, none for encrypted posts as the cache would hold them in
+    /// plain text next to their sources
+    pub(crate) fn key(
+        &self,
+        source: &Path,
+        raw: &str,
+        metadata: &Metadata,
+        location: &LocationData,
+    ) -> Result<Option<String>> {
+        if decrypt::is_encrypted(source) {
+            return Ok(None);
+        }
+        let mut hashed = self.site.clone().into_bytes();
+        hashed.extend(canonical_json(&(metadata, location))?);
+        hashed.extend(raw.bytes());
+        Ok(Some(digest(&hashed)))
+    }
+
+    /// The conversion an earlier build made, none when it is gone or unreadable
+    pub(crate) fn get(&self, key: &str) -> Option<Converted> {
+        let entry = self.dir.join(key);
+        let converted = serde_json::from_slice(&std::fs::read(&entry).ok()?).ok()?;
+        cache::touch(&entry);
+        Some(converted)
+    }
+
+    /// Keep a conversion for the builds to come
+    pub(crate) fn put(&self, key: &str, converted: &Converted) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| anyhow!("Unable to create the cache directory [{:?}]", self.dir))?;
+        let entry = self.dir.join(key);
+        std::fs::write(&entry, serde_json::to_vec(converted)?)
+            .with_context(|| anyhow!("Unable to write the conversion [{entry:?}]"))
+    }
+}
+
+/// JSON with the keys of every object sorted, unlike the maps it is serialized
+/// from, so the same value always hashes the same
+fn canonical_json<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&serde_json::to_value(value)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversions(name: &str) -> Conversions {
+        let dir = std::env::temp_dir().join(format!("mub-{name}-{}", std::process::id()));
+        Conversions {
+            dir,
+            site: String::from("site"),
+        }
+    }
+
+    fn converted(html: &str) -> Converted {
+        Converted {
+            html: html.to_owned(),
+            summary_html: None,
+            text: Some(String::from("text")),
+            assets: vec![String::from("cover.png")],
+            stats: PostStats::default(),
+            reading: Reading::default(),
+            toc: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn conversions_are_keyed_by_what_they_read() {
+        let conversions = conversions("keys");
+        let post = Content::stub("post", "2024-03-05", true);
+        let (metadata, location) = (&post.post.metadata, &post.location);
+        let source = Path::new("post.md");
+        let key = |raw| conversions.key(source, raw, metadata, location).unwrap();
+        assert!(key("# Title").is_some());
+        assert_eq!(key("# Title"), key("# Title"));
+        assert_ne!(key("# Title"), key("# Other title"));
+
+        let moved = Content::stub("moved", "2024-03-05", true);
+        let elsewhere = conversions
+            .key(source, "# Title", metadata, &moved.location)
+            .unwrap();
+        assert_ne!(key("# Title"), elsewhere);
+    }
+
+    #[test]
+    fn encrypted_posts_are_never_kept() {
+        let conversions = conversions("encrypted");
+        let post = Content::stub("post", "2024-03-05", true);
+        let (metadata, location) = (&post.post.metadata, &post.location);
+        for source in ["post.md.age", "post.md.gpg", "post.md.asc"] {
+            let key = conversions
+                .key(Path::new(source), "# Secret", metadata, location)
+                .unwrap();
+            assert_eq!(key, None, "{source}");
+        }
+        assert!(!conversions.dir.exists());
+    }
+
+    #[test]
+    fn conversions_are_kept_for_later_builds() {
+        let conversions = conversions("kept");
+        assert!(conversions.get("key").is_none());
+        conversions.put("key", &converted("<p>Hi</p>")).unwrap();
+        let kept = conversions.get("key").unwrap();
+        assert_eq!(kept.html, "<p>Hi</p>");
+        assert_eq!(kept.assets, ["cover.png"]);
+        std::fs::remove_dir_all(&conversions.dir).unwrap();
+    }
+
+    #[test]
+    fn fresh_pages_keep_the_files_extracted_from_them() {
+        let dir = std::env::temp_dir().join(format!("mub-record-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut post = Content::stub("post", "2024-03-05", true);
+        post.location.dst = dir.join("post.html");
+        let extracted = dir.join("plot.png");
+        let record = |previous| BuildRecord {
+            path: dir.join("record.json"),
+            site: String::from("site"),
+            previous,
+            current: Mutex::default(),
+        };
+
+        let first = record(BTreeMap::new());
+        let Freshness::Stale(hash) = first.check(&post).unwrap() else {
+            panic!("a page never rendered is fresh");
+        };
+        first
+            .rendered(&post, hash, vec![extracted.clone()])
+            .unwrap();
+        std::fs::write(&post.location.dst, "page").unwrap();
+        std::fs::write(&extracted, "plot").unwrap();
+
+        let second = record(first.current.into_inner().unwrap());
+        match second.check(&post).unwrap() {
+            Freshness::Fresh(files) => assert_eq!(files, [extracted.as_path()]),
+            Freshness::Stale(_) => panic!("an unchanged page is stale"),
+        }
+        std::fs::remove_file(&extracted).unwrap();
+        assert!(matches!(second.check(&post).unwrap(), Freshness::Stale(_)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,29 +1,126 @@
 use std::{
+    borrow::Cow,
+    collections::HashMap,
     ffi::OsStr,
-    fs::{read_dir, read_to_string, File},
-    io::{BufWriter, Write},
-    path::PathBuf,
+    fs::read_dir,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use changelog::ChangelogEntry;
+use citations::Bibliography;
+use collation::Collation;
 use config::Config;
 use glob::glob;
+use incremental::{BuildRecord, Conversions, Converted, Freshness};
+use media::MediaStore;
 use minijinja::{context, Environment};
+use output::{Output, Written};
+use plugins::Plugins;
 use rayon::prelude::*;
+use scripts::Scripts;
 use serde::Serialize;
-use types::{AvailableContent, Content, Post, PostSourceKind, SearchableDoc};
+use shortcodes::Shortcodes;
+use transform::{CacheStats, VariantCache};
+use types::{BuildInfo, PostSourceKind, PostStats, Reading};
+use typography::Typography;
 
-use crate::types::{LocationData, Metadata};
+use crate::types::PageUrl;
 
 const POSTS_DIR: &str = "posts";
+/// Content file stems that hold the homepage rather than a post
+const HOME_FILES: [&str; 2] = ["_index", "home"];
+/// Stem of the data file in `content/` with the front matter defaults of posts
+const DEFAULTS_FILE: &str = "_defaults";
 
+pub(crate) mod ansi;
+pub mod archive;
+pub(crate) mod asciinema;
+pub(crate) mod assets;
+pub mod build_hooks;
+pub mod cache;
+pub(crate) mod calendar;
+pub mod cancel;
+pub(crate) mod captions;
+pub(crate) mod changelog;
+pub mod check;
+pub(crate) mod citations;
+pub(crate) mod collation;
 pub mod config;
+pub mod daemon;
+pub(crate) mod data;
+pub(crate) mod data_uri;
+pub(crate) mod decrypt;
+pub mod deploy;
+mod error;
+pub(crate) mod feed;
+pub(crate) mod filters;
+pub(crate) mod hooks;
+pub(crate) mod icons;
+pub(crate) mod incremental;
+pub(crate) mod listing;
+pub mod manifest;
+pub(crate) mod media;
+pub(crate) mod output;
+pub(crate) mod plugins;
+pub(crate) mod podcast;
+pub(crate) mod process;
+pub(crate) mod redirects;
+pub(crate) mod scripts;
+pub(crate) mod search;
+pub(crate) mod sections;
+pub mod serve;
+pub(crate) mod shortcodes;
+pub(crate) mod sitemap;
+pub mod social;
+pub(crate) mod tags;
+pub(crate) mod templates;
+pub(crate) mod transform;
 pub(crate) mod types;
+pub(crate) mod typography;
+pub(crate) mod verify;
 
-fn try_parse_post(filepath: PathBuf) -> Result<Post> {
+pub use error::Error;
+pub use types::{AvailableContent, Content, LocationData, Metadata, Post, PostDate};
+
+/// What posts are parsed with besides the config
+#[derive(Clone, Copy)]
+struct ParseContext<'a> {
+    bibliography: &'a Bibliography,
+    /// Markdown converted by earlier builds, when builds are incremental
+    conversions: Option<&'a Conversions>,
+    media: &'a MediaStore,
+    plugins: &'a Plugins,
+    scripts: &'a Scripts,
+    shortcodes: &'a Shortcodes,
+}
+
+/// Parse a content file, placing it in the output with `locate` once its front
+/// matter is known. `text` stands in for the file when the build hooks made it up
+fn try_parse_post<F>(
+    filepath: PathBuf,
+    text: Option<String>,
+    defaults: &HashMap<String, serde_json::Value>,
+    locate: F,
+    config: &Config,
+    parsing: ParseContext,
+) -> Result<(LocationData, Post)>
+where
+    F: FnOnce(&Metadata) -> Result<LocationData>,
+{
+    config.cancel.check()?;
+    let started = Instant::now();
+    let ParseContext {
+        conversions,
+        media,
+        plugins,
+        scripts,
+        ..
+    } = parsing;
     let kind = PostSourceKind::try_from(
-        filepath
+        decrypt::plain_path(&filepath)
             .extension()
             .with_context(|| {
                 anyhow!("Provided content file does not have an extension [{filepath:?}]",)
@@ -35,62 +132,346 @@ fn try_parse_post(filepath: PathBuf) -> Result<Post> {
     )?;
 
     // Read the file
-    let content = read_to_string(&filepath)
-        .with_context(|| anyhow!("Unable to read content of a file to string [{filepath:?}]"))?;
-
-    let (front_matter, content) = content.split_once("---").with_context(|| {
-        anyhow!("Unable to find the '---' delimiter marking the end of front matter for file [{filepath:?}]")
-    })?;
-
-    let metadata: Metadata = front_matter.try_into().with_context(|| {
-        anyhow!("Unable to extract front matter metadata for a markdown file: [{filepath:?}]")
-    })?;
-
-    let raw = String::from(content);
-    let mut html = raw.clone();
-    let mut text = None;
-
-    // Parse markdown if needs conversion
-    if let PostSourceKind::Markdown = kind {
-        let mut text_in_markdown = String::new();
-        html = String::new();
-        let parser = pulldown_cmark::Parser::new(content).inspect(|event| {
-            if let pulldown_cmark::Event::Text(t) = event {
-                text_in_markdown.push_str(t);
-                text_in_markdown.push(' ')
+    let (content, modified) = match text {
+        Some(text) => (text, chrono::Utc::now()),
+        None => (
+            decrypt::read_content(&filepath, config)?,
+            std::fs::metadata(&filepath)
+                .and_then(|metadata| metadata.modified())
+                .with_context(|| anyhow!("Unable to read the modification time of [{filepath:?}]"))?
+                .into(),
+        ),
+    };
+
+    // TOML front matter is fenced by `+++` lines, anything else ends at `---`
+    let (metadata, front_matter, content) = match content.strip_prefix("+++") {
+        Some(fenced) => {
+            let (front_matter, content) = fenced.split_once("\n+++").with_context(|| {
+                anyhow!("Unable to find the closing '+++' of the TOML front matter for file [{filepath:?}]")
+            })?;
+            let metadata = Metadata::from_toml(front_matter, defaults, &config.date_formats)
+                .with_context(|| {
+                    anyhow!("Unable to extract TOML front matter metadata: [{filepath:?}]")
+                })?;
+            (metadata, front_matter, content)
+        }
+        None => {
+            let (front_matter, content) = content.split_once("---").with_context(|| {
+                anyhow!("Unable to find the '---' delimiter marking the end of front matter for file [{filepath:?}]")
+            })?;
+            let metadata = Metadata::from_yaml(front_matter, defaults, &config.date_formats)
+                .with_context(|| {
+                    anyhow!(
+                    "Unable to extract front matter metadata for a markdown file: [{filepath:?}]"
+                )
+                })?;
+            (metadata, front_matter, content)
+        }
+    };
+
+    // Scripts see and may change the front matter before anything reads it
+    let mut metadata = match scripts.is_empty() {
+        true => metadata,
+        false => Metadata::from_extra(scripts.metadata(metadata.extra)?, &config.date_formats)
+            .with_context(|| {
+                anyhow!("Unable to read front matter changed by scripts [{filepath:?}]")
+            })?,
+    };
+
+    config.tags.normalize(&mut metadata.extra);
+
+    let location = locate(&metadata)?;
+
+    // Posts outside the root of a subtree build get a page to link to, not converted
+    if let Some(root) = config
+        .root
+        .as_ref()
+        .filter(|root| !filepath.starts_with(root))
+    {
+        let post = stub_post(metadata, front_matter, modified, root, config);
+        tracing::debug!(src = %filepath.display(), "Stubbed post outside the root");
+        return Ok((location, post));
+    }
+
+    let mut raw = plugins
+        .transform(String::from(content))
+        .with_context(|| anyhow!("Unable to transform [{filepath:?}] with plugins"))?;
+    config
+        .build_hooks
+        .pre_content(&filepath, &mut raw)
+        .with_context(|| anyhow!("Build hooks failed before converting [{filepath:?}]"))?;
+    // Markdown converted by an earlier build exactly as it would be now is reused
+    let converted = match kind {
+        PostSourceKind::Markdown => {
+            let key = conversions
+                .map(|conversions| conversions.key(&filepath, &raw, &metadata, &location))
+                .transpose()?
+                .flatten();
+            let cached = conversions
+                .zip(key.as_deref())
+                .and_then(|(conversions, key)| conversions.get(key));
+            match cached {
+                Some(converted) => {
+                    tracing::debug!(src = %filepath.display(), "Markdown unchanged since an earlier build");
+                    converted
+                }
+                None => {
+                    let converted =
+                        convert_markdown(&raw, &filepath, &metadata, &location, config, parsing)?;
+                    if let (Some(conversions), Some(key)) = (conversions, &key) {
+                        conversions.put(key, &converted)?;
+                    }
+                    converted
+                }
             }
-        });
-        // Push the html
-        pulldown_cmark::html::push_html(&mut html, parser);
-        text = Some(text_in_markdown);
+        }
+        PostSourceKind::Html => Converted {
+            summary_html: process::html_summary_end(&raw).map(|end| raw[..end].to_owned()),
+            html: raw.clone(),
+            text: None,
+            assets: Vec::new(),
+            stats: PostStats::default(),
+            reading: Reading::default(),
+            toc: Vec::new(),
+        },
+    };
+    let Converted {
+        html,
+        mut summary_html,
+        mut text,
+        mut assets,
+        stats,
+        reading,
+        toc,
+    } = converted;
+
+    // Without a marker the summary is the start of the text, when configured
+    if let (None, Some(words)) = (&summary_html, config.summary_words) {
+        let text = match &text {
+            Some(text) => Cow::Borrowed(text.as_str()),
+            None => Cow::Owned(captions::strip_tags(&html)),
+        };
+        if !text.trim().is_empty() {
+            let summary = filters::truncate_words(&text, words, "…");
+            summary_html = Some(format!("<p>{}</p>", feed::escape_xml(&summary)));
+        }
+    }
+
+    if let Some(cover) = metadata.extra_str("cover_image") {
+        assets.push(cover.to_owned());
     }
 
-    Ok(Post {
+    let media_file = metadata
+        .extra_str("audio")
+        .or_else(|| metadata.extra_str("video"))
+        .or_else(|| metadata.extra_str("enclosure"));
+    let enclosure = media_file.map(|reference| {
+        assets.push(reference.to_owned());
+        podcast::Enclosure::for_reference(reference, &location.path, config, media)
+    });
+
+    // Transcript and chapters come from the front matter or sit next to the media file
+    let mut sidecar = |key: &str, kind: Option<&str>| -> Result<Vec<captions::Cue>> {
+        let reference = match metadata.extra_str(key) {
+            Some(reference) => reference.to_owned(),
+            None => match media_file
+                .and_then(|file| captions::sidecar(file, kind, &location.path, config))
+            {
+                Some(reference) => reference,
+                None => return Ok(Vec::new()),
+            },
+        };
+        assets.push(reference.clone());
+        captions::load(&reference, &location.path, config)
+    };
+    let transcript = sidecar("transcript", None)?;
+    let chapters = sidecar("chapters", Some("chapters"))?;
+
+    if !transcript.is_empty() {
+        let spoken = transcript
+            .iter()
+            .map(|cue| cue.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut searchable = text.unwrap_or_else(|| raw.clone());
+        searchable.push(' ');
+        searchable.push_str(&spoken);
+        text = Some(searchable);
+    }
+
+    let mut post = Post {
         metadata,
+        front_matter: front_matter.trim_matches('\n').to_owned(),
+        modified,
         text,
         html,
+        summary_html,
         raw,
+        assets,
+        stats,
+        reading,
+        toc,
+        enclosure,
+        transcript,
+        chapters,
+    };
+    config
+        .build_hooks
+        .post_content(&filepath, &mut post)
+        .with_context(|| anyhow!("Build hooks failed on the converted [{filepath:?}]"))?;
+    tracing::debug!(src = %filepath.display(), elapsed = ?started.elapsed(), "Parsed post");
+    Ok((location, post))
+}
+
+/// Markdown of a post converted to HTML, with its plain text, the files it
+/// references and the structure of its text
+fn convert_markdown(
+    raw: &str,
+    filepath: &Path,
+    metadata: &Metadata,
+    location: &LocationData,
+    config: &Config,
+    parsing: ParseContext,
+) -> Result<Converted> {
+    let ParseContext {
+        bibliography,
+        media,
+        shortcodes,
+        ..
+    } = parsing;
+    let mut text = String::new();
+    let mut assets = Vec::new();
+    let mut stats = PostStats::default();
+    let mut reading = Reading::default();
+    let mut in_cast = false;
+
+    let typography = Typography::for_post(config, metadata);
+    let mut options = pulldown_cmark::Options::ENABLE_FOOTNOTES;
+    if typography.smart_punctuation.unwrap_or(false) {
+        options |= pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION;
+    }
+    let content = shortcodes
+        .expand(raw)
+        .with_context(|| anyhow!("Unable to expand shortcodes in [{filepath:?}]"))?;
+    let (content, abbreviations) = process::extract_abbreviations(&content);
+    let content = citations::cite(&content, bibliography, filepath)?;
+    let parser = pulldown_cmark::Parser::new_ext(&content, options).inspect(|event| {
+        stats.record(event);
+        reading.record(event);
+        match event {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(
+                pulldown_cmark::CodeBlockKind::Fenced(info),
+            )) => in_cast = process::code_block_info(info).0 == "asciinema",
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::CodeBlock) => in_cast = false,
+            pulldown_cmark::Event::Text(t) if in_cast => assets.push(t.trim().to_string()),
+            pulldown_cmark::Event::Text(t) => {
+                text.push_str(t);
+                text.push(' ')
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Image { dest_url, .. }) => {
+                assets.push(dest_url.to_string())
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link {
+                link_type,
+                dest_url,
+                ..
+            }) if *link_type != pulldown_cmark::LinkType::Email
+                && assets::is_file_link(dest_url) =>
+            {
+                assets.push(dest_url.to_string())
+            }
+            _ => {}
+        }
+    });
+    let page = process::Page {
+        path: &location.path,
+        config,
+        typography,
+        abbreviations,
+        media,
+    };
+    let (events, toc) = process::heading_ids(parser.collect());
+    let events = process::process(events, &page);
+    let summary_html = process::summary_end(&events).map(|end| {
+        let mut summary = String::new();
+        pulldown_cmark::html::push_html(&mut summary, events[..end].iter().cloned());
+        summary
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    Ok(Converted {
+        html,
+        summary_html,
+        text: Some(text),
+        assets,
+        stats,
+        reading,
+        toc,
     })
 }
 
-fn render_content<S>(
+/// A post outside the root of a subtree build, its body only saying so
+fn stub_post(
+    metadata: Metadata,
+    front_matter: &str,
+    modified: chrono::DateTime<chrono::Utc>,
+    root: &Path,
+    config: &Config,
+) -> Post {
+    let root = root.strip_prefix(&config.input).unwrap_or(root);
+    let html = format!(
+        "<p>{} is outside of {}, the only part of the site this build converts.</p>",
+        feed::escape_xml(&metadata.title),
+        feed::escape_xml(&root.display().to_string())
+    );
+    Post {
+        metadata,
+        front_matter: front_matter.trim_matches('\n').to_owned(),
+        modified,
+        raw: String::new(),
+        html,
+        summary_html: None,
+        text: None,
+        assets: Vec::new(),
+        stats: PostStats::default(),
+        reading: Reading::default(),
+        toc: Vec::new(),
+        enclosure: None,
+        transcript: Vec::new(),
+        chapters: Vec::new(),
+    }
+}
+
+/// Context of the page of a post
+fn post_context(content: &Content, build: &BuildInfo, config: &Config) -> minijinja::Value {
+    let page = PageUrl::new(&content.location.href, config);
+    context!(data => content, page => page, build => build, ..context!(config))
+}
+
+/// Context shared by the templates of `render`, each gets its own `page` too
+fn pages_context(
+    content: &AvailableContent,
+    build: &BuildInfo,
+    changelog: &[ChangelogEntry],
+    config: &Config,
+) -> minijinja::Value {
+    context!(data => content, build => build, changelog => changelog, ..context!(config))
+}
+
+fn render_content(
     content: &Content,
     templates: Arc<Environment>,
     config: &Config,
-    data: S,
-) -> Result<()>
-where
-    S: Serialize,
-{
+    build: &BuildInfo,
+    output: &Output,
+) -> Result<Vec<PathBuf>> {
+    let mut extracted = Vec::new();
     if !content.bare {
-        if let Some(folder) = &content.location.dst.parent() {
-            std::fs::create_dir_all(folder).context("Unable to create post output directory")?;
-        }
-
         // Render the template
-        let context = context!(data => data, ..context!(config));
+        let started = Instant::now();
+        let context = post_context(content, build, config);
 
-        let rendered = templates
+        let mut rendered = templates
             .get_template(&content.post.metadata.template)?
             .render(&context)
             .with_context(|| {
@@ -99,175 +480,676 @@ where
                     content.post.metadata.name
                 )
             })?;
+        if config.stamp {
+            rendered.push_str(&build.stamp());
+        }
+        let rendered = data_uri::extract(rendered, config, output, &mut extracted)?;
+        tracing::debug!(
+            template = content.post.metadata.template,
+            dst = %content.location.dst.display(),
+            elapsed = ?started.elapsed(),
+            "Rendered post"
+        );
 
-        let mut writer =
-            BufWriter::new(File::create(&content.location.dst).with_context(|| {
-                anyhow!(
-                    "Unable to create a file  for a post: [{:?}]",
-                    content.post.metadata.name
-                )
-            })?);
-        writer.write_all(rendered.as_bytes()).with_context(|| {
-            anyhow!(
-                "Unable to write post file into output destination [{}]",
-                content.location.dst.to_string_lossy()
-            )
-        })?;
+        output.add(content.location.dst.clone(), rendered)?;
     }
 
-    Ok(())
+    Ok(extracted)
 }
 
 fn render_contents(
     content: &[Content],
     templates: Arc<Environment>,
     config: &Config,
+    build: &BuildInfo,
+    output: &Output,
+    record: Option<&BuildRecord>,
+    written: &Written,
 ) -> Result<()> {
     content
         .iter()
         .par_bridge()
         .filter(|content| content.publish)
-        .map(|content| render_content(content, templates.clone(), config, content))
+        .map(|content| {
+            config.cancel.check()?;
+            let Some(record) = record else {
+                return render_content(content, templates.clone(), config, build, output).map(drop);
+            };
+            match record.check(content)? {
+                Freshness::Fresh(extracted) => {
+                    tracing::debug!(dst = %content.location.dst.display(), "Unchanged since the last build");
+                    // The files taken out of the page are still its own
+                    for file in extracted {
+                        written.add(file)?;
+                    }
+                    written.add(content.location.dst.clone())
+                }
+                Freshness::Stale(hash) => {
+                    let extracted = render_content(content, templates.clone(), config, build, output)?;
+                    record.rendered(content, hash, extracted)
+                }
+            }
+        })
         .collect::<Result<()>>()
 }
 
-fn render(content: &AvailableContent, config: &Config) -> Result<()> {
-    let templates = Arc::new({
-        let mut env = Environment::new();
-        let template_dir = &config.input.join("templates");
-        env.set_loader(minijinja::path_loader(template_dir));
-        env
-    });
-
-    // Cleanup output directory before rendering
-    if config.output.exists() {
-        std::fs::remove_dir_all(&config.output)
-            .context("Unable to remove completely the output directory")?;
-    }
+fn render(content: &AvailableContent, config: &Config, written: &Written) -> Result<()> {
+    let build = BuildInfo::collect(config);
+    let templates = Arc::new(templates::environment(
+        config,
+        &build,
+        &content.media,
+        &content.plugins,
+        &content.scripts,
+    )?);
+    let output = Output::default();
+    let record = BuildRecord::load(config)?;
 
     // Create Posts directory
     std::fs::create_dir_all(&config.output).context("Unable to create post output directory")?;
 
     // Render posts
-    render_contents(&content.content, templates.clone(), config)?;
+    render_contents(
+        &content.content,
+        templates.clone(),
+        config,
+        &build,
+        &output,
+        record.as_ref(),
+        written,
+    )?;
+
+    let changelog = match &config.changelog {
+        Some(settings) => {
+            let changelog = changelog::collect(content, config, settings.limit);
+            if settings.feed {
+                changelog::write_feed(&changelog, config, &output)?;
+            }
+            changelog
+        }
+        None => Vec::new(),
+    };
 
     // Context for rendering supplamentary pages
-    let context = context!(data => content, ..context!(config));
-
-    for template in config.render.iter() {
-        // Render index
-        let rendered = templates.get_template(template)?.render(&context)?;
-        let out_filepath = config.output.join(template);
-        let mut writer = BufWriter::new(
-            File::create(&out_filepath)
-                .context(format!("Unable to create a file for [{template}]"))?,
-        );
-        writer.write_all(rendered.as_bytes()).context(format!(
-            "Failed to write the rendered template [{template}]"
-        ))?;
+    let context = pages_context(content, &build, &changelog, config);
+
+    for entry in config.render.iter() {
+        let template = entry.template();
+        let started = Instant::now();
+        let page = entry.page(config)?;
+        let mut rendered = templates
+            .get_template(template)?
+            .render(context!(page => page, ..context.clone()))
+            .with_context(|| anyhow!("Failed to render the template [{template}]"))?;
+        if config.stamp && template.ends_with(".html") {
+            rendered.push_str(&build.stamp());
+        }
+        tracing::debug!(template, elapsed = ?started.elapsed(), "Rendered template");
+        output.add(config.output.join(template), rendered)?;
     }
 
-    if config.search {
-        // Create searchable index
-        write_search_index(content, config)?;
+    icons::write_sprite(config, &output)?;
+    content.media.write_manifest(config, &output)?;
+    feed::write(content, config, &output)?;
+    sitemap::write(content, config, &output)?;
+    podcast::write(content, config, &output)?;
+    redirects::write(content, config, &output)?;
+
+    sections::render(content, &templates, config, &build, &output)?;
+
+    if config.calendar {
+        calendar::render(content, &templates, config, &build, &output)?;
     }
 
-    Ok(())
+    search::write(content, config, &output)?;
+
+    output.write(config.fsync, written)?;
+    match record {
+        Some(record) => record.finish(),
+        None => Ok(()),
+    }
 }
 
-fn write_search_index(contents: &AvailableContent, config: &Config) -> Result<()> {
-    let output_path = config.output.join("search-index.json");
-    let writer = BufWriter::new(File::create(&output_path).context(format!(
-        "Unable to create a file for the search index: [{}]",
-        output_path.display()
-    ))?);
-    let docs = contents
-        .content
-        .par_iter()
-        .filter(|content| content.post.metadata.publish)
-        .map(TryFrom::try_from)
-        .collect::<Result<Vec<SearchableDoc>>>()?;
+/// Whether a content file holds the homepage
+fn is_home(path: &Path) -> bool {
+    decrypt::plain_path(path)
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .is_some_and(|stem| HOME_FILES.contains(&stem))
+}
+
+/// Whether a content file holds the front matter defaults
+fn is_defaults(path: &Path) -> bool {
+    path.file_stem().is_some_and(|stem| stem == DEFAULTS_FILE)
+        && path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|extension| data::DATA_EXTENSIONS.contains(&extension))
+}
+
+/// Front matter every post starts from, `content/_defaults.yaml` or the same
+/// in another data format. Posts replace whole keys, nested values aren't merged
+fn front_matter_defaults(content_dir: &Path) -> Result<HashMap<String, serde_json::Value>> {
+    let Some(path) = data::DATA_EXTENSIONS
+        .iter()
+        .map(|extension| content_dir.join(format!("{DEFAULTS_FILE}.{extension}")))
+        .find(|path| path.is_file())
+    else {
+        return Ok(HashMap::new());
+    };
+    match data::load(&path)? {
+        serde_json::Value::Object(defaults) => Ok(defaults.into_iter().collect()),
+        serde_json::Value::Null => Ok(HashMap::new()),
+        _ => bail!("Front matter defaults [{path:?}] are not a mapping"),
+    }
+}
 
-    serde_json::to_writer(writer, &docs)?;
-    Ok(())
+/// Parse the homepage content file, if the site has one
+fn collect_home(
+    content_dir: &Path,
+    config: &Config,
+    parsing: ParseContext,
+) -> Result<Option<Content>> {
+    let mut homes: Vec<PathBuf> = read_dir(content_dir)
+        .context("Unable to read content directory")?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_home(path))
+        .collect();
+    homes.sort();
+    let filepath = match homes.as_slice() {
+        [] => return Ok(None),
+        [home] => home.clone(),
+        _ => bail!("Found more than one homepage content file: {homes:?}"),
+    };
+
+    let locate = |_: &Metadata| Ok(LocationData::for_home(filepath.clone(), config));
+    let (location, post) = try_parse_post(
+        filepath.clone(),
+        None,
+        &HashMap::new(),
+        locate,
+        config,
+        parsing,
+    )?;
+    Ok(Some(Content {
+        location,
+        publish: true,
+        draft: false,
+        section: None,
+        bare: true,
+        post,
+    }))
+}
+
+/// Content files under `dir` with their section, the `/` separated path of
+/// their directory below `content/`. The homepage and hidden entries are left out.
+fn content_files(dir: &Path, section: Option<&str>) -> Result<Vec<(PathBuf, Option<String>)>> {
+    let mut files = Vec::new();
+    for entry in
+        read_dir(dir).with_context(|| anyhow!("Unable to read content directory [{dir:?}]"))?
+    {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            let nested = match section {
+                Some(section) => format!("{section}/{name}"),
+                None => name.to_owned(),
+            };
+            files.extend(content_files(&path, Some(&nested))?);
+        } else if path.is_file() && !(section.is_none() && (is_home(&path) || is_defaults(&path))) {
+            files.push((path, section.map(str::to_owned)));
+        }
+    }
+    Ok(files)
 }
 
 fn collect_content(config: &Config) -> Result<AvailableContent> {
+    let bibliography = Bibliography::load(config)?;
+    let media = MediaStore::collect(config)?;
     let content_dir = config.input.join("content");
-    read_dir(content_dir)
-        .context("Unable to read content directory")?
-        .par_bridge()
-        .filter_map(|entry| {
-            entry.ok().and_then(|entry| {
-                let path = entry.path();
-                if path.is_file() {
-                    return Some(path);
+    if let Some(root) = config.root.as_ref().filter(|root| !root.is_dir()) {
+        bail!("Unable to build only [{root:?}], it is not a directory");
+    }
+    let plugins = Plugins::load(config)?;
+    let scripts = Scripts::load(config)?;
+    let shortcodes = Shortcodes::load(config, &media, &plugins, &scripts)?;
+    let collation = Collation::new(config.collation.as_deref())?;
+    let conversions = Conversions::load(config)?;
+    let parsing = ParseContext {
+        bibliography: &bibliography,
+        conversions: conversions.as_ref(),
+        media: &media,
+        plugins: &plugins,
+        scripts: &scripts,
+        shortcodes: &shortcodes,
+    };
+    let home = collect_home(&content_dir, config, parsing);
+    let defaults = front_matter_defaults(&content_dir)?;
+    let made_up = config
+        .build_hooks
+        .content(config)
+        .context("Build hooks failed to make up content")?
+        .into_iter()
+        .map(|(relative, text)| {
+            let section = relative
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .map(|parent| parent.to_string_lossy().replace('\\', "/"));
+            (content_dir.join(relative), section, Some(text))
+        });
+    let files = content_files(&content_dir, None)?
+        .into_iter()
+        .map(|(filepath, section)| (filepath, section, None));
+    let parsed: Vec<(PathBuf, Result<Content>)> = files
+        .chain(made_up)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|(filepath, section, text)| {
+            let locate = |metadata: &Metadata| {
+                LocationData::for_post(filepath.clone(), section.as_deref(), metadata, config)
+            };
+            // The template of the section comes before the site-wide defaults
+            let template = section
+                .as_ref()
+                .and_then(|section| config.sections.get(section))
+                .and_then(|settings| settings.template.as_ref());
+            let defaults = match template {
+                Some(template) => {
+                    let mut defaults = defaults.clone();
+                    defaults.insert(String::from("template"), template.clone().into());
+                    Cow::Owned(defaults)
                 }
-                None
-            })
-        })
-        .map(|filepath| -> Result<Content> {
-            try_parse_post(filepath.clone()).and_then(|post| {
-                let publish = post.metadata.publish;
+                None => Cow::Borrowed(&defaults),
+            };
+            let parsed = try_parse_post(filepath.clone(), text, &defaults, locate, config, parsing);
+            let content = parsed.map(|(location, post)| {
+                let published = post.metadata.publish
+                    && !(config.hold_future_posts && post.metadata.is_future());
+                let publish = published || config.drafts;
+                let draft = !published && config.drafts;
                 let bare = post.metadata.bare;
-                Ok(Content {
-                    location: LocationData::for_post(filepath, config)?,
+                Content {
+                    location,
                     publish,
+                    draft,
+                    section,
                     bare,
                     post,
-                })
-            })
+                }
+            });
+            (filepath, content)
         })
-        .try_fold(
-            AvailableContent::default,
-            |mut acc, content| -> Result<AvailableContent> {
-                acc.content.push(content?);
-                Ok(acc)
-            },
-        )
-        .try_reduce(
-            AvailableContent::default,
-            |mut a, mut b| -> Result<AvailableContent> {
-                a.content.append(&mut b.content);
-                Ok(a)
-            },
-        )
+        .collect();
+    config.cancel.check()?;
+
+    // Every broken file is reported at once, so they can all be fixed in one go
+    let mut content = AvailableContent::default();
+    let mut failures = Vec::new();
+    let home = home.unwrap_or_else(|e| {
+        failures.push(format!("  {e:#}"));
+        None
+    });
+    for (filepath, parsed) in parsed {
+        match parsed {
+            Ok(parsed) => content.content.push(parsed),
+            Err(e) => failures.push(format!("  [{}] {e:#}", filepath.display())),
+        }
+    }
+    if !failures.is_empty() {
+        failures.sort();
+        bail!(
+            "Unable to parse {} content file(s):\n{}",
+            failures.len(),
+            failures.join("\n")
+        );
+    }
+    content.content.sort_by(|a, b| {
+        let (a, b) = (&a.post.metadata, &b.post.metadata);
+        b.date
+            .cmp(&a.date)
+            .then_with(|| collation.compare(&a.name, &b.name))
+    });
+    let mut pages: HashMap<&Path, &Path> = HashMap::new();
+    for content in &content.content {
+        let location = &content.location;
+        if let Some(other) = pages.insert(&location.path, &location.src) {
+            bail!(
+                "Posts [{other:?}] and [{:?}] are both written to [{:?}]",
+                location.src,
+                location.path
+            );
+        }
+    }
+    content.sections = sections::collect(&content.content, config, &collation);
+    content.home = home;
+    content.media = media;
+    content.plugins = plugins;
+    content.scripts = scripts;
+    Ok(content)
 }
 
-fn include_extras(config: Config) -> Result<()> {
-    let include_dir = config.input.join("include");
-    if include_dir.exists() {
+fn include_extras(
+    config: &Config,
+    media: &MediaStore,
+    cache: &VariantCache,
+    written: &Written,
+) -> Result<()> {
+    let include_dirs = config.layered_dirs("include");
+    for (layer, include_dir) in include_dirs.iter().enumerate() {
+        // Glob drops a leading `./` from the paths it yields
+        let include_prefix = include_dir.strip_prefix(".").unwrap_or(include_dir);
+        if !include_dir.exists() {
+            continue;
+        }
         if let Some(include_dir_str) = include_dir.to_str() {
             let pattern = format!("{include_dir_str}/**/*");
+            // Directories, symlinked ones included, come out of the glob too and
+            // are recreated by the files in them
             glob(&pattern)
                 .with_context(|| anyhow!("Unable to glob include directory: [{pattern}]"))?
                 .par_bridge()
                 .filter_map(Result::ok)
+                .filter(|src| !src.is_dir())
                 .map(|src| -> Result<()> {
-                    let file = src.strip_prefix("include").with_context(|| {
+                    config.cancel.check()?;
+                    let file = src.strip_prefix(include_prefix).with_context(|| {
                         anyhow!("Unable to strip the prefix [{include_dir:?}] from a glob pattern: [{src:?}]")
                     })?;
+                    // Files of the site hide the theme's at the same path
+                    let overridden =
+                        include_dirs[..layer].iter().any(|dir| dir.join(file).exists());
+                    if media.contains(file) || overridden || transform::is_excluded(file, config)? {
+                        return Ok(());
+                    }
+                    if !src.is_file() {
+                        bail!("Include file [{src:?}] is not a regular file or a symlink to one");
+                    }
                     let dst = config.output.join(file);
+                    if let Some(parent) = dst.parent() {
+                        std::fs::create_dir_all(parent).with_context(|| {
+                            anyhow!("Unable to create output directory [{parent:?}]")
+                        })?;
+                    }
 
-                    std::fs::copy(&src, &dst).with_context(|| {
-                        anyhow!(
-                            "Unable to copy include file [{src:?}] into output directory as [{dst:?}]"
-                        )
-                    })?;
-                    Ok(())
+                    transform::install(&src, file, &dst, config, cache)?;
+                    written.add(dst)
                 })
                 .collect::<Result<()>>()?;
         };
     }
-    Ok(())
+    media.copy(config, cache, written)
 }
 
-pub fn generate(config: Config) -> Result<()> {
-    let content = collect_content(&config)?;
+/// Post found by its `name` or source file name
+pub(crate) fn find_post<'a>(content: &'a AvailableContent, post: &str) -> Result<&'a Content> {
+    content
+        .content
+        .iter()
+        .find(|content| {
+            content.post.metadata.name == post
+                || content
+                    .location
+                    .src
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy() == post)
+        })
+        .with_context(|| anyhow!("No post named [{post}]"))
+}
 
-    // Render
-    render(&content, &config)?;
+/// Context a page is rendered with, as JSON. `page` is a post, by its `name` or
+/// source file name, or a template of `render`. Without it, the context all
+/// `render` templates share.
+pub fn dump_context(config: &Config, page: Option<&str>) -> Result<serde_json::Value> {
+    let content = collect_content(config)?;
+    let build = BuildInfo::collect(config);
+    let changelog = match &config.changelog {
+        Some(settings) => changelog::collect(&content, config, settings.limit),
+        None => Vec::new(),
+    };
+    let context = pages_context(&content, &build, &changelog, config);
 
-    // Include extras
-    include_extras(config)
+    let context = match page {
+        None => context,
+        Some(page) => match config.render.iter().find(|entry| entry.template() == page) {
+            Some(entry) => context!(page => entry.page(config)?, ..context),
+            None => post_context(find_post(&content, page)?, &build, config),
+        },
+    };
+    Ok(serde_json::to_value(&context)?)
+}
+
+/// Remove the output directory, refusing to when the input lives inside it
+pub fn clean(config: &Config) -> Result<()> {
+    if !config.output.exists() {
+        return Ok(());
+    }
+    let output = config
+        .output
+        .canonicalize()
+        .with_context(|| anyhow!("Unable to resolve output directory [{:?}]", config.output))?;
+    let input = config
+        .input
+        .canonicalize()
+        .with_context(|| anyhow!("Unable to resolve input directory [{:?}]", config.input))?;
+    if input.starts_with(&output) {
+        bail!("Refusing to remove output directory [{output:?}], it holds the input [{input:?}]");
+    }
+    std::fs::remove_dir_all(&output)
+        .with_context(|| anyhow!("Unable to remove output directory [{output:?}]"))
+}
+
+/// Start an unpublished post dated today, returning the path of its file
+///
+/// The post is rendered from the archetype `archetypes/<archetype>.md` of the
+/// site or its theme, given the `name`, `title` and `date` of the post. Without
+/// an archetype `archetypes/default.md` is used when there is one. The title
+/// is given as typed, `{{ title | tojson }}` quotes it for the front matter.
+pub fn new_post(config: &Config, title: &str, archetype: Option<&str>) -> Result<PathBuf> {
+    let name = filters::slugify(title);
+    if name.is_empty() {
+        bail!("Unable to make a post name out of the title [{title}]");
+    }
+    let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+
+    let find = |archetype: &str| {
+        config
+            .layered_dirs("archetypes")
+            .into_iter()
+            .map(|dir| dir.join(format!("{archetype}.md")))
+            .find(|path| path.is_file())
+    };
+    let template = match archetype {
+        Some(archetype) => Some(
+            find(archetype).with_context(|| anyhow!("No archetype [{archetype}] to start from"))?,
+        ),
+        None => find("default"),
+    };
+    let post = match template {
+        Some(template) => {
+            let source = std::fs::read_to_string(&template)
+                .with_context(|| anyhow!("Unable to read archetype [{template:?}]"))?;
+            let mut env = Environment::new();
+            env.set_keep_trailing_newline(true);
+            env.render_str(
+                &source,
+                context!(name => name, title => title, date => date),
+            )
+            .with_context(|| anyhow!("Unable to render archetype [{template:?}]"))?
+        }
+        None => format!(
+            "name: {name}\ntitle: {}\ndate: {date}\npublish: false\n---\n",
+            serde_json::to_string(title)?
+        ),
+    };
+
+    let path = config.input.join("content").join(format!("{name}.md"));
+    if path.exists() {
+        bail!("Post already exists [{path:?}]");
+    }
+    std::fs::write(&path, post).with_context(|| anyhow!("Unable to write post [{path:?}]"))?;
+    Ok(path)
+}
+
+/// Where the time of a build went, serialized in seconds
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Timings {
+    /// Reading and parsing the content
+    #[serde(serialize_with = "seconds")]
+    pub content: Duration,
+    /// Rendering and copying into every output
+    #[serde(serialize_with = "seconds")]
+    pub output: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub total: Duration,
+}
+
+fn seconds<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// What a build did, for reporting
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct BuildReport {
+    /// Published posts rendered into pages
+    pub posts: usize,
+    /// Use of the cache of include rule outputs
+    pub variants: CacheStats,
+    /// Output directories written, the site and its configured variants
+    pub outputs: usize,
+    /// Size and manifest hash of the site's own output
+    pub output: manifest::Summary,
+    pub timings: Timings,
+    /// Include rule commands that wrote to stdout or stderr, with what they wrote
+    pub commands: Vec<transform::CommandOutput>,
+}
+
+impl std::fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} posts, include variants {}",
+            self.posts, self.variants
+        )?;
+        if self.outputs > 1 {
+            write!(f, ", {} outputs", self.outputs)?;
+        }
+        if !self.commands.is_empty() {
+            write!(f, ", {} commands with output", self.commands.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse every content file of the site without rendering anything, for tools
+/// that only want to look at the posts
+pub fn collect(config: &Config) -> Result<AvailableContent, Error> {
+    collect_content(config).map_err(|e| match config.cancel.is_cancelled() {
+        true => Error::Cancelled(e),
+        false => Error::Content(e),
+    })
+}
+
+/// Build the site. Builds stopped through the `cancel` of the config fail
+/// with `Error::Cancelled`, whatever they were doing when they stopped.
+pub fn generate(config: Config) -> Result<BuildReport, Error> {
+    let cancel = config.cancel.clone();
+    build_site(config).map_err(|e| match cancel.is_cancelled() {
+        true => Error::Cancelled(e.into_inner()),
+        false => e,
+    })
+}
+
+fn build_site(config: Config) -> Result<BuildReport, Error> {
+    let started = Instant::now();
+    tracing::info!(input = %config.input.display(), output = %config.output.display(), "Building");
+    config.build_hooks.pre_build(&config).map_err(Error::Hook)?;
+    let mut content = collect_content(&config).map_err(Error::Content)?;
+
+    // Make sure everything posts point at will be there
+    assets::check_assets(&content, &config).map_err(Error::Content)?;
+    assets::check_alt_text(&content, &config).map_err(Error::Content)?;
+    config.cancel.check().map_err(Error::Cancelled)?;
+
+    let parsed = Instant::now();
+    tracing::info!(
+        posts = content.content.len(),
+        elapsed = ?(parsed - started),
+        "Parsed content"
+    );
+    let cache = VariantCache::new(&config);
+    let output = write_output(&content, &config, &cache).map_err(Error::Output)?;
+
+    let mut variants: Vec<_> = config.variants.iter().collect();
+    variants.sort_by_key(|(name, _)| *name);
+    for (name, variant) in &variants {
+        let variant = config.variant(name, variant).map_err(Error::Config)?;
+        // Posts link with the path the site is served under, so only variants
+        // served under the same one can share the parsed content
+        config.cancel.check().map_err(Error::Cancelled)?;
+        tracing::info!(variant = name, output = %variant.output.display(), "Building variant");
+        if variant.path_prefix() == config.path_prefix() {
+            relocate(&mut content, &variant);
+        } else {
+            content = collect_content(&variant).map_err(Error::Content)?;
+        }
+        write_output(&content, &variant, &cache).map_err(Error::Output)?;
+    }
+
+    cache::collect_garbage(&config).map_err(Error::Output)?;
+
+    let finished = Instant::now();
+    tracing::info!(elapsed = ?(finished - parsed), "Wrote output");
+    let report = BuildReport {
+        posts: content
+            .content
+            .iter()
+            .filter(|content| content.publish && !content.bare)
+            .count(),
+        variants: cache.stats(),
+        commands: cache.command_outputs(),
+        outputs: variants.len() + 1,
+        output,
+        timings: Timings {
+            content: parsed - started,
+            output: finished - parsed,
+            total: finished - started,
+        },
+    };
+    config
+        .build_hooks
+        .post_build(&config, &report)
+        .map_err(Error::Hook)?;
+    Ok(report)
+}
+
+/// Render the content and copy the include files into the output of `config`
+fn write_output(
+    content: &AvailableContent,
+    config: &Config,
+    cache: &VariantCache,
+) -> Result<manifest::Summary> {
+    // Render, then copy the extras over what the last build left
+    let written = Written::default();
+    render(content, config, &written)?;
+    include_extras(config, &content.media, cache, &written)?;
+    written.remove_stale(config)?;
+
+    listing::write(config, &written)?;
+    search::pagefind(config)?;
+
+    manifest::write(config)
+}
+
+/// Point parsed content at the output and origin of another config
+fn relocate(content: &mut AvailableContent, config: &Config) {
+    for content in content.content.iter_mut().chain(content.home.as_mut()) {
+        let location = &mut content.location;
+        location.dst = config.output.join(&location.path);
+        location.canonical = config.absolute_url(&location.href);
+    }
 }
@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use pulldown_cmark_escape::{escape_href, escape_html};
+
+use crate::{
+    config::Config,
+    output::{Output, Written},
+};
+
+/// File written into every listed directory
+const LISTING: &str = "index.html";
+
+/// A file or directory shown in a listing
+struct Entry {
+    name: String,
+    dir: bool,
+    size: u64,
+}
+
+/// File size the way directory listings usually show it
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if size < 1024 {
+        return format!("{size}");
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1}{}", UNITS[unit])
+}
+
+/// Directories first, then files, each sorted by name
+fn entries(dir: &Path) -> Result<Vec<Entry>> {
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| anyhow!("Unable to read listed directory [{dir:?}]"))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some(Entry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                dir: metadata.is_dir(),
+                size: metadata.len(),
+            })
+        })
+        .filter(|entry| entry.name != LISTING)
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.dir.cmp(&a.dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+/// The listing page of a directory served at `url`
+fn page(url: &str, entries: &[Entry], parent: bool) -> String {
+    let escape = |text: &str| {
+        let mut escaped = String::new();
+        let _ = escape_html(&mut escaped, text);
+        escaped
+    };
+    let mut rows = String::new();
+    if parent {
+        rows.push_str("<tr><td><a href=\"../\">../</a></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let name = match entry.dir {
+            true => format!("{}/", entry.name),
+            false => entry.name.clone(),
+        };
+        let mut href = String::new();
+        let _ = escape_href(&mut href, &urlencoding::encode(&entry.name));
+        if entry.dir {
+            href.push('/');
+        }
+        let size = match entry.dir {
+            true => String::from("-"),
+            false => human_size(entry.size),
+        };
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td>{size}</td></tr>\n",
+            escape(&href),
+            escape(&name)
+        ));
+    }
+    format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>Index of {url}</title></head>\
+         <body><h1>Index of {url}</h1><table>\n{rows}</table></body></html>\n",
+        url = escape(url),
+    )
+}
+
+/// Queue a listing for `dir` and every directory below it, refusing to
+/// replace a page the build wrote there
+fn queue_tree(
+    dir: &Path,
+    url: &str,
+    parent: bool,
+    output: &Output,
+    written: &Written,
+) -> Result<()> {
+    let entries = entries(dir)?;
+    let listing = dir.join(LISTING);
+    if written.contains(&listing)? {
+        bail!("Directory listing [{listing:?}] would replace a page of the site");
+    }
+    output.add(listing, page(url, &entries, parent))?;
+    for entry in entries.iter().filter(|entry| entry.dir) {
+        queue_tree(
+            &dir.join(&entry.name),
+            &format!("{url}{}/", entry.name),
+            true,
+            output,
+            written,
+        )?;
+    }
+    Ok(())
+}
+
+/// Generate browsable file indexes for the configured output directories, once
+/// everything has been copied into them and the stale files removed
+pub(crate) fn write(config: &Config, written: &Written) -> Result<()> {
+    let output = Output::default();
+    for dir in &config.listings {
+        let relative = dir.strip_prefix("/").unwrap_or(dir);
+        let path: PathBuf = config.output.join(relative);
+        if !path.is_dir() {
+            continue;
+        }
+        let url = config.site_url(&format!("{}/", relative.to_string_lossy()));
+        queue_tree(&path, &url, false, &output, written)
+            .with_context(|| anyhow!("Unable to list [{dir:?}]"))?;
+    }
+    output.write(config.fsync, written)
+}
@@ -1,23 +1,384 @@
-use std::{env::args, path::PathBuf, process::exit};
+use std::{
+    io::IsTerminal,
+    path::PathBuf,
+    process::exit,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
-use mub::config::Config;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use mub::{
+    cancel::Cancel,
+    config::{Config, Overrides},
+};
+
+#[derive(Parser)]
+#[command(
+    name = "mub",
+    version,
+    about = "A small opinionated static site generator"
+)]
+struct Cli {
+    /// Config file of the site
+    #[arg(long, short, global = true, default_value = "config.json")]
+    config: PathBuf,
+    /// Generate into this directory instead of the configured `output`
+    #[arg(long, short, global = true)]
+    output: Option<PathBuf>,
+    /// Build unpublished posts too, flagged as drafts
+    #[arg(long, global = true)]
+    drafts: bool,
+    /// Only convert the posts under this directory of the input, as
+    /// `content/notes`, with stub pages for the rest
+    #[arg(long, global = true)]
+    root: Option<PathBuf>,
+    /// Report what is being done and how long it took, repeat for every post,
+    /// template and file
+    #[arg(long, short, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Only report errors
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Write the logs as text or as JSON lines
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Config file, for the original `mub config.json` form of building
+    #[arg(hide = true)]
+    legacy_config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate the site, the default without a command
+    Build {
+        /// Also pack the output into a reproducible `.tar.gz`
+        #[arg(long)]
+        archive: Option<PathBuf>,
+        /// Write what the build did, its timings and output size as JSON
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Give up on the build after this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+    },
+    /// Serve the output and rebuild whenever the input changes
+    Serve {
+        #[arg(long, default_value_t = mub::serve::DEFAULT_PORT)]
+        port: u16,
+        /// Leave pages as they are instead of reloading them after rebuilds
+        #[arg(long)]
+        no_reload: bool,
+        /// Serve a build with the unpublished posts too on this address, as
+        /// `0.0.0.0:8001`, to those logging in with the `user:password` in
+        /// `MUB_DRAFTS_CREDENTIALS`
+        #[arg(long, value_name = "ADDRESS")]
+        share_drafts: Option<std::net::SocketAddr>,
+    },
+    /// Rebuild on the configured schedule and rebuild hook
+    Daemon,
+    /// Start an unpublished post dated today
+    New {
+        /// Title of the post, its name is made from it
+        title: String,
+        /// Archetype in `archetypes/` to start from, `default` when there is one
+        #[arg(short, long)]
+        archetype: Option<String>,
+    },
+    /// Remove the output directory
+    Clean,
+    /// Check content for broken links, missing alt text and the like
+    Check {
+        /// Also audit titles, descriptions and social cards
+        #[arg(long)]
+        seo: bool,
+        /// List published posts not updated for a while
+        #[arg(long)]
+        stale: bool,
+        /// How long a post goes without updates before it is stale, e.g. `90d`, `6m` or `2y`
+        #[arg(long, default_value = "1y", value_parser = mub::check::parse_age, requires = "stale")]
+        older_than: chrono::TimeDelta,
+    },
+    /// Compare the output, or a deployed copy of it, against the build manifest
+    Verify {
+        /// Directory to verify instead of the output
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Base64 ed25519 public key the manifest has to be signed with
+        #[arg(long)]
+        key: Option<String>,
+    },
+    /// Copy the changes of the output since the last deploy into a directory,
+    /// printing the plan and asking before carrying it out
+    Deploy {
+        /// Local directory the site is deployed to, or synced to remote storage
+        /// from. Remote storage isn't listed or uploaded to directly.
+        target: PathBuf,
+        /// Manifest of what is deployed, as downloaded from the site, instead
+        /// of the one in the target
+        #[arg(long)]
+        remote: Option<PathBuf>,
+        /// Deploy without asking
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Show how a post unfurls when its link is shared
+    PreviewCard {
+        /// Name or source file name of the post
+        post: String,
+        /// Write an HTML mock up of the card and print its path
+        #[arg(long)]
+        html: bool,
+    },
+    /// Look into what templates get to render
+    Context {
+        #[command(subcommand)]
+        command: ContextCommand,
+    },
+    /// Work with the cache kept between builds
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Work with the config file itself
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ContextCommand {
+    /// Print the JSON context of a page
+    Dump {
+        /// Name or source file name of a post, or a template of `render`. The
+        /// context every `render` template shares when not given
+        page: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove the whole cache
+    Clean,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Validate the config file against the schema
+    Check,
+    /// Print the JSON Schema of the config file
+    Schema,
+}
+
+impl Cli {
+    fn overrides(&self) -> Overrides {
+        Overrides {
+            output: self.output.clone(),
+            drafts: self.drafts,
+            root: self.root.clone(),
+        }
+    }
+}
+
+fn load_config(cli: &Cli) -> Config {
+    let path = cli.legacy_config.as_ref().unwrap_or(&cli.config);
+    match Config::try_load(path) {
+        Ok(config) => {
+            tracing::info!(path = %path.display(), "Loaded config");
+            config.with_overrides(&cli.overrides())
+        }
+        Err(e) => {
+            eprintln!("Unable to load config [{path:?}]");
+            eprintln!("{e:#}");
+            exit(1);
+        }
+    }
+}
+
+fn build(
+    cli: &Cli,
+    archive: Option<&PathBuf>,
+    report_path: Option<&PathBuf>,
+    timeout: Option<u64>,
+) -> Result<()> {
+    let mut config = load_config(cli);
+    if let Some(seconds) = timeout {
+        config = config.with_cancel(Cancel::with_timeout(Duration::from_secs(seconds)));
+    }
+    let output = config.output().to_path_buf();
+    let started = Instant::now();
+    let report = mub::generate(config)?;
+    tracing::info!(
+        "Built [{}] in {:.2?}: {report}",
+        output.display(),
+        started.elapsed()
+    );
+    if let Some(path) = report_path {
+        std::fs::write(path, serde_json::to_vec_pretty(&report)?)
+            .with_context(|| anyhow!("Unable to write build report [{path:?}]"))?;
+    }
+    if let Some(archive) = archive {
+        mub::archive::write(&output, archive)?;
+        tracing::info!("Archived [{}]", archive.display());
+    }
+    Ok(())
+}
+
+/// Send the logs to stderr, warnings and errors only unless asked for more.
+/// Servers report their address and builds too, unless told to be quiet.
+fn init_logging(cli: &Cli) {
+    let serving = matches!(cli.command, Some(Command::Serve { .. } | Command::Daemon));
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => tracing::Level::ERROR,
+        (false, 0) if serving => tracing::Level::INFO,
+        (false, 0) => tracing::Level::WARN,
+        (false, 1) => tracing::Level::INFO,
+        (false, 2) => tracing::Level::DEBUG,
+        (false, _) => tracing::Level::TRACE,
+    };
+    let logs = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr);
+    match cli.log_format {
+        LogFormat::Text => logs.with_ansi(std::io::stderr().is_terminal()).init(),
+        LogFormat::Json => logs.json().init(),
+    }
+}
+
+/// Ask a yes or no question on the terminal, `--yes` has to answer it otherwise
+fn confirm(question: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        bail!("Not asking on a terminal, pass --yes to go ahead");
+    }
+    eprint!("{question} [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
 
 fn main() -> Result<()> {
-    let args: Vec<String> = args().collect();
-    if args.len() != 2 {
-        println!("Usage: mub config.json");
-        exit(1);
-    }
-    let config_path: PathBuf = args[1].parse().unwrap_or_else(|e| {
-        eprintln!("Unable to parse config path: {path}", path = args[1]);
-        eprintln!("{e}");
-        exit(1);
-    });
-    let config = Config::try_load(&config_path).unwrap_or_else(|e| {
-        eprintln!("Unable to load config [{config_path:?}]");
-        eprintln!("{e}");
-        exit(1);
-    });
-    mub::generate(config)
+    let cli = Cli::parse();
+    init_logging(&cli);
+    match &cli.command {
+        None => build(&cli, None, None, None),
+        Some(Command::Build {
+            archive,
+            report,
+            timeout,
+        }) => build(&cli, archive.as_ref(), report.as_ref(), *timeout),
+        Some(Command::Serve {
+            port,
+            no_reload,
+            share_drafts,
+        }) => mub::serve::serve(
+            &cli.config,
+            &cli.overrides(),
+            *port,
+            !no_reload,
+            *share_drafts,
+        ),
+        Some(Command::Daemon) => mub::daemon::daemon(&cli.config, &cli.overrides()),
+        Some(Command::New { title, archetype }) => {
+            let config = load_config(&cli);
+            let path = mub::new_post(&config, title, archetype.as_deref())?;
+            println!("{}", path.display());
+            Ok(())
+        }
+        Some(Command::Clean) => {
+            let config = load_config(&cli);
+            mub::clean(&config)?;
+            tracing::info!("Removed [{}]", config.output().display());
+            Ok(())
+        }
+        Some(Command::Check {
+            seo,
+            stale,
+            older_than,
+        }) => {
+            let config = load_config(&cli);
+            let options = mub::check::Options {
+                seo: *seo,
+                stale: stale.then_some(*older_than),
+            };
+            let report = mub::check::check(&config, &options)?;
+            print!("{report}");
+            if !report.errors().is_empty() {
+                exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Verify { dir, key }) => {
+            let config = load_config(&cli);
+            let dir = dir.clone().unwrap_or_else(|| config.output().to_path_buf());
+            let report = mub::manifest::verify(&dir, &config, key.as_deref())?;
+            print!("{report}");
+            if !report.is_clean() {
+                exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Deploy {
+            target,
+            remote,
+            yes,
+        }) => {
+            let config = load_config(&cli);
+            let plan = mub::deploy::plan(config.output(), target, remote.as_deref())?;
+            print!("{plan}");
+            if plan.is_empty() || !(*yes || confirm("Deploy?")?) {
+                return Ok(());
+            }
+            mub::deploy::apply(&plan, config.output(), target)?;
+            println!("Deployed to [{}]", target.display());
+            Ok(())
+        }
+        Some(Command::PreviewCard { post, html }) => {
+            let config = load_config(&cli);
+            let card = mub::social::preview_card(&config, post)?;
+            if *html {
+                let preview = card.preview_path();
+                std::fs::write(&preview, card.to_html())
+                    .with_context(|| anyhow!("Unable to write preview [{preview:?}]"))?;
+                println!("{}", preview.display());
+            } else {
+                print!("{card}");
+            }
+            Ok(())
+        }
+        Some(Command::Context { command }) => match command {
+            ContextCommand::Dump { page } => {
+                let context = mub::dump_context(&load_config(&cli), page.as_deref())?;
+                println!("{}", serde_json::to_string_pretty(&context)?);
+                Ok(())
+            }
+        },
+        Some(Command::Cache { command }) => match command {
+            CacheCommand::Clean => mub::cache::clean(&load_config(&cli)),
+        },
+        Some(Command::Config { command }) => match command {
+            ConfigCommand::Schema => {
+                println!("{}", serde_json::to_string_pretty(&Config::schema())?);
+                Ok(())
+            }
+            ConfigCommand::Check => {
+                let problems = Config::check(&cli.config)?;
+                if problems.is_empty() {
+                    println!("Config is valid");
+                    return Ok(());
+                }
+                for problem in problems {
+                    eprintln!("{problem}");
+                }
+                exit(1);
+            }
+        },
+    }
 }
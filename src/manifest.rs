@@ -0,0 +1,278 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use glob::glob;
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, output::Preserved};
+
+/// Name of the manifest in the output directory
+pub(crate) const FILE: &str = ".mub-manifest.json";
+
+/// Name of the signature of the manifest in the output directory
+pub(crate) const SIGNATURE_FILE: &str = ".mub-manifest.sig";
+
+/// Environment variable holding the signing key, overriding `signing.key`
+const KEY_VAR: &str = "MUB_SIGNING_KEY";
+
+/// Signing of the build manifest, so copies of the output can be checked
+/// against the key of whoever built it
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SigningConfig {
+    /// File holding the base64 encoded 32 byte ed25519 secret key, relative to
+    /// the input directory. Keep it out of the include directories.
+    #[serde(default)]
+    pub(crate) key: Option<PathBuf>,
+}
+
+impl SigningConfig {
+    /// Secret key from the environment, falling back to the key file
+    fn signing_key(&self, config: &Config) -> Result<SigningKey> {
+        let encoded = match (
+            std::env::var(KEY_VAR).ok().filter(|key| !key.is_empty()),
+            &self.key,
+        ) {
+            (Some(key), _) => key,
+            (None, Some(path)) => {
+                let path = config.input.join(path);
+                std::fs::read_to_string(&path)
+                    .with_context(|| anyhow!("Unable to read signing key [{path:?}]"))?
+            }
+            (None, None) => bail!("Signing needs a key, set `signing.key` or {KEY_VAR}"),
+        };
+        let seed: [u8; 32] = STANDARD
+            .decode(encoded.trim())
+            .context("Signing key is not valid base64")?
+            .try_into()
+            .map_err(|_| anyhow!("Signing key is not 32 bytes long"))?;
+        Ok(SigningKey::from_bytes(&seed))
+    }
+
+    /// Base64 encoded public key of the secret key
+    pub(crate) fn public_key(&self, config: &Config) -> Result<String> {
+        Ok(STANDARD.encode(self.signing_key(config)?.verifying_key().as_bytes()))
+    }
+}
+
+/// Contents of the signature file
+#[derive(Serialize, Deserialize)]
+struct Signature {
+    algorithm: String,
+    public_key: String,
+    signature: String,
+}
+
+/// Hex encoded SHA-256 of `bytes`
+pub(crate) fn digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Hash of every file under `dir` by its path relative to it, the manifest
+/// itself and the preserved paths left out
+fn hash_tree(dir: &Path, preserved: &Preserved) -> Result<BTreeMap<PathBuf, String>> {
+    let pattern = format!("{}/**/*", dir.display());
+    // Glob drops a leading `./` from the paths it yields
+    let prefix = dir.strip_prefix(".").unwrap_or(dir);
+    glob(&pattern)
+        .with_context(|| anyhow!("Unable to glob output directory: [{pattern}]"))?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+        .par_bridge()
+        .map(|path| -> Result<(PathBuf, String)> {
+            let bytes = std::fs::read(&path)
+                .with_context(|| anyhow!("Unable to read output file [{path:?}]"))?;
+            Ok((path.strip_prefix(prefix)?.to_path_buf(), digest(&bytes)))
+        })
+        .filter(|entry| {
+            entry.as_ref().map_or(true, |(path, _)| {
+                path != Path::new(FILE)
+                    && path != Path::new(SIGNATURE_FILE)
+                    && !preserved.contains(path)
+            })
+        })
+        .collect()
+}
+
+/// Size and fingerprint of a built output directory
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Summary {
+    /// Bytes of every file in the manifest
+    pub size: u64,
+    /// Hash of the manifest, which changes with the contents of any file
+    pub hash: String,
+}
+
+/// Record the hash of every file in the output directory, for `mub verify`
+pub(crate) fn write(config: &Config) -> Result<Summary> {
+    let manifest = hash_tree(&config.output, &Preserved::load(config)?)?;
+    let size = manifest
+        .keys()
+        .filter_map(|file| config.output.join(file).metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let path = config.output.join(FILE);
+    let bytes = serde_json::to_vec_pretty(&manifest)?;
+    std::fs::write(&path, &bytes)
+        .with_context(|| anyhow!("Unable to write build manifest [{path:?}]"))?;
+    if let Some(signing) = &config.signing {
+        let key = signing.signing_key(config)?;
+        let signature = Signature {
+            algorithm: String::from("ed25519"),
+            public_key: STANDARD.encode(key.verifying_key().as_bytes()),
+            signature: STANDARD.encode(key.sign(&bytes).to_bytes()),
+        };
+        let path = config.output.join(SIGNATURE_FILE);
+        std::fs::write(&path, serde_json::to_vec_pretty(&signature)?)
+            .with_context(|| anyhow!("Unable to write manifest signature [{path:?}]"))?;
+    }
+    Ok(Summary {
+        size,
+        hash: digest(&bytes),
+    })
+}
+
+/// How a tree differs from the manifest of the build it came from
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Files whose contents changed since the build
+    pub tampered: Vec<PathBuf>,
+    /// Files of the build that are gone
+    pub missing: Vec<PathBuf>,
+    /// Files that weren't part of the build
+    pub extraneous: Vec<PathBuf>,
+    /// Public key the manifest is signed with, when it is signed and the
+    /// signature holds
+    pub signed_by: Option<String>,
+    /// Why the signature of the manifest doesn't hold, if it doesn't
+    pub bad_signature: Option<String>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.tampered.is_empty()
+            && self.missing.is_empty()
+            && self.extraneous.is_empty()
+            && self.bad_signature.is_none()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (label, paths) in [
+            ("tampered", &self.tampered),
+            ("missing", &self.missing),
+            ("extraneous", &self.extraneous),
+        ] {
+            for path in paths {
+                writeln!(f, "{label:<10} {}", path.display())?;
+            }
+        }
+        if let Some(problem) = &self.bad_signature {
+            writeln!(f, "{:<10} {problem}", "signature")?;
+        }
+        if self.is_clean() {
+            writeln!(f, "Output matches the build manifest")?;
+        }
+        if let Some(key) = &self.signed_by {
+            writeln!(f, "Manifest signed by {key}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The build manifest in `dir`
+pub(crate) fn read(dir: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    let path = dir.join(FILE);
+    let bytes = std::fs::read(&path)
+        .with_context(|| anyhow!("Unable to read build manifest [{path:?}]"))?;
+    serde_json::from_slice(&bytes).with_context(|| anyhow!("Invalid build manifest [{path:?}]"))
+}
+
+/// Check the signature of the manifest `bytes` in `dir`, returning the key it
+/// was made with. The signature has to be made with `key` when given, or with
+/// the configured signing key, otherwise any key does and the caller is left
+/// to judge the one returned.
+fn check_signature(dir: &Path, bytes: &[u8], key: Option<&str>, config: &Config) -> Result<String> {
+    let path = dir.join(SIGNATURE_FILE);
+    let signature: Signature = serde_json::from_slice(
+        &std::fs::read(&path).with_context(|| anyhow!("Unable to read [{path:?}]"))?,
+    )
+    .with_context(|| anyhow!("Invalid manifest signature [{path:?}]"))?;
+    if signature.algorithm != "ed25519" {
+        bail!("Unknown signature algorithm [{}]", signature.algorithm);
+    }
+    let expected = match (key, &config.signing) {
+        (Some(key), _) => Some(key.to_owned()),
+        (None, Some(signing)) => Some(signing.public_key(config)?),
+        (None, None) => None,
+    };
+    if let Some(expected) = expected.filter(|expected| *expected != signature.public_key) {
+        bail!(
+            "Signed by [{}] instead of [{expected}]",
+            signature.public_key
+        );
+    }
+
+    let public_key: [u8; 32] = STANDARD
+        .decode(&signature.public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .with_context(|| anyhow!("Invalid public key [{}]", signature.public_key))?;
+    let bytes_of_signature: [u8; 64] = STANDARD
+        .decode(&signature.signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .context("Invalid signature encoding")?;
+    VerifyingKey::from_bytes(&public_key)
+        .context("Invalid public key")?
+        .verify_strict(
+            bytes,
+            &ed25519_dalek::Signature::from_bytes(&bytes_of_signature),
+        )
+        .map_err(|_| anyhow!("Signature does not match the manifest"))?;
+    Ok(signature.public_key)
+}
+
+/// Compare the files under `dir` against the build manifest it holds, the
+/// paths the config preserves aside, and check the signature of the manifest
+/// when there is one. A base64 public `key` requires the manifest to be
+/// signed with it.
+pub fn verify(dir: &Path, config: &Config, key: Option<&str>) -> Result<Report> {
+    let path = dir.join(FILE);
+    let bytes = std::fs::read(&path)
+        .with_context(|| anyhow!("Unable to read build manifest [{path:?}]"))?;
+    let manifest: BTreeMap<PathBuf, String> = serde_json::from_slice(&bytes)
+        .with_context(|| anyhow!("Invalid build manifest [{path:?}]"))?;
+    let mut found = hash_tree(dir, &Preserved::load(config)?)?;
+
+    let mut report = Report::default();
+    let signed = key.is_some() || dir.join(SIGNATURE_FILE).is_file();
+    if signed {
+        match check_signature(dir, &bytes, key, config) {
+            Ok(key) => report.signed_by = Some(key),
+            Err(e) => report.bad_signature = Some(format!("{e:#}")),
+        }
+    }
+    for (path, hash) in manifest {
+        match found.remove(&path) {
+            Some(found) if found == hash => {}
+            Some(_) => report.tampered.push(path),
+            None => report.missing.push(path),
+        }
+    }
+    report.extraneous = found.into_keys().collect();
+    Ok(report)
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use glob::glob;
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    manifest,
+    output::{Output, Written},
+    transform::{self, VariantCache},
+};
+
+/// Hex digits of the content hash kept in stored file names
+const HASH_LENGTH: usize = 20;
+
+fn default_dir() -> String {
+    String::from("media")
+}
+
+fn default_extensions() -> Vec<String> {
+    [
+        "png", "jpg", "jpeg", "gif", "webp", "avif", "svg", "mp4", "webm", "mp3", "ogg", "pdf",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Store media from the include directory under content hash paths
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MediaConfig {
+    /// Output directory holding the stored files
+    #[serde(default = "default_dir")]
+    pub(crate) dir: String,
+    /// Extensions of the include files that go into the store
+    #[serde(default = "default_extensions")]
+    pub(crate) extensions: Vec<String>,
+}
+
+/// Include files stored by content hash, identical files sharing one copy
+#[derive(Debug, Default, Clone)]
+pub(crate) struct MediaStore {
+    /// Stored URL by the output-root-relative path the file was included at
+    urls: BTreeMap<PathBuf, String>,
+    /// Source of each stored file, and where it sits in the include directory,
+    /// by its output-root-relative path
+    files: BTreeMap<PathBuf, (PathBuf, PathBuf)>,
+}
+
+impl MediaStore {
+    /// Hash every media file of the include directory, an empty store when the
+    /// media store isn't configured
+    pub(crate) fn collect(config: &Config) -> Result<Self> {
+        let Some(media) = &config.media else {
+            return Ok(Self::default());
+        };
+        let include_dir = config.input.join("include");
+        if !include_dir.exists() {
+            return Ok(Self::default());
+        }
+        // Glob drops a leading `./` from the paths it yields
+        let include_prefix = include_dir.strip_prefix(".").unwrap_or(&include_dir);
+        let pattern = format!("{}/**/*", include_dir.display());
+
+        let hashed = glob(&pattern)
+            .with_context(|| anyhow!("Unable to glob include directory: [{pattern}]"))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .filter(|path| {
+                path.strip_prefix(include_prefix).is_ok_and(|included| {
+                    !transform::is_excluded(included, config).unwrap_or(false)
+                })
+            })
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| media.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+            })
+            .par_bridge()
+            .map(|src| -> Result<(PathBuf, PathBuf, PathBuf)> {
+                let included = src.strip_prefix(include_prefix)?.to_path_buf();
+                let bytes = std::fs::read(&src)
+                    .with_context(|| anyhow!("Unable to read media file [{src:?}]"))?;
+                let hash = manifest::digest(&bytes);
+                let mut stored = PathBuf::from(&media.dir).join(&hash[..HASH_LENGTH]);
+                if let Some(ext) = src.extension() {
+                    stored.set_extension(ext.to_ascii_lowercase());
+                }
+                Ok((included, stored, src))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut store = Self::default();
+        for (included, stored, src) in hashed {
+            store
+                .urls
+                .insert(included.clone(), config.site_url(&stored.to_string_lossy()));
+            store.files.entry(stored).or_insert((src, included));
+        }
+        Ok(store)
+    }
+
+    /// Stored URL of a file included at `path`, relative to the output root
+    pub(crate) fn url(&self, path: &Path) -> Option<&str> {
+        self.urls.get(path).map(String::as_str)
+    }
+
+    /// URL a file included at `path` is served at, from the store or not
+    pub(crate) fn href(&self, path: &Path, config: &Config) -> String {
+        self.url(path)
+            .map_or_else(|| config.site_url(&path.to_string_lossy()), str::to_owned)
+    }
+
+    /// Whether the include file at `path` is served from the store instead
+    pub(crate) fn contains(&self, path: &Path) -> bool {
+        self.urls.contains_key(path)
+    }
+
+    /// Write the mapping from included paths to stored URLs as `media.json`
+    pub(crate) fn write_manifest(&self, config: &Config, output: &Output) -> Result<()> {
+        if config.media.is_none() {
+            return Ok(());
+        }
+        let manifest: BTreeMap<String, &String> = self
+            .urls
+            .iter()
+            .map(|(path, url)| (format!("/{}", path.to_string_lossy()), url))
+            .collect();
+        output.add(
+            config.output.join("media.json"),
+            serde_json::to_vec_pretty(&manifest)?,
+        )
+    }
+
+    /// Install one file per distinct content into the output, through the
+    /// include rules like any other include file
+    pub(crate) fn copy(
+        &self,
+        config: &Config,
+        cache: &VariantCache,
+        written: &Written,
+    ) -> Result<()> {
+        self.files
+            .par_iter()
+            .map(|(stored, (src, included))| -> Result<()> {
+                config.cancel.check()?;
+                let dst = config.output.join(stored);
+                if let Some(parent) = dst.parent() {
+                    std::fs::create_dir_all(parent).with_context(|| {
+                        anyhow!("Unable to create media directory [{parent:?}]")
+                    })?;
+                }
+                transform::install(src, included, &dst, config, cache)?;
+                written.add(dst)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context, Result};
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// When written output files are flushed to stable storage
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Fsync {
+    /// Leave it to the operating system
+    #[default]
+    Never,
+    /// Sync every file after writing it
+    File,
+    /// Sync each output directory once, after everything is written
+    Directory,
+}
+
+/// Rendered files waiting to be written into the output directory. Collecting
+/// them first lets every directory be created once and the writes happen in
+/// path order, which matters on network filesystems.
+#[derive(Debug, Default)]
+pub(crate) struct Output {
+    files: Mutex<Vec<(PathBuf, Vec<u8>)>>,
+}
+
+impl Output {
+    pub(crate) fn add<C: Into<Vec<u8>>>(&self, path: PathBuf, contents: C) -> Result<()> {
+        self.files
+            .lock()
+            .map_err(|_| anyhow!("Output queue lock is poisoned"))?
+            .push((path, contents.into()));
+        Ok(())
+    }
+
+    /// Write every queued file, syncing according to the policy
+    pub(crate) fn write(self, fsync: Fsync, written: &Written) -> Result<()> {
+        let mut files = self
+            .files
+            .into_inner()
+            .map_err(|_| anyhow!("Output queue lock is poisoned"))?;
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        // Content addressed files can be queued by every page that has them
+        files.dedup_by(|(a, _), (b, _)| a == b);
+        for (path, _) in &files {
+            written.add(path.clone())?;
+        }
+
+        let directories: BTreeSet<&Path> =
+            files.iter().filter_map(|(path, _)| path.parent()).collect();
+        for directory in &directories {
+            std::fs::create_dir_all(directory).with_context(|| {
+                anyhow!(
+                    "Unable to create output directory [{}]",
+                    directory.display()
+                )
+            })?;
+        }
+
+        files
+            .par_iter()
+            .map(|(path, contents)| -> Result<()> {
+                let mut file = File::create(path).with_context(|| {
+                    anyhow!("Unable to create output file [{}]", path.display())
+                })?;
+                file.write_all(contents)
+                    .with_context(|| anyhow!("Unable to write output file [{}]", path.display()))?;
+                if fsync == Fsync::File {
+                    file.sync_all().with_context(|| {
+                        anyhow!("Unable to sync output file [{}]", path.display())
+                    })?;
+                }
+                tracing::trace!(dst = %path.display(), size = contents.len(), "Wrote file");
+                Ok(())
+            })
+            .collect::<Result<()>>()?;
+
+        if fsync == Fsync::Directory {
+            for directory in &directories {
+                File::open(directory)
+                    .and_then(|dir| dir.sync_all())
+                    .with_context(|| {
+                        anyhow!("Unable to sync output directory [{}]", directory.display())
+                    })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Paths of the output directory builds leave alone, see `preserve` in the config
+pub(crate) struct Preserved(Vec<glob::Pattern>);
+
+impl Preserved {
+    pub(crate) fn load(config: &Config) -> Result<Self> {
+        config
+            .preserve
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern)
+                    .with_context(|| anyhow!("Invalid preserve pattern [{pattern}]"))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Self)
+    }
+
+    /// Whether a path relative to the output is preserved, or is inside a
+    /// preserved directory
+    pub(crate) fn contains(&self, relative: &Path) -> bool {
+        relative
+            .ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .any(|path| self.0.iter().any(|pattern| pattern.matches_path(path)))
+    }
+}
+
+/// Every file a build put into the output directory, whatever else is in there
+/// was left by earlier builds
+#[derive(Debug, Default)]
+pub(crate) struct Written {
+    files: Mutex<HashSet<PathBuf>>,
+}
+
+impl Written {
+    pub(crate) fn add(&self, path: PathBuf) -> Result<()> {
+        self.files
+            .lock()
+            .map_err(|_| anyhow!("Written files lock is poisoned"))?
+            .insert(path);
+        Ok(())
+    }
+
+    /// Whether the build already wrote `path`
+    pub(crate) fn contains(&self, path: &Path) -> Result<bool> {
+        Ok(self
+            .files
+            .lock()
+            .map_err(|_| anyhow!("Written files lock is poisoned"))?
+            .contains(path))
+    }
+
+    /// Remove the files of the output this build didn't write, and the
+    /// directories left empty, except for the preserved paths
+    pub(crate) fn remove_stale(&self, config: &Config) -> Result<()> {
+        let preserved = Preserved::load(config)?;
+        let files = self
+            .files
+            .lock()
+            .map_err(|_| anyhow!("Written files lock is poisoned"))?;
+        remove_stale(&config.output, Path::new(""), &files, &preserved)
+    }
+}
+
+fn remove_stale(
+    dir: &Path,
+    relative: &Path,
+    written: &HashSet<PathBuf>,
+    preserved: &Preserved,
+) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| anyhow!("Unable to read output directory [{}]", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = relative.join(entry.file_name());
+        if preserved.contains(&relative) {
+            continue;
+        }
+        // Symlinks are removed like files, never followed
+        if entry.file_type()?.is_dir() {
+            remove_stale(&path, &relative, written, preserved)?;
+            // Fails, as it should, when anything is left in the directory
+            let _ = std::fs::remove_dir(&path);
+        } else if !written.contains(&path) {
+            std::fs::remove_file(&path)
+                .with_context(|| anyhow!("Unable to remove stale output [{}]", path.display()))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use minijinja::Environment;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// A WebAssembly module extending the build, needs mub built with the `wasm`
+/// feature. Modules export their `memory` and `alloc(len: i32) -> i32`, and
+/// every hook takes `(ptr: i32, len: i32)` of its UTF-8 input, written into
+/// memory from `alloc`, returning `(ptr << 32) | len` of its UTF-8 output.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Compiled module, or its text format
+    pub(crate) path: PathBuf,
+    /// Run every post body through the module's `transform` export, in
+    /// config order, before it is rendered
+    #[serde(default)]
+    pub(crate) transform: bool,
+    /// Exports added as template functions under their own names, called with
+    /// a JSON array of their arguments and returning text
+    #[serde(default)]
+    pub(crate) functions: Vec<String>,
+}
+
+#[cfg(feature = "wasm")]
+mod wasm {
+    use std::sync::Arc;
+
+    use anyhow::{anyhow, Context, Result};
+    use minijinja::{value::Rest, Environment, Error, ErrorKind, Value};
+    use wasmi::{Engine, Linker, Module, Store};
+
+    use super::PluginConfig;
+
+    /// Instructions a single hook call may run before it is stopped
+    const FUEL: u64 = 10_000_000_000;
+
+    #[derive(Debug)]
+    pub(super) struct Plugin {
+        config: PluginConfig,
+        engine: Engine,
+        module: Module,
+    }
+
+    impl Plugin {
+        pub(super) fn load(config: &PluginConfig) -> Result<Self> {
+            let path = &config.path;
+            let wasm =
+                std::fs::read(path).with_context(|| anyhow!("Unable to read plugin [{path:?}]"))?;
+            let mut settings = wasmi::Config::default();
+            settings.consume_fuel(true);
+            let engine = Engine::new(&settings);
+            let module = Module::new(&engine, wasm)
+                .with_context(|| anyhow!("Unable to compile plugin [{path:?}]"))?;
+            Ok(Self {
+                config: config.clone(),
+                engine,
+                module,
+            })
+        }
+
+        /// Run `export` on `input` in a fresh instance, so calls can't see
+        /// each other's state
+        fn call(&self, export: &str, input: &str) -> Result<String> {
+            let path = &self.config.path;
+            let mut store = Store::new(&self.engine, ());
+            store.set_fuel(FUEL)?;
+            let instance = Linker::new(&self.engine)
+                .instantiate_and_start(&mut store, &self.module)
+                .with_context(|| anyhow!("Unable to instantiate plugin [{path:?}]"))?;
+            let memory = instance
+                .get_memory(&store, "memory")
+                .with_context(|| anyhow!("Plugin [{path:?}] exports no `memory`"))?;
+            let alloc = instance
+                .get_typed_func::<i32, i32>(&store, "alloc")
+                .with_context(|| anyhow!("Plugin [{path:?}] exports no `alloc(i32) -> i32`"))?;
+            let hook = instance
+                .get_typed_func::<(i32, i32), i64>(&store, export)
+                .with_context(|| {
+                    anyhow!("Plugin [{path:?}] exports no `{export}(i32, i32) -> i64`")
+                })?;
+
+            let len = i32::try_from(input.len())?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as u32 as usize, input.as_bytes())?;
+            let packed = hook
+                .call(&mut store, (ptr, len))
+                .with_context(|| anyhow!("Plugin [{path:?}] failed in `{export}`"))?
+                as u64;
+            let mut output = vec![0; (packed & 0xffff_ffff) as usize];
+            memory.read(&store, (packed >> 32) as usize, &mut output)?;
+            String::from_utf8(output).with_context(|| {
+                anyhow!("Plugin [{path:?}] returned invalid UTF-8 from `{export}`")
+            })
+        }
+
+        pub(super) fn transform(&self, content: String) -> Result<String> {
+            match self.config.transform {
+                true => self.call("transform", &content),
+                false => Ok(content),
+            }
+        }
+
+        pub(super) fn register(self: &Arc<Self>, env: &mut Environment) {
+            for name in &self.config.functions {
+                let plugin = self.clone();
+                let export = name.clone();
+                env.add_function(name.clone(), move |args: Rest<Value>| {
+                    let input = serde_json::to_string(&args.0)
+                        .map_err(|e| Error::new(ErrorKind::InvalidOperation, e.to_string()))?;
+                    plugin
+                        .call(&export, &input)
+                        .map_err(|e| Error::new(ErrorKind::InvalidOperation, format!("{e:#}")))
+                });
+            }
+        }
+    }
+}
+
+/// Plugins of the config, loaded and compiled
+#[derive(Debug, Default)]
+pub(crate) struct Plugins {
+    #[cfg(feature = "wasm")]
+    loaded: Vec<std::sync::Arc<wasm::Plugin>>,
+}
+
+impl Plugins {
+    #[cfg(feature = "wasm")]
+    pub(crate) fn load(config: &Config) -> Result<Self> {
+        let loaded = config
+            .plugins
+            .iter()
+            .map(|plugin| wasm::Plugin::load(plugin).map(std::sync::Arc::new))
+            .collect::<Result<_>>()?;
+        Ok(Self { loaded })
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    pub(crate) fn load(config: &Config) -> Result<Self> {
+        if !config.plugins.is_empty() {
+            anyhow::bail!("Plugins are configured but mub was built without the `wasm` feature");
+        }
+        Ok(Self::default())
+    }
+
+    /// A post body, through every transforming plugin
+    pub(crate) fn transform(&self, content: String) -> Result<String> {
+        #[cfg(feature = "wasm")]
+        let content = self
+            .loaded
+            .iter()
+            .try_fold(content, |content, plugin| plugin.transform(content))?;
+        Ok(content)
+    }
+
+    /// Add the template functions of the plugins to `env`
+    pub(crate) fn register(&self, _env: &mut Environment) {
+        #[cfg(feature = "wasm")]
+        for plugin in &self.loaded {
+            plugin.register(_env);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets,
+    config::Config,
+    feed::{escape_xml, rfc2822},
+    media::MediaStore,
+    output::Output,
+    types::{AvailableContent, Content},
+};
+
+fn default_file() -> String {
+    String::from("podcast.xml")
+}
+
+/// Podcast RSS feed of the posts with an `audio` or `enclosure` file
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PodcastConfig {
+    /// Show title, `site.title` when not set
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default)]
+    pub(crate) description: Option<String>,
+    #[serde(default)]
+    pub(crate) author: Option<String>,
+    /// Contact address for podcast directories
+    #[serde(default)]
+    pub(crate) email: Option<String>,
+    /// Cover art, at least 1400x1400 for most directories
+    #[serde(default)]
+    pub(crate) image: Option<String>,
+    /// iTunes category, e.g. `Technology`
+    #[serde(default)]
+    pub(crate) category: Option<String>,
+    #[serde(default)]
+    pub(crate) explicit: bool,
+    /// Where the feed is written in the output
+    #[serde(default = "default_file")]
+    pub(crate) file: String,
+}
+
+/// A media file attached to a post, as feeds describe it
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct Enclosure {
+    /// Root-relative URL the file is served at
+    pub(crate) href: String,
+    /// Size in bytes
+    pub(crate) length: u64,
+    pub(crate) mime: &'static str,
+}
+
+/// MIME type of a media file from its extension
+fn mime(reference: &str) -> &'static str {
+    match reference
+        .rsplit('.')
+        .next()
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("mp3") => "audio/mpeg",
+        Some("m4a") => "audio/mp4",
+        Some("aac") => "audio/aac",
+        Some("ogg" | "oga") => "audio/ogg",
+        Some("opus") => "audio/opus",
+        Some("flac") => "audio/flac",
+        Some("wav") => "audio/wav",
+        Some("mp4" | "m4v") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mov") => "video/quicktime",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+impl Enclosure {
+    /// Describe a file referenced from the page at `page`, its length read from
+    /// the include directory
+    pub(crate) fn for_reference(
+        reference: &str,
+        page: &Path,
+        config: &Config,
+        media: &MediaStore,
+    ) -> Self {
+        let resolved = assets::resolve(page, reference);
+        let href = match &resolved {
+            Some(path) => media.href(path, config),
+            None => reference.to_owned(),
+        };
+        let length = resolved
+            .and_then(|path| std::fs::metadata(config.input.join("include").join(path)).ok())
+            .map_or(0, |metadata| metadata.len());
+        Self {
+            href,
+            length,
+            mime: mime(reference),
+        }
+    }
+}
+
+/// An `<item>` for an episode
+fn item(content: &Content, enclosure: &Enclosure, config: &Config) -> String {
+    let metadata = &content.post.metadata;
+    let link = escape_xml(&content.location.canonical);
+    let mut item = format!(
+        "<item><title>{}</title><link>{link}</link><guid isPermaLink=\"true\">{link}</guid>",
+        escape_xml(&metadata.title)
+    );
+    item.push_str(&format!("<pubDate>{}</pubDate>", rfc2822(&metadata.date)));
+    if let Some(description) = metadata.extra_str("description") {
+        item.push_str(&format!(
+            "<description>{}</description>",
+            escape_xml(description)
+        ));
+    }
+    item.push_str(&format!(
+        "<enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>",
+        escape_xml(&config.absolute_url(&enclosure.href)),
+        enclosure.length,
+        enclosure.mime
+    ));
+    for (key, tag) in [
+        ("duration", "itunes:duration"),
+        ("episode", "itunes:episode"),
+        ("season", "itunes:season"),
+    ] {
+        let value = metadata.extra.get(key).map(|value| {
+            value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_owned)
+        });
+        if let Some(value) = value {
+            item.push_str(&format!("<{tag}>{}</{tag}>", escape_xml(&value)));
+        }
+    }
+    item.push_str("</item>\n");
+    item
+}
+
+/// Queue the podcast feed of every published post with an enclosure
+pub(crate) fn write(content: &AvailableContent, config: &Config, output: &Output) -> Result<()> {
+    let Some(podcast) = &config.podcast else {
+        return Ok(());
+    };
+    let Some(base_url) = &config.base_url else {
+        bail!("The podcast feed needs `base_url` for the absolute URLs directories require");
+    };
+
+    let episodes: Vec<_> = content
+        .content
+        .iter()
+        .filter(|content| content.publish && !content.draft)
+        .filter_map(|content| Some((content, content.post.enclosure.as_ref()?)))
+        .collect();
+
+    let site = |key: &str| config.site.get(key).and_then(|value| value.as_str());
+    let title = podcast
+        .title
+        .as_deref()
+        .or(site("title"))
+        .unwrap_or("Podcast");
+    let mut channel = format!(
+        "<title>{}</title><link>{}</link><description>{}</description>",
+        escape_xml(title),
+        escape_xml(base_url),
+        escape_xml(podcast.description.as_deref().unwrap_or_default())
+    );
+    if let Some(language) = site("lang") {
+        channel.push_str(&format!("<language>{}</language>", escape_xml(language)));
+    }
+    if let Some(author) = &podcast.author {
+        channel.push_str(&format!(
+            "<itunes:author>{}</itunes:author>",
+            escape_xml(author)
+        ));
+    }
+    if let Some(email) = &podcast.email {
+        channel.push_str(&format!(
+            "<itunes:owner><itunes:name>{}</itunes:name><itunes:email>{}</itunes:email></itunes:owner>",
+            escape_xml(podcast.author.as_deref().unwrap_or(title)),
+            escape_xml(email)
+        ));
+    }
+    if let Some(image) = &podcast.image {
+        channel.push_str(&format!(
+            "<itunes:image href=\"{}\"/>",
+            escape_xml(
+                &config.absolute_url(
+                    &content
+                        .media
+                        .href(Path::new(image.trim_start_matches('/')), config)
+                )
+            )
+        ));
+    }
+    if let Some(category) = &podcast.category {
+        channel.push_str(&format!(
+            "<itunes:category text=\"{}\"/>",
+            escape_xml(category)
+        ));
+    }
+    channel.push_str(&format!(
+        "<itunes:explicit>{}</itunes:explicit>\n",
+        podcast.explicit
+    ));
+    for (content, enclosure) in episodes {
+        channel.push_str(&item(content, enclosure, config));
+    }
+
+    let feed = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\"><channel>{channel}</channel></rss>\n"
+    );
+    output.add(config.output.join(&podcast.file), feed)
+}
@@ -0,0 +1,928 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use base64::Engine;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, LinkType, Tag, TagEnd};
+use pulldown_cmark_escape::{escape_href, escape_html};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ansi, asciinema, assets, config::Config, filters, media::MediaStore, types::TocEntry,
+    typography::Typography,
+};
+
+/// What the post-processing passes need to know about the page being rendered
+pub(crate) struct Page<'a> {
+    /// Output file of the page, relative to the output root
+    pub(crate) path: &'a Path,
+    pub(crate) config: &'a Config,
+    pub(crate) typography: Typography,
+    /// Abbreviations defined in the post itself, on top of the site-wide ones
+    pub(crate) abbreviations: HashMap<String, String>,
+    pub(crate) media: &'a MediaStore,
+}
+
+impl Page<'_> {
+    /// Where a local reference from this page lives in the source tree
+    fn source_of(&self, reference: &str) -> Option<PathBuf> {
+        assets::resolve(self.path, reference)
+            .map(|path| self.config.input.join("include").join(path))
+    }
+
+    /// URL a local reference from this page is served at, which differs from
+    /// the reference for files moved into the media store and for root-relative
+    /// references on sites deployed under a path
+    fn href<'r>(&self, reference: &'r str) -> Cow<'r, str> {
+        if let Some(url) =
+            assets::resolve(self.path, reference).and_then(|path| self.media.url(&path))
+        {
+            return Cow::Owned(url.to_owned());
+        }
+        match reference.starts_with('/') && !reference.starts_with("//") {
+            true => Cow::Owned(self.config.site_url(reference)),
+            false => Cow::Borrowed(reference),
+        }
+    }
+}
+
+fn default_anchor_symbol() -> String {
+    String::from("#")
+}
+
+fn default_anchor_class() -> String {
+    String::from("anchor")
+}
+
+/// Permalinks appended to every heading of a post
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AnchorConfig {
+    /// Text of the link
+    #[serde(default = "default_anchor_symbol")]
+    pub(crate) symbol: String,
+    /// Class of the link
+    #[serde(default = "default_anchor_class")]
+    pub(crate) class: String,
+}
+
+/// Run the markdown events of a post through the post-processing passes
+pub(crate) fn process<'a>(events: Vec<Event<'a>>, page: &Page) -> Vec<Event<'a>> {
+    let events = decorative_images(events);
+    let events = dark_variants(events, page);
+    let events = image_dimensions(events, page);
+    let events = match page.config.obfuscate_contacts {
+        true => obfuscate_contacts(events),
+        false => events,
+    };
+    let events = site_urls(events, page);
+    let events = abbreviations(events, page);
+    let events = diffs(events);
+    let events = terminal_output(events);
+    let events = casts(events, page);
+    let events = playgrounds(events);
+    let events = strip_code_block_flags(events);
+    let events = match &page.config.heading_anchors {
+        Some(anchors) => heading_anchors(events, anchors),
+        None => events,
+    };
+    let events = match page.config.sidenotes {
+        true => sidenotes(events),
+        false => events,
+    };
+    match page.typography.widows {
+        Some(max_length) => widows(events, max_length),
+        None => events,
+    }
+}
+
+/// An image found in the event stream, with its alt text flattened
+struct Image<'a> {
+    dest_url: CowStr<'a>,
+    title: CowStr<'a>,
+    alt: String,
+}
+
+impl Image<'_> {
+    /// Render the image as an `<img>` tag with extra attributes appended
+    fn to_html(&self, page: &Page, attributes: &[(&str, String)]) -> String {
+        let mut html = String::from("<img src=\"");
+        let _ = escape_href(&mut html, &page.href(&self.dest_url));
+        html.push_str("\" alt=\"");
+        let _ = escape_html(&mut html, &self.alt);
+        html.push('"');
+        if !self.title.is_empty() {
+            html.push_str(" title=\"");
+            let _ = escape_html(&mut html, &self.title);
+            html.push('"');
+        }
+        for (name, value) in attributes {
+            html.push_str(&format!(" {name}=\""));
+            let _ = escape_html(&mut html, value);
+            html.push('"');
+        }
+        html.push_str(" />");
+        html
+    }
+}
+
+/// Replace every image in the stream using `rewrite`, which returns the HTML to
+/// emit instead or `None` to leave the image untouched.
+fn rewrite_images<'a, F>(events: Vec<Event<'a>>, mut rewrite: F) -> Vec<Event<'a>>
+where
+    F: FnMut(&Image<'a>) -> Option<String>,
+{
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) = event
+        else {
+            out.push(event);
+            continue;
+        };
+
+        // Gather everything up to the matching end of the image
+        let mut inner = Vec::new();
+        let mut depth = 1;
+        for event in events.by_ref() {
+            match &event {
+                Event::Start(Tag::Image { .. }) => depth += 1,
+                Event::End(TagEnd::Image) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            inner.push(event);
+        }
+
+        let alt = inner
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+        let image = Image {
+            dest_url,
+            title,
+            alt,
+        };
+
+        match rewrite(&image) {
+            Some(html) => out.push(Event::InlineHtml(html.into())),
+            None => {
+                out.push(Event::Start(Tag::Image {
+                    link_type,
+                    dest_url: image.dest_url,
+                    title: image.title,
+                    id,
+                }));
+                out.extend(inner);
+                out.push(Event::End(TagEnd::Image));
+            }
+        }
+    }
+    out
+}
+
+/// Add `width` and `height` to local images so browsers can reserve their space
+fn image_dimensions<'a>(events: Vec<Event<'a>>, page: &Page) -> Vec<Event<'a>> {
+    rewrite_images(events, |image| {
+        let source = page.source_of(&image.dest_url)?;
+        let size = imagesize::size(source).ok()?;
+        Some(image.to_html(
+            page,
+            &[
+                ("width", size.width.to_string()),
+                ("height", size.height.to_string()),
+            ],
+        ))
+    })
+}
+
+/// The `name.dark.ext` sibling of an image reference, if it has an extension
+fn dark_variant(reference: &str) -> Option<String> {
+    let (stem, ext) = reference.rsplit_once('.')?;
+    (!stem.ends_with(".dark") && !ext.contains('/')).then(|| format!("{stem}.dark.{ext}"))
+}
+
+/// Wrap images that have a `.dark` sibling in a `<picture>` that switches to it
+/// when the reader prefers a dark colour scheme
+fn dark_variants<'a>(events: Vec<Event<'a>>, page: &Page) -> Vec<Event<'a>> {
+    rewrite_images(events, |image| {
+        let dark = dark_variant(&image.dest_url)?;
+        if !page.source_of(&dark)?.exists() {
+            return None;
+        }
+
+        let dimensions = page
+            .source_of(&image.dest_url)
+            .and_then(|source| imagesize::size(source).ok())
+            .map(|size| {
+                vec![
+                    ("width", size.width.to_string()),
+                    ("height", size.height.to_string()),
+                ]
+            })
+            .unwrap_or_default();
+
+        let mut html = String::from("<picture><source srcset=\"");
+        let _ = escape_href(&mut html, &page.href(&dark));
+        html.push_str("\" media=\"(prefers-color-scheme: dark)\" />");
+        html.push_str(&image.to_html(page, &dimensions));
+        html.push_str("</picture>");
+        Some(html)
+    })
+}
+
+/// Point the remaining images and local links at where they are served, see
+/// [`Page::href`]
+fn site_urls<'a>(events: Vec<Event<'a>>, page: &Page) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Image {
+                link_type,
+                dest_url: page.href(&dest_url).into_owned().into(),
+                title,
+                id,
+            }),
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) if link_type != LinkType::Email && !assets::is_external(&dest_url) => {
+                Event::Start(Tag::Link {
+                    link_type,
+                    dest_url: page.href(&dest_url).into_owned().into(),
+                    title,
+                    id,
+                })
+            }
+            event => event,
+        })
+        .collect()
+}
+
+/// Every character as a numeric character reference, which browsers read as
+/// the text itself and address harvesters looking for plain text don't
+fn obfuscate(text: &str) -> String {
+    text.chars().map(|c| format!("&#{};", c as u32)).collect()
+}
+
+/// Write `mailto:` and `tel:` links, with their text, as character references
+fn obfuscate_contacts(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut in_contact = false;
+    for event in events {
+        match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                ..
+            }) if link_type == LinkType::Email
+                || dest_url.starts_with("mailto:")
+                || dest_url.starts_with("tel:") =>
+            {
+                // Email autolinks come without their scheme
+                let href = match link_type == LinkType::Email && !dest_url.starts_with("mailto:") {
+                    true => format!("mailto:{dest_url}"),
+                    false => dest_url.to_string(),
+                };
+                let mut html = format!("<a href=\"{}\"", obfuscate(&href));
+                if !title.is_empty() {
+                    html.push_str(&format!(" title=\"{}\"", obfuscate(&title)));
+                }
+                html.push('>');
+                out.push(Event::InlineHtml(html.into()));
+                in_contact = true;
+            }
+            Event::Text(text) if in_contact => out.push(Event::InlineHtml(obfuscate(&text).into())),
+            Event::Code(code) if in_contact => out.push(Event::InlineHtml(
+                format!("<code>{}</code>", obfuscate(&code)).into(),
+            )),
+            Event::End(TagEnd::Link) if in_contact => {
+                out.push(Event::InlineHtml("</a>".into()));
+                in_contact = false;
+            }
+            event => out.push(event),
+        }
+    }
+    out
+}
+
+/// Whether raw HTML is the `<!-- more -->` comment ending the summary of a post
+fn is_more_marker(html: &str) -> bool {
+    html.trim()
+        .strip_prefix("<!--")
+        .and_then(|comment| comment.strip_suffix("-->"))
+        .is_some_and(|comment| comment.trim().eq_ignore_ascii_case("more"))
+}
+
+/// Where the summary of a markdown post ends, at a `<!-- more -->` marker
+/// between the blocks of the top level
+pub(crate) fn summary_end(events: &[Event]) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::HtmlBlock) if depth == 0 => {
+                if let Some(Event::Html(html)) = events.get(index + 1) {
+                    if is_more_marker(html) {
+                        return Some(index);
+                    }
+                }
+                depth += 1;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Where the summary of an HTML post ends, at its `<!-- more -->` marker
+pub(crate) fn html_summary_end(html: &str) -> Option<usize> {
+    html.match_indices("<!--")
+        .map(|(start, _)| start)
+        .find(|&start| {
+            html[start..]
+                .find("-->")
+                .is_some_and(|length| is_more_marker(&html[start..start + length + 3]))
+        })
+}
+
+/// Give every heading an `id` made from its text, unique within the post, and
+/// list the headings for the table of contents
+pub(crate) fn heading_ids(mut events: Vec<Event<'_>>) -> (Vec<Event<'_>>, Vec<TocEntry>) {
+    let mut toc = Vec::new();
+    let mut taken: HashMap<String, usize> = HashMap::new();
+    let mut start = None;
+    let mut text = String::new();
+    for index in 0..events.len() {
+        match &events[index] {
+            Event::Start(Tag::Heading { .. }) => {
+                start = Some(index);
+                text.clear();
+            }
+            Event::Text(t) | Event::Code(t) if start.is_some() => text.push_str(t),
+            Event::End(TagEnd::Heading(_)) => {
+                let Some(Event::Start(Tag::Heading { level, id, .. })) =
+                    start.take().map(|start| &mut events[start])
+                else {
+                    continue;
+                };
+                let id = match id {
+                    Some(id) => id.to_string(),
+                    None => {
+                        let mut slug = filters::slugify(&text);
+                        if slug.is_empty() {
+                            slug = String::from("section");
+                        }
+                        let seen = taken.entry(slug.clone()).or_default();
+                        *seen += 1;
+                        if *seen > 1 {
+                            slug = format!("{slug}-{}", *seen - 1);
+                        }
+                        *id = Some(slug.clone().into());
+                        slug
+                    }
+                };
+                toc.push(TocEntry {
+                    level: *level as usize,
+                    text: text.trim().to_owned(),
+                    id,
+                });
+            }
+            _ => {}
+        }
+    }
+    (events, toc)
+}
+
+/// Link every heading to itself at its end, using the id it was given
+fn heading_anchors<'a>(events: Vec<Event<'a>>, anchors: &AnchorConfig) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut current = None;
+    for event in events {
+        match &event {
+            Event::Start(Tag::Heading { id, .. }) => current = id.clone(),
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(id) = current.take() {
+                    let mut html = String::from(" <a class=\"");
+                    let _ = escape_html(&mut html, &anchors.class);
+                    html.push_str("\" href=\"#");
+                    let _ = escape_href(&mut html, &id);
+                    html.push_str("\" aria-label=\"Permalink\">");
+                    let _ = escape_html(&mut html, &anchors.symbol);
+                    html.push_str("</a>");
+                    out.push(Event::InlineHtml(html.into()));
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+    out
+}
+
+/// Drop the waivers of decorative images, a `-` alt text or a `decorative`
+/// title, so they get the empty alt screen readers skip
+fn decorative_images(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut out: Vec<Event> = Vec::with_capacity(events.len());
+    for event in events {
+        match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) if title.as_ref() == assets::DECORATIVE_TITLE => {
+                out.push(Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title: CowStr::Borrowed(""),
+                    id,
+                }))
+            }
+            Event::End(TagEnd::Image)
+                if matches!(
+                    out.as_slice(),
+                    [.., Event::Start(Tag::Image { .. }), Event::Text(alt)]
+                        if alt.trim() == assets::DECORATIVE_ALT
+                ) =>
+            {
+                out.pop();
+                out.push(event);
+            }
+            event => out.push(event),
+        }
+    }
+    out
+}
+
+/// Replace the last space of each paragraph with a non-breaking one when the
+/// final word is short, so it never ends up alone on a line
+fn widows(mut events: Vec<Event<'_>>, max_length: usize) -> Vec<Event<'_>> {
+    let ends: Vec<usize> = events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| matches!(event, Event::End(TagEnd::Paragraph)))
+        .map(|(index, _)| index)
+        .collect();
+
+    for end in ends {
+        // Only the text directly before the end of the paragraph counts
+        let Some(Event::Text(text)) = end.checked_sub(1).and_then(|i| events.get(i)) else {
+            continue;
+        };
+        let trimmed = text.trim_end();
+        let Some(space) = trimmed.rfind(' ') else {
+            continue;
+        };
+        if trimmed[space + 1..].chars().count() > max_length {
+            continue;
+        }
+        let mut glued = String::with_capacity(text.len() + 1);
+        glued.push_str(&text[..space]);
+        glued.push('\u{a0}');
+        glued.push_str(&text[space + 1..]);
+        events[end - 1] = Event::Text(glued.into());
+    }
+    events
+}
+
+/// Pull markdown-extra style `*[ABBR]: Expansion` lines out of the content,
+/// leaving those in fenced code alone
+pub(crate) fn extract_abbreviations(content: &str) -> (String, HashMap<String, String>) {
+    let mut abbreviations = HashMap::new();
+    let mut remaining = String::with_capacity(content.len());
+    let mut in_fence = false;
+    for line in content.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let definition = line
+            .trim_end()
+            .strip_prefix("*[")
+            .and_then(|rest| rest.split_once("]:"))
+            .filter(|(abbreviation, _)| !in_fence && !abbreviation.is_empty());
+        match definition {
+            Some((abbreviation, expansion)) => {
+                abbreviations.insert(abbreviation.to_owned(), expansion.trim().to_owned());
+            }
+            None => remaining.push_str(line),
+        }
+    }
+    (remaining, abbreviations)
+}
+
+/// Split `text` around whole-word occurrences of any abbreviation, longest first
+fn split_abbreviations<'t>(
+    text: &'t str,
+    abbreviations: &[(&str, &'t str)],
+) -> Vec<(&'t str, Option<&'t str>)> {
+    let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    let mut pieces = Vec::new();
+    let mut plain_start = 0;
+    let mut index = 0;
+    while index < text.len() {
+        let before = text[..index].chars().next_back();
+        let found = (!is_word(before))
+            .then(|| {
+                abbreviations.iter().find(|(abbreviation, _)| {
+                    text[index..].starts_with(abbreviation)
+                        && !is_word(text[index + abbreviation.len()..].chars().next())
+                })
+            })
+            .flatten();
+        match found {
+            Some((abbreviation, title)) => {
+                pieces.push((&text[plain_start..index], None));
+                let end = index + abbreviation.len();
+                pieces.push((&text[index..end], Some(*title)));
+                plain_start = end;
+                index = end;
+            }
+            None => index += text[index..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    pieces.push((&text[plain_start..], None));
+    pieces
+}
+
+/// Wrap known abbreviations in `<abbr title="...">` outside of code and images
+fn abbreviations<'a>(events: Vec<Event<'a>>, page: &Page) -> Vec<Event<'a>> {
+    let mut merged: HashMap<&str, &str> = page
+        .config
+        .abbreviations
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    merged.extend(
+        page.abbreviations
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str())),
+    );
+    if merged.is_empty() {
+        return events;
+    }
+    let mut known: Vec<(&str, &str)> = merged.into_iter().collect();
+    known.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+
+    let mut out = Vec::with_capacity(events.len());
+    let mut skip_depth = 0usize;
+    for event in events {
+        match &event {
+            Event::Start(Tag::CodeBlock(_) | Tag::Image { .. }) => skip_depth += 1,
+            Event::End(TagEnd::CodeBlock | TagEnd::Image) => {
+                skip_depth = skip_depth.saturating_sub(1)
+            }
+            _ => {}
+        }
+        let Event::Text(text) = &event else {
+            out.push(event);
+            continue;
+        };
+        if skip_depth > 0 {
+            out.push(event);
+            continue;
+        }
+
+        let pieces = split_abbreviations(text, &known);
+        if pieces.len() == 1 {
+            out.push(event);
+            continue;
+        }
+        for (piece, title) in pieces {
+            match title {
+                Some(title) => {
+                    let mut open = String::from("<abbr title=\"");
+                    let _ = escape_html(&mut open, title);
+                    open.push_str("\">");
+                    out.push(Event::InlineHtml(open.into()));
+                    out.push(Event::Text(piece.to_owned().into()));
+                    out.push(Event::InlineHtml("</abbr>".into()));
+                }
+                None if piece.is_empty() => {}
+                None => out.push(Event::Text(piece.to_owned().into())),
+            }
+        }
+    }
+    out
+}
+
+/// Render footnotes as Tufte-style margin notes next to their reference. The
+/// footnote reference and definitions stay in place so themes can fall back to
+/// regular footnotes on narrow screens.
+fn sidenotes(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    // Render each definition's content to inline html first
+    let mut definitions: HashMap<String, String> = HashMap::new();
+    let mut current: Option<(String, Vec<Event>)> = None;
+    for event in &events {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                current = Some((label.to_string(), Vec::new()));
+            }
+            Event::End(TagEnd::FootnoteDefinition) => {
+                if let Some((label, inner)) = current.take() {
+                    let mut html = String::new();
+                    pulldown_cmark::html::push_html(&mut html, inner.into_iter());
+                    definitions.insert(label, html);
+                }
+            }
+            // Paragraphs cannot live inside the inline sidenote
+            Event::Start(Tag::Paragraph) | Event::End(TagEnd::Paragraph) => {}
+            event => {
+                if let Some((_, inner)) = current.as_mut() {
+                    inner.push(event.clone());
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(events.len());
+    let mut number = 0;
+    for event in events {
+        let sidenote = match &event {
+            Event::FootnoteReference(label) => definitions.get(label.as_ref()).map(|html| {
+                number += 1;
+                format!(
+                    "<label for=\"sn-{number}\" class=\"margin-toggle sidenote-number\"></label>\
+                     <input type=\"checkbox\" id=\"sn-{number}\" class=\"margin-toggle\" />\
+                     <span class=\"sidenote\">{}</span>",
+                    html.trim()
+                )
+            }),
+            _ => None,
+        };
+        out.push(event);
+        if let Some(sidenote) = sidenote {
+            out.push(Event::InlineHtml(sidenote.into()));
+        }
+    }
+    out
+}
+
+/// Split a fenced code block info string like `rust,playground` or
+/// `rust playground` into the language and its flags
+pub(crate) fn code_block_info(info: &str) -> (&str, Vec<&str>) {
+    let mut parts = info.split([',', ' ']).filter(|part| !part.is_empty());
+    let language = parts.next().unwrap_or("");
+    (language, parts.collect())
+}
+
+/// A fenced code block lifted out of the event stream
+struct CodeBlock<'a> {
+    language: &'a str,
+    flags: Vec<&'a str>,
+    code: String,
+}
+
+/// Replace fenced code blocks using `rewrite`, which returns the events to emit
+/// instead or `None` to leave the block untouched
+fn rewrite_code_blocks<'a, F>(events: Vec<Event<'a>>, mut rewrite: F) -> Vec<Event<'a>>
+where
+    F: FnMut(&CodeBlock) -> Option<Vec<Event<'a>>>,
+{
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = event else {
+            out.push(event);
+            continue;
+        };
+        let inner: Vec<Event> = events
+            .by_ref()
+            .take_while(|event| !matches!(event, Event::End(TagEnd::CodeBlock)))
+            .collect();
+        let (language, flags) = code_block_info(&info);
+        let block = CodeBlock {
+            language,
+            flags,
+            code: inner
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Text(text) => Some(text.as_ref()),
+                    _ => None,
+                })
+                .collect(),
+        };
+        match rewrite(&block) {
+            Some(replacement) => out.extend(replacement),
+            None => {
+                out.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))));
+                out.extend(inner);
+                out.push(Event::End(TagEnd::CodeBlock));
+            }
+        }
+    }
+    out
+}
+
+/// The code block as plain highlighted markup, with flags stripped from the language
+fn plain_code_block<'a>(block: &CodeBlock) -> Vec<Event<'a>> {
+    vec![
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
+            block.language.to_owned().into(),
+        ))),
+        Event::Text(block.code.clone().into()),
+        Event::End(TagEnd::CodeBlock),
+    ]
+}
+
+/// Link to run the code of a block in an online playground for its language
+fn playground_link(language: &str, code: &str) -> String {
+    match language {
+        "rust" | "rs" => format!(
+            "<a class=\"playground\" href=\"https://play.rust-lang.org/?version=stable&amp;mode=debug&amp;edition=2021&amp;code={}\">Run in the Rust Playground</a>",
+            urlencoding::encode(code)
+        ),
+        "html" | "css" | "js" | "javascript" => {
+            let key = match language {
+                "javascript" => "js",
+                other => other,
+            };
+            let mut data = String::new();
+            let _ = escape_html(&mut data, &serde_json::json!({ key: code }).to_string());
+            format!(
+                "<form class=\"playground\" action=\"https://codepen.io/pen/define\" method=\"POST\" target=\"_blank\">\
+                 <input type=\"hidden\" name=\"data\" value=\"{data}\" />\
+                 <button type=\"submit\">Open in CodePen</button></form>"
+            )
+        }
+        language => {
+            let state = serde_json::json!({
+                "sessions": [{ "id": 1, "language": language, "source": code, "compilers": [] }]
+            });
+            format!(
+                "<a class=\"playground\" href=\"https://godbolt.org/clientstate/{}\">Open in Compiler Explorer</a>",
+                urlencoding::encode(&base64::engine::general_purpose::STANDARD.encode(state.to_string()))
+            )
+        }
+    }
+}
+
+/// Follow code blocks flagged `playground` with a link that runs them online
+fn playgrounds(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    rewrite_code_blocks(events, |block| {
+        if !block.flags.contains(&"playground") {
+            return None;
+        }
+        let mut events = plain_code_block(block);
+        events.push(Event::Html(
+            format!(
+                "<p class=\"playground-link\">{}</p>\n",
+                playground_link(block.language, &block.code)
+            )
+            .into(),
+        ));
+        Some(events)
+    })
+}
+
+/// Wrap already escaped code in the `<pre><code>` markup pulldown-cmark emits,
+/// with an optional class on the `<pre>`
+fn code_block_html(class: Option<&str>, language: &str, code: &str) -> String {
+    let mut html = String::from("<pre");
+    if let Some(class) = class {
+        html.push_str(&format!(" class=\"{class}\""));
+    }
+    html.push_str("><code");
+    if !language.is_empty() {
+        html.push_str(" class=\"language-");
+        let _ = escape_html(&mut html, language);
+        html.push('"');
+    }
+    html.push('>');
+    html.push_str(code);
+    html.push_str("</code></pre>\n");
+    html
+}
+
+/// Class of a diff line from its leading marker
+fn diff_line_class(line: &str) -> &'static str {
+    match line.chars().next() {
+        Some('+') if !line.starts_with("+++") => "added",
+        Some('-') if !line.starts_with("---") => "removed",
+        Some('@') if line.starts_with("@@") => "hunk",
+        _ => "context",
+    }
+}
+
+/// Render `diff` blocks, and blocks of any language flagged `diff`, one span per
+/// line classed `added`, `removed`, `hunk` or `context`. In flagged blocks the
+/// gutter column is dropped so the code reads as the language itself.
+fn diffs(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    rewrite_code_blocks(events, |block| {
+        let patch = matches!(block.language, "diff" | "patch");
+        if !patch && !block.flags.contains(&"diff") {
+            return None;
+        }
+        let mut code = String::new();
+        for line in block.code.lines() {
+            let class = diff_line_class(line);
+            // The first column of a flagged block is the gutter
+            let line = match patch {
+                false if line.starts_with(['+', '-', ' ']) => &line[1..],
+                _ => line,
+            };
+            code.push_str(&format!("<span class=\"line {class}\">"));
+            let _ = escape_html(&mut code, line);
+            code.push_str("</span>\n");
+        }
+        Some(vec![Event::Html(
+            code_block_html(None, block.language, &code).into(),
+        )])
+    })
+}
+
+/// Render blocks of terminal output, in the `ansi` language or flagged `ansi`,
+/// with their ANSI colour escape codes turned into styled spans
+fn terminal_output(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    rewrite_code_blocks(events, |block| {
+        let language = match block.language {
+            "ansi" => "",
+            _ if block.flags.contains(&"ansi") => block.language,
+            _ => return None,
+        };
+        let code = ansi::to_html(&block.code);
+        Some(vec![Event::Html(
+            code_block_html(Some("terminal"), language, &code).into(),
+        )])
+    })
+}
+
+/// Embed the asciinema casts named by `asciinema` blocks
+fn casts<'a>(events: Vec<Event<'a>>, page: &Page) -> Vec<Event<'a>> {
+    let mut first = true;
+    rewrite_code_blocks(events, |block| {
+        if block.language != "asciinema" {
+            return None;
+        }
+        let url = block.code.trim();
+        let html = asciinema::embed(
+            &page.href(url),
+            page.source_of(url).as_deref(),
+            page.config.asciinema.as_ref(),
+            page.config,
+            first,
+        );
+        first = false;
+        Some(vec![Event::Html(html.into())])
+    })
+}
+
+/// Drop flags like `verify` from the info string of the remaining code blocks so
+/// only the language ends up in the `language-*` class
+fn strip_code_block_flags(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let (language, _) = code_block_info(&info);
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
+                    language.to_owned().into(),
+                )))
+            }
+            event => event,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abbreviations_in_fenced_code_are_kept() {
+        let content = "*[HTML]: Hyper Text Markup Language\n\
+                       ```markdown\n*[CSS]: Cascading Style Sheets\n```\n\
+                       HTML and CSS\n";
+        let (remaining, abbreviations) = extract_abbreviations(content);
+        assert_eq!(
+            remaining,
+            "```markdown\n*[CSS]: Cascading Style Sheets\n```\nHTML and CSS\n"
+        );
+        assert_eq!(abbreviations.len(), 1);
+        assert_eq!(abbreviations["HTML"], "Hyper Text Markup Language");
+    }
+}
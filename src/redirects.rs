@@ -0,0 +1,125 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{config::Config, feed::escape_xml, output::Output, types::AvailableContent};
+
+const REDIRECTS_JSON: &str = "redirects.json";
+
+/// Old paths of a post from its `aliases` front matter, a list or a single path
+fn aliases(value: Option<&serde_json::Value>) -> Vec<&str> {
+    match value {
+        Some(serde_json::Value::String(alias)) => vec![alias.as_str()],
+        Some(serde_json::Value::Array(aliases)) => {
+            aliases.iter().filter_map(|alias| alias.as_str()).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Output file answering for an alias of the post at `src`, the alias itself
+/// when it names a file and its `index.html` when it is a directory. Aliases
+/// leading out of the output are refused.
+fn stub_path(alias: &str, src: &Path) -> Result<PathBuf> {
+    let relative = Path::new(alias.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        bail!("Alias [{alias}] of [{src:?}] leads out of the output");
+    }
+    Ok(
+        match alias.ends_with('/') || relative.extension().is_none() {
+            true => relative.join("index.html"),
+            false => relative.to_path_buf(),
+        },
+    )
+}
+
+fn stub(target: &str) -> String {
+    let target = escape_xml(target);
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Redirecting</title>\
+         <link rel=\"canonical\" href=\"{target}\">\
+         <meta http-equiv=\"refresh\" content=\"0; url={target}\"></head>\
+         <body><a href=\"{target}\">{target}</a></body></html>\n"
+    )
+}
+
+/// Queue a redirecting page at every alias of the published posts, and the
+/// whole mapping from old to current root-relative URLs as `redirects.json`
+/// for edge workers and hosts with their own redirect rules
+pub(crate) fn write(content: &AvailableContent, config: &Config, output: &Output) -> Result<()> {
+    let pages: HashSet<&Path> = content
+        .content
+        .iter()
+        .filter(|content| content.publish)
+        .map(|content| content.location.path.as_path())
+        .collect();
+
+    let mut redirects: BTreeMap<String, &str> = BTreeMap::new();
+    for content in content.content.iter().filter(|content| content.publish) {
+        let target = content.location.href.as_str();
+        for alias in aliases(content.post.metadata.extra.get("aliases")) {
+            let path = stub_path(alias, &content.location.src)?;
+            if pages.contains(path.as_path()) {
+                bail!(
+                    "Alias [{alias}] of [{:?}] points at an existing page",
+                    content.location.src
+                );
+            }
+            if let Some(other) = redirects.insert(config.site_url(alias), target) {
+                if other != target {
+                    bail!("Alias [{alias}] is claimed by both [{other}] and [{target}]");
+                }
+            }
+            output.add(config.output.join(path), stub(target))?;
+        }
+    }
+
+    if redirects.is_empty() {
+        return Ok(());
+    }
+    output.add(
+        config.output.join(REDIRECTS_JSON),
+        serde_json::to_vec_pretty(&redirects)?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stubs_files_and_directories() {
+        let src = Path::new("content/post.md");
+        let stub = |alias| stub_path(alias, src).unwrap();
+        assert_eq!(stub("/old/post.html"), Path::new("old/post.html"));
+        assert_eq!(stub("/old/post/"), Path::new("old/post/index.html"));
+        assert_eq!(stub("old/post"), Path::new("old/post/index.html"));
+    }
+
+    #[test]
+    fn refuses_aliases_leading_out_of_the_output() {
+        let src = Path::new("content/post.md");
+        for alias in ["../../x", "/old/../../x.html", "old/../x/"] {
+            let error = stub_path(alias, src).unwrap_err().to_string();
+            assert!(
+                error.contains(alias) && error.contains("post.md"),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn reads_one_or_many_aliases() {
+        let one = serde_json::json!("/old/");
+        let many = serde_json::json!(["/a/", "/b/", 3]);
+        assert_eq!(aliases(Some(&one)), ["/old/"]);
+        assert_eq!(aliases(Some(&many)), ["/a/", "/b/"]);
+        assert!(aliases(None).is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Result;
+use minijinja::Environment;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// A Rhai script customising the build, needs mub built with the `scripts` feature
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptConfig {
+    pub(crate) path: PathBuf,
+    /// Script functions added as template filters under their own names, called
+    /// with the filtered value followed by the filter arguments
+    #[serde(default)]
+    pub(crate) filters: Vec<String>,
+    /// Pass the front matter of every post through the script's
+    /// `metadata(front_matter)` function, which returns it changed
+    #[serde(default)]
+    pub(crate) metadata: bool,
+}
+
+#[cfg(feature = "scripts")]
+mod rhai_scripts {
+    use std::{collections::HashMap, sync::Arc};
+
+    use anyhow::{anyhow, Result};
+    use minijinja::{value::Rest, Environment, Error, ErrorKind, Value};
+    use rhai::{Dynamic, Engine, Scope, AST};
+
+    use super::ScriptConfig;
+
+    /// Operations a single script call may run before it is stopped
+    const MAX_OPERATIONS: u64 = 100_000_000;
+
+    #[derive(Debug)]
+    pub(super) struct Script {
+        config: ScriptConfig,
+        engine: Engine,
+        ast: AST,
+    }
+
+    impl Script {
+        pub(super) fn load(config: &ScriptConfig) -> Result<Self> {
+            let mut engine = Engine::new();
+            engine.set_max_operations(MAX_OPERATIONS);
+            let ast = engine
+                .compile_file(config.path.clone())
+                .map_err(|e| anyhow!("Unable to compile script [{:?}]: {e}", config.path))?;
+            Ok(Self {
+                config: config.clone(),
+                engine,
+                ast,
+            })
+        }
+
+        /// Call the script function `name`, converting through serde both ways
+        fn call<T: serde::de::DeserializeOwned>(
+            &self,
+            name: &str,
+            args: Vec<Dynamic>,
+        ) -> Result<T> {
+            let path = &self.config.path;
+            let result: Dynamic = self
+                .engine
+                .call_fn(&mut Scope::new(), &self.ast, name, args)
+                .map_err(|e| anyhow!("Script [{path:?}] failed in `{name}`: {e}"))?;
+            rhai::serde::from_dynamic(&result).map_err(|e| {
+                anyhow!("Script [{path:?}] returned an unusable value from `{name}`: {e}")
+            })
+        }
+
+        pub(super) fn metadata(
+            &self,
+            front_matter: HashMap<String, serde_json::Value>,
+        ) -> Result<HashMap<String, serde_json::Value>> {
+            if !self.config.metadata {
+                return Ok(front_matter);
+            }
+            let front_matter = rhai::serde::to_dynamic(front_matter).map_err(|e| anyhow!("{e}"))?;
+            self.call("metadata", vec![front_matter])
+        }
+
+        pub(super) fn register(self: &Arc<Self>, env: &mut Environment) {
+            for name in &self.config.filters {
+                let script = self.clone();
+                let function = name.clone();
+                env.add_filter(name.clone(), move |value: Value, args: Rest<Value>| {
+                    let error = |e: String| Error::new(ErrorKind::InvalidOperation, e);
+                    let args = std::iter::once(value)
+                        .chain(args.0)
+                        .map(|arg| rhai::serde::to_dynamic(arg).map_err(|e| error(e.to_string())))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    script
+                        .call::<serde_json::Value>(&function, args)
+                        .map(Value::from_serialize)
+                        .map_err(|e| error(format!("{e:#}")))
+                });
+            }
+        }
+    }
+}
+
+/// Scripts of the config, loaded and compiled
+#[derive(Debug, Default)]
+pub(crate) struct Scripts {
+    #[cfg(feature = "scripts")]
+    loaded: Vec<std::sync::Arc<rhai_scripts::Script>>,
+}
+
+impl Scripts {
+    #[cfg(feature = "scripts")]
+    pub(crate) fn load(config: &Config) -> Result<Self> {
+        let loaded = config
+            .scripts
+            .iter()
+            .map(|script| rhai_scripts::Script::load(script).map(std::sync::Arc::new))
+            .collect::<Result<_>>()?;
+        Ok(Self { loaded })
+    }
+
+    #[cfg(not(feature = "scripts"))]
+    pub(crate) fn load(config: &Config) -> Result<Self> {
+        if !config.scripts.is_empty() {
+            anyhow::bail!("Scripts are configured but mub was built without the `scripts` feature");
+        }
+        Ok(Self::default())
+    }
+
+    /// Whether no script is loaded
+    pub(crate) fn is_empty(&self) -> bool {
+        #[cfg(feature = "scripts")]
+        return self.loaded.is_empty();
+        #[cfg(not(feature = "scripts"))]
+        true
+    }
+
+    /// Post front matter, through every script transforming it
+    pub(crate) fn metadata(
+        &self,
+        front_matter: HashMap<String, serde_json::Value>,
+    ) -> Result<HashMap<String, serde_json::Value>> {
+        #[cfg(feature = "scripts")]
+        let front_matter = self
+            .loaded
+            .iter()
+            .try_fold(front_matter, |front_matter, script| {
+                script.metadata(front_matter)
+            })?;
+        Ok(front_matter)
+    }
+
+    /// Add the template filters of the scripts to `env`
+    pub(crate) fn register(&self, _env: &mut Environment) {
+        #[cfg(feature = "scripts")]
+        for script in &self.loaded {
+            script.register(_env);
+        }
+    }
+}
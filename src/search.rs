@@ -0,0 +1,248 @@
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+
+use crate::{
+    config::Config,
+    feed,
+    output::Output,
+    types::{AvailableContent, Content},
+};
+
+const SEARCH_INDEX: &str = "search-index.json";
+
+/// Directory of the output the pagefind bundle is written to
+const PAGEFIND_DIR: &str = "pagefind";
+
+/// Fields of `search-index.json` indexed when `fields` isn't set
+const DEFAULT_FIELDS: [SearchField; 5] = [
+    SearchField::Path,
+    SearchField::Url,
+    SearchField::Title,
+    SearchField::Date,
+    SearchField::Text,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchField {
+    /// Root-relative URL of the post
+    Path,
+    /// Absolute URL of the post, the same as `path` without a `base_url`
+    Url,
+    Title,
+    Date,
+    /// Section of the post, empty for top level posts
+    Section,
+    /// The `description` front matter, empty when not set
+    Description,
+    /// Plain text of the post, cut down to `text_length`
+    Text,
+}
+
+/// Shape of the search index, for the client-side engine that loads it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchFormat {
+    /// An array of the posts with their `fields`
+    #[default]
+    Json,
+    /// A prebuilt elasticlunr.js index of the `fields` besides `path` and
+    /// `url`, referencing posts by their `path`, for `elasticlunr.Index.load`
+    Elasticlunr,
+    /// The `title`, `url` and `body` of every post that `tinysearch
+    /// search-index.json` builds its WebAssembly index from
+    Tinysearch,
+    /// No `search-index.json`, the `pagefind` CLI indexes the written pages
+    /// into `pagefind/` of the output after every build
+    Pagefind,
+}
+
+/// What `search-index.json` holds of every published post
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Fields of every post, in the order they are written. `path`, `url`,
+    /// `title`, `date` and `text` when not set
+    #[serde(default)]
+    pub(crate) fields: Option<Vec<SearchField>>,
+    /// Client-side engine the index is written for, `json` by default
+    #[serde(default)]
+    pub(crate) format: SearchFormat,
+    /// Characters of post text kept, cut at a word with `…` like feed summaries.
+    /// The whole text when not set
+    #[serde(default)]
+    pub(crate) text_length: Option<usize>,
+    /// Indent the index for reading, it is minified by default
+    #[serde(default)]
+    pub(crate) pretty: bool,
+}
+
+impl SearchField {
+    fn value(self, content: &Content, search: &SearchConfig) -> String {
+        let post = &content.post;
+        match self {
+            SearchField::Path => content.location.href.clone(),
+            SearchField::Url => content.location.canonical.clone(),
+            SearchField::Title => post.metadata.title.clone(),
+            SearchField::Date => post.metadata.date.to_string(),
+            SearchField::Section => content.section.clone().unwrap_or_default(),
+            SearchField::Description => post
+                .metadata
+                .extra_str("description")
+                .unwrap_or_default()
+                .to_owned(),
+            SearchField::Text => {
+                let text = post.text.as_deref().unwrap_or(&post.raw);
+                match search.text_length {
+                    Some(length) => feed::summary(text, length),
+                    None => text.to_owned(),
+                }
+            }
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            SearchField::Path => "path",
+            SearchField::Url => "url",
+            SearchField::Title => "title",
+            SearchField::Date => "date",
+            SearchField::Section => "section",
+            SearchField::Description => "description",
+            SearchField::Text => "text",
+        }
+    }
+}
+
+/// A post in the index, its fields in the configured order
+struct Doc<'a> {
+    fields: &'a [SearchField],
+    values: Vec<String>,
+}
+
+impl Serialize for Doc<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.fields.len()))?;
+        for (field, value) in self.fields.iter().zip(&self.values) {
+            map.serialize_entry(field.key(), value)?;
+        }
+        map.end()
+    }
+}
+
+/// A post in the shape `tinysearch` reads
+#[derive(Serialize)]
+struct TinysearchPost<'a> {
+    title: &'a str,
+    url: &'a str,
+    body: String,
+}
+
+fn encode<T: Serialize>(index: &T, pretty: bool) -> Result<Vec<u8>> {
+    Ok(match pretty {
+        true => serde_json::to_vec_pretty(index)?,
+        false => serde_json::to_vec(index)?,
+    })
+}
+
+/// Queue `search-index.json` with the published posts in the configured
+/// format, leaving out posts with `search: false` in their front matter
+pub(crate) fn write(content: &AvailableContent, config: &Config, output: &Output) -> Result<()> {
+    if !config.search {
+        return Ok(());
+    }
+
+    let search = &config.search_index;
+    let fields = search.fields.as_deref().unwrap_or(&DEFAULT_FIELDS);
+    let published: Vec<&Content> = content
+        .content
+        .iter()
+        .filter(|content| {
+            content.publish
+                && !content.draft
+                && !content.bare
+                && content.post.metadata.extra_bool("search") != Some(false)
+        })
+        .collect();
+
+    let index = match search.format {
+        SearchFormat::Json => {
+            let docs: Vec<Doc> = published
+                .par_iter()
+                .map(|content| Doc {
+                    fields,
+                    values: fields
+                        .iter()
+                        .map(|field| field.value(content, search))
+                        .collect(),
+                })
+                .collect();
+            encode(&docs, search.pretty)?
+        }
+        SearchFormat::Elasticlunr => {
+            let indexed: Vec<SearchField> = fields
+                .iter()
+                .copied()
+                .filter(|field| !matches!(field, SearchField::Path | SearchField::Url))
+                .collect();
+            if indexed.is_empty() {
+                bail!("The elasticlunr search index needs a field besides `path` and `url`");
+            }
+            let mut index = elasticlunr::Index::new(indexed.iter().map(|field| field.key()));
+            for content in &published {
+                index.add_doc(
+                    &content.location.href,
+                    indexed.iter().map(|field| field.value(content, search)),
+                );
+            }
+            encode(&index, search.pretty)?
+        }
+        SearchFormat::Tinysearch => {
+            let posts: Vec<TinysearchPost> = published
+                .par_iter()
+                .map(|content| TinysearchPost {
+                    title: &content.post.metadata.title,
+                    url: &content.location.canonical,
+                    body: SearchField::Text.value(content, search),
+                })
+                .collect();
+            encode(&posts, search.pretty)?
+        }
+        SearchFormat::Pagefind => return Ok(()),
+    };
+    output.add(config.output.join(SEARCH_INDEX), index)
+}
+
+/// Index the written pages with the `pagefind` CLI when it is the search
+/// format, once nothing else is left to write but the manifest
+pub(crate) fn pagefind(config: &Config) -> Result<()> {
+    if !config.search || config.search_index.format != SearchFormat::Pagefind {
+        return Ok(());
+    }
+    let bundle = config.output.join(PAGEFIND_DIR);
+    let mut command = Command::new("pagefind");
+    command
+        .arg("--site")
+        .arg(&config.output)
+        .arg("--output-path")
+        .arg(&bundle);
+    let output = config.cancel.output(&mut command, None).with_context(|| {
+        anyhow!(
+            "Unable to run pagefind to index [{:?}], is it installed?",
+            config.output
+        )
+    })?;
+    if !output.status.success() {
+        bail!(
+            "Unable to index [{:?}] with pagefind: {}",
+            config.output,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    tracing::debug!(bundle = %bundle.display(), "Indexed pages with pagefind");
+    Ok(())
+}
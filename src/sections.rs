@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use minijinja::{context, Environment};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collation::Collation,
+    config::Config,
+    output::Output,
+    types::{AvailableContent, BuildInfo, Content, PageUrl},
+};
+
+fn default_index() -> String {
+    String::from("section.html")
+}
+
+/// Templates and title of a content subdirectory, named by its path below `content/`
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SectionConfig {
+    /// Title of the section, its name when not set
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    /// Template of the section's posts that don't name their own
+    #[serde(default)]
+    pub(crate) template: Option<String>,
+    /// Template of the section index page
+    #[serde(default = "default_index")]
+    pub(crate) index: String,
+}
+
+/// A content subdirectory, its posts written under the same path in the output
+#[derive(Debug, Serialize)]
+pub(crate) struct Section {
+    /// Path below `content/`, `/` separated
+    pub(crate) name: String,
+    pub(crate) title: String,
+    /// Output file of the index page, relative to the output root
+    pub(crate) path: PathBuf,
+    /// URL of the index page, relative to the output root and shaped by `link_style`
+    pub(crate) url: String,
+    /// Root-relative URL of the index page, including the deployment prefix
+    pub(crate) href: String,
+    /// Whether an index page is rendered, only for configured sections
+    pub(crate) index: bool,
+}
+
+/// Every section that holds content, in name order of the site's collation
+pub(crate) fn collect(content: &[Content], config: &Config, collation: &Collation) -> Vec<Section> {
+    let mut names: Vec<&str> = content
+        .iter()
+        .filter_map(|content| content.section.as_deref())
+        .collect();
+    names.sort_by(|a, b| collation.compare(a, b));
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| {
+            let settings = config.sections.get(name);
+            let (path, url) = config.link_style().index(name);
+            Section {
+                name: name.to_owned(),
+                title: settings
+                    .and_then(|settings| settings.title.clone())
+                    .unwrap_or_else(|| name.to_owned()),
+                path,
+                href: config.site_url(&url),
+                url,
+                index: settings.is_some(),
+            }
+        })
+        .collect()
+}
+
+/// Render the index page of every configured section, with its published posts
+pub(crate) fn render(
+    content: &AvailableContent,
+    templates: &Environment,
+    config: &Config,
+    build: &BuildInfo,
+    output: &Output,
+) -> Result<()> {
+    for section in content.sections.iter().filter(|section| section.index) {
+        let template = &config.sections[&section.name].index;
+        let posts: Vec<&Content> = content
+            .content
+            .iter()
+            .filter(|content| content.publish && content.section.as_ref() == Some(&section.name))
+            .collect();
+
+        let context = context!(
+            data => content,
+            section => section,
+            posts => posts,
+            page => PageUrl::new(&section.href, config),
+            build => build,
+            ..context!(config)
+        );
+        let mut rendered = templates
+            .get_template(template)?
+            .render(context)
+            .with_context(|| anyhow!("Unable to render the section [{}]", section.name))?;
+        if config.stamp {
+            rendered.push_str(&build.stamp());
+        }
+        tracing::debug!(template, section = section.name, "Rendered section index");
+        output.add(config.output.join(&section.path), rendered)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,520 @@
+use std::{
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use tiny_http::{Header, Response, Server};
+
+use crate::{
+    cache,
+    cancel::Cancel,
+    config::{Config, Overrides},
+    daemon::DaemonConfig,
+    hooks::{self, HooksConfig},
+};
+
+/// How often the input directory is scanned for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Port `mub serve` listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 8000;
+
+/// Path of the event stream telling pages to reload, under the site's prefix
+const RELOAD_PATH: &str = "/__mub/reload";
+
+/// Directory of the cache the build with drafts is shared from
+pub(crate) const SHARED_DRAFTS_DIR: &str = "shared-drafts";
+
+/// Environment variable with the `user:password` asked of readers of shared drafts
+pub const DRAFTS_CREDENTIALS_VAR: &str = "MUB_DRAFTS_CREDENTIALS";
+
+/// Count of finished builds, waited on by the pages being served
+#[derive(Default)]
+struct Builds {
+    count: Mutex<u64>,
+    finished: Condvar,
+}
+
+impl Builds {
+    fn finish(&self) {
+        if let Ok(mut count) = self.count.lock() {
+            *count += 1;
+            self.finished.notify_all();
+        }
+    }
+
+    /// Block until the next build is done
+    fn wait(&self) {
+        let Ok(count) = self.count.lock() else {
+            return;
+        };
+        let started = *count;
+        drop(self.finished.wait_while(count, |count| *count == started));
+    }
+}
+
+/// Answer the reload stream of a page once the site is rebuilt, for the page
+/// to reload itself
+fn stream_reload(request: tiny_http::Request, builds: &Builds) -> Result<()> {
+    builds.wait();
+    let header = Header::from_bytes("Content-Type", "text/event-stream")
+        .map_err(|_| anyhow!("Invalid content type header"))?;
+    request.respond(Response::from_string("data: reload\n\n").with_header(header))?;
+    Ok(())
+}
+
+/// `html` with a script reloading it once the site served under `prefix` is
+/// rebuilt added at the end of its body
+fn inject_reload(html: Vec<u8>, prefix: &str) -> Vec<u8> {
+    let mut html = match String::from_utf8(html) {
+        Ok(html) => html,
+        Err(e) => return e.into_bytes(),
+    };
+    let script = format!(
+        "<script>new EventSource(\"{prefix}{RELOAD_PATH}\").onmessage = () => location.reload();</script>"
+    );
+    match html.rfind("</body>") {
+        Some(end) => html.insert_str(end, &script),
+        None => html.push_str(&script),
+    }
+    html.into_bytes()
+}
+
+/// Content type of a served file from its extension
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        Some("pdf") => "application/pdf",
+        Some("mp3") => "audio/mpeg",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Modification time and size of every file that goes into the build, so a
+/// change to any of them shows up as a different fingerprint
+pub(crate) fn fingerprint(dir: &Path, skip: &[&Path], files: &mut Vec<(PathBuf, SystemTime, u64)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        // Paths under `.` compare unequal to the same paths without the `./`
+        let relative = path.strip_prefix(".").unwrap_or(&path);
+        if skip
+            .iter()
+            .any(|skip| skip.strip_prefix(".").unwrap_or(skip) == relative)
+            || entry.file_name() == ".git"
+        {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            fingerprint(&path, skip, files);
+        } else if let Ok(modified) = metadata.modified() {
+            files.push((path, modified, metadata.len()));
+        }
+    }
+}
+
+/// Where a site is built from and into, and when to rebuild it, all the
+/// server and daemon need from its config
+pub(crate) struct Directories {
+    pub(crate) input: PathBuf,
+    /// Theme directory, watched too as it may live outside of the input
+    pub(crate) theme: Option<PathBuf>,
+    pub(crate) output: PathBuf,
+    /// Path the site is served under from its `base_url`, empty at the root
+    pub(crate) prefix: String,
+    pub(crate) cache: PathBuf,
+    pub(crate) schedule: Option<DaemonConfig>,
+    pub(crate) hooks: Option<HooksConfig>,
+}
+
+/// Sources the site is built from: the input and theme directories and the config file
+fn sources(config_path: &Path, dirs: &Directories) -> Vec<(PathBuf, SystemTime, u64)> {
+    let mut files = Vec::new();
+    fingerprint(&dirs.input, &[&dirs.output, &dirs.cache], &mut files);
+    if let Some(theme) = &dirs.theme {
+        fingerprint(theme, &[&dirs.output, &dirs.cache], &mut files);
+    }
+    if let Ok(metadata) = std::fs::metadata(config_path) {
+        if let Ok(modified) = metadata.modified() {
+            files.push((config_path.to_path_buf(), modified, metadata.len()));
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Load the config and build the site, reporting failures instead of returning
+/// them so the server keeps running while the site is broken. `None` when the
+/// config itself can't be loaded.
+pub(crate) fn build(
+    config_path: &Path,
+    overrides: &Overrides,
+    cancel: &Cancel,
+) -> Option<Directories> {
+    let config = match Config::try_load(config_path) {
+        Ok(config) => config.with_overrides(overrides).with_cancel(cancel.clone()),
+        Err(e) => {
+            tracing::error!("Unable to load config [{config_path:?}]: {e:#}");
+            return None;
+        }
+    };
+    let dirs = Directories {
+        input: config.input.clone(),
+        theme: config.theme.as_ref().map(|theme| config.input.join(theme)),
+        output: config.output.clone(),
+        prefix: config.path_prefix().to_owned(),
+        cache: cache::dir(&config),
+        schedule: config.daemon.clone(),
+        hooks: config.hooks.clone(),
+    };
+    match crate::generate(config) {
+        Ok(report) => tracing::info!("Built [{}]: {report}", dirs.output.display()),
+        Err(crate::Error::Cancelled(_)) => tracing::info!("Build cancelled, starting over"),
+        Err(e) => tracing::error!("Build failed: {e:#}"),
+    }
+    Some(dirs)
+}
+
+/// Build like `build`, cancelling the build and starting over whenever `stale`
+/// says what it is building from changed before it is done
+pub(crate) fn build_fresh(
+    config_path: &Path,
+    overrides: &Overrides,
+    mut stale: impl FnMut() -> bool,
+) -> Option<Directories> {
+    loop {
+        let cancel = Cancel::new();
+        let built = thread::scope(|scope| {
+            let (done, finished) = mpsc::channel();
+            let cancel = &cancel;
+            scope.spawn(move || {
+                let _ = done.send(build(config_path, overrides, cancel));
+            });
+            loop {
+                match finished.recv_timeout(POLL_INTERVAL) {
+                    Ok(built) => return built,
+                    Err(RecvTimeoutError::Timeout) if stale() => cancel.cancel(),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // The build panicked
+                    Err(RecvTimeoutError::Disconnected) => return None,
+                }
+            }
+        });
+        if !cancel.is_cancelled() {
+            return built;
+        }
+    }
+}
+
+/// A built site as it is served, its output under the path of its `base_url`
+struct Site {
+    output: PathBuf,
+    /// Path the pages link each other under, empty at the root
+    prefix: String,
+}
+
+impl Site {
+    /// Whether the request is for the event stream telling pages to reload
+    fn is_reload(&self, request: &tiny_http::Request) -> bool {
+        request
+            .url()
+            .strip_prefix(self.prefix.as_str())
+            .is_some_and(|path| path == RELOAD_PATH)
+    }
+
+    /// File in the output directory a request path refers to
+    fn resolve(&self, url: &str) -> Option<PathBuf> {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        let path = urlencoding::decode(path).ok()?;
+        let path = path.strip_prefix(self.prefix.as_str())?;
+        if !(path.is_empty() || path.starts_with('/')) {
+            return None;
+        }
+        let mut file = self.output.clone();
+        for component in Path::new(path.trim_start_matches('/')).components() {
+            match component {
+                Component::Normal(part) => file.push(part),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        if file.is_dir() {
+            file.push("index.html");
+        } else if !file.exists() && file.extension().is_none() {
+            file.set_extension("html");
+        }
+        file.is_file().then_some(file)
+    }
+
+    /// Serve a request from the output directory, with `404.html` for anything
+    /// missing, adding the reload script to pages when `reload` is on. The
+    /// root redirects to the prefix the site is served under.
+    fn respond(&self, request: tiny_http::Request, reload: bool) -> Result<()> {
+        let path = request.url().split(['?', '#']).next().unwrap_or_default();
+        if !self.prefix.is_empty() && path == "/" {
+            let header = Header::from_bytes("Location", format!("{}/", self.prefix))
+                .map_err(|_| anyhow!("Invalid location header"))?;
+            request.respond(Response::empty(302).with_header(header))?;
+            return Ok(());
+        }
+        let (status, file) = match self.resolve(request.url()) {
+            Some(file) => (200, Some(file)),
+            None => (
+                404,
+                Some(self.output.join("404.html")).filter(|file| file.is_file()),
+            ),
+        };
+        let response = match file {
+            Some(file) => {
+                let mut body = std::fs::read(&file)?;
+                let content_type = content_type(&file);
+                if reload && content_type.starts_with("text/html") {
+                    body = inject_reload(body, &self.prefix);
+                }
+                let header = Header::from_bytes("Content-Type", content_type)
+                    .map_err(|_| anyhow!("Invalid content type header"))?;
+                Response::from_data(body)
+                    .with_status_code(status)
+                    .with_header(header)
+            }
+            None => Response::from_data(b"Not found".to_vec()).with_status_code(404),
+        };
+        request.respond(response)?;
+        Ok(())
+    }
+}
+
+/// The `user:password` readers of shared drafts log in with
+fn drafts_credentials() -> Result<String> {
+    std::env::var(DRAFTS_CREDENTIALS_VAR)
+        .ok()
+        .filter(|credentials| credentials.contains(':'))
+        .with_context(|| {
+            anyhow!("Sharing drafts needs [{DRAFTS_CREDENTIALS_VAR}] set to `user:password`")
+        })
+}
+
+/// Whether the request logs in with `credentials` over HTTP basic auth
+fn logged_in(request: &tiny_http::Request, credentials: &str) -> bool {
+    request.headers().iter().any(|header| {
+        header.field.equiv("Authorization")
+            && header
+                .value
+                .as_str()
+                .strip_prefix("Basic ")
+                .and_then(|encoded| {
+                    base64::engine::general_purpose::STANDARD
+                        .decode(encoded.trim())
+                        .ok()
+                })
+                .and_then(|decoded| String::from_utf8(decoded).ok())
+                .is_some_and(|given| hooks::same(&given, credentials))
+    })
+}
+
+/// Serve the build with drafts on `address` to those logging in with
+/// `credentials`, asking everyone else to log in
+fn share_drafts(
+    address: SocketAddr,
+    site: Site,
+    credentials: String,
+    builds: Arc<Builds>,
+    reload: bool,
+) -> Result<()> {
+    let server =
+        Server::http(address).map_err(|e| anyhow!("Unable to listen on [{address}]: {e}"))?;
+    tracing::info!(
+        "Sharing drafts [{}] on http://{address}{}/",
+        site.output.display(),
+        site.prefix
+    );
+    let (site, credentials) = (Arc::new(site), Arc::<str>::from(credentials));
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let site = site.clone();
+            let credentials = credentials.clone();
+            let builds = builds.clone();
+            thread::spawn(move || {
+                let responded = if !logged_in(&request, &credentials) {
+                    Header::from_bytes("WWW-Authenticate", "Basic realm=\"mub drafts\"")
+                        .map_err(|_| anyhow!("Invalid authenticate header"))
+                        .and_then(|header| {
+                            let response = Response::from_string("Unauthorized")
+                                .with_status_code(401)
+                                .with_header(header);
+                            Ok(request.respond(response)?)
+                        })
+                } else if reload && site.is_reload(&request) {
+                    stream_reload(request, &builds)
+                } else {
+                    site.respond(request, reload)
+                };
+                if let Err(e) = responded {
+                    tracing::warn!("Unable to respond: {e:#}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Build the site, serve its output over HTTP on `port` and rebuild whenever
+/// the config or anything in the input directory changes, or the rebuild hook
+/// is called. With `reload` the pages served reload themselves after every
+/// rebuild. With `drafts` a build with the unpublished posts too is served on
+/// that address, behind the basic auth login in `MUB_DRAFTS_CREDENTIALS`.
+pub fn serve(
+    config_path: &Path,
+    overrides: &Overrides,
+    port: u16,
+    reload: bool,
+    drafts: Option<SocketAddr>,
+) -> Result<()> {
+    let credentials = drafts.map(|_| drafts_credentials()).transpose()?;
+    let dirs = build(config_path, overrides, &Cancel::new())
+        .context("Unable to load the config to serve")?;
+    let site = Arc::new(Site {
+        output: dirs.output.clone(),
+        prefix: dirs.prefix.clone(),
+    });
+    // Drafts are built into the cache, which changes to it never trigger rebuilds
+    let shared = drafts.map(|_| Overrides {
+        output: Some(dirs.cache.join(SHARED_DRAFTS_DIR)),
+        drafts: true,
+        ..overrides.clone()
+    });
+    if let Some(shared) = &shared {
+        build(config_path, shared, &Cancel::new());
+    }
+    let secret: Option<Arc<str>> = dirs
+        .hooks
+        .as_ref()
+        .map(|hooks| hooks.secret().map(Arc::from))
+        .transpose()?;
+
+    let (rebuild, requested) = mpsc::channel();
+    let watched = config_path.to_path_buf();
+    let overrides = overrides.clone();
+    let builds = Arc::new(Builds::default());
+    if let (Some(address), Some(shared), Some(credentials)) = (drafts, &shared, credentials) {
+        let drafts = Site {
+            output: shared.output.clone().unwrap_or_default(),
+            prefix: dirs.prefix.clone(),
+        };
+        share_drafts(address, drafts, credentials, builds.clone(), reload)?;
+    }
+    let built = builds.clone();
+    thread::spawn(move || {
+        let mut dirs = dirs;
+        let mut last = sources(&watched, &dirs);
+        loop {
+            let hooked = match requested.recv_timeout(POLL_INTERVAL) {
+                Ok(()) => true,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            let mut current = sources(&watched, &dirs);
+            if hooked || current != last {
+                // Hook calls made before this build are all served by it
+                while requested.try_recv().is_ok() {}
+                // Changes made while building make the build stale
+                let mut stale = || {
+                    let now = sources(&watched, &dirs);
+                    let changed = now != current;
+                    current = now;
+                    changed
+                };
+                let rebuilt = build_fresh(&watched, &overrides, &mut stale);
+                if let Some(shared) = &shared {
+                    build_fresh(&watched, shared, &mut stale);
+                }
+                if let Some(rebuilt) = rebuilt {
+                    dirs = rebuilt;
+                }
+                last = current;
+                built.finish();
+            }
+        }
+    });
+
+    let address = format!("127.0.0.1:{port}");
+    let server =
+        Server::http(&address).map_err(|e| anyhow!("Unable to listen on [{address}]: {e}"))?;
+    tracing::info!(
+        "Serving [{}] on http://{address}{}/",
+        site.output.display(),
+        site.prefix
+    );
+    for request in server.incoming_requests() {
+        let site = site.clone();
+        let secret = secret.clone();
+        let rebuild = rebuild.clone();
+        let builds = builds.clone();
+        thread::spawn(move || {
+            let responded = match secret {
+                Some(secret) if hooks::is_hook(&request) => {
+                    hooks::handle(request, &secret, &rebuild)
+                }
+                _ if reload && site.is_reload(&request) => stream_reload(request, &builds),
+                _ => site.respond(request, reload),
+            };
+            if let Err(e) = responded {
+                tracing::warn!("Unable to respond: {e:#}");
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_paths_under_the_prefix() {
+        let dir = std::env::temp_dir().join(format!("mub-serve-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("posts")).unwrap();
+        std::fs::write(dir.join("index.html"), "").unwrap();
+        std::fs::write(dir.join("posts/hello.html"), "").unwrap();
+        let site = Site {
+            output: dir.clone(),
+            prefix: "/blog".into(),
+        };
+        assert_eq!(site.resolve("/blog/"), Some(dir.join("index.html")));
+        assert_eq!(
+            site.resolve("/blog/posts/hello?x=1"),
+            Some(dir.join("posts/hello.html"))
+        );
+        assert_eq!(site.resolve("/posts/hello.html"), None);
+        assert_eq!(site.resolve("/blogposts/hello.html"), None);
+        assert_eq!(site.resolve("/blog/../index.html"), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
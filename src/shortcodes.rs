@@ -0,0 +1,164 @@
+use std::{borrow::Cow, collections::BTreeMap, fs::read_dir};
+
+use anyhow::{anyhow, Context, Result};
+use minijinja::{context, value::Kwargs, Environment, Error, State, Value};
+
+use crate::{
+    config::Config, media::MediaStore, plugins::Plugins, scripts::Scripts, templates,
+    types::BuildInfo,
+};
+
+/// Directory of the shortcode templates, inside the templates directory
+const SHORTCODES_DIR: &str = "shortcodes";
+
+/// Templates of `templates/shortcodes/`, the site's or the theme's, called from markdown by their file
+/// stem, as in `{{ youtube(id="...") }}`. Every call is replaced by its template
+/// rendered with the keyword arguments before the markdown gets converted, so
+/// shortcodes can write HTML or markdown.
+#[derive(Default)]
+pub(crate) struct Shortcodes {
+    env: Option<Environment<'static>>,
+    names: Vec<String>,
+}
+
+impl Shortcodes {
+    /// Register every shortcode template, none when neither the site nor its
+    /// theme have a shortcode directory
+    pub(crate) fn load(
+        config: &Config,
+        media: &MediaStore,
+        plugins: &Plugins,
+        scripts: &Scripts,
+    ) -> Result<Self> {
+        let dirs: Vec<_> = config
+            .layered_dirs("templates")
+            .into_iter()
+            .map(|dir| dir.join(SHORTCODES_DIR))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        if dirs.is_empty() {
+            return Ok(Self::default());
+        }
+        let build = BuildInfo::collect(config);
+        let mut env = templates::environment(config, &build, media, plugins, scripts)?;
+
+        let mut names: Vec<String> = Vec::new();
+        let mut entries = Vec::new();
+        for dir in &dirs {
+            entries.extend(
+                read_dir(dir).with_context(|| anyhow!("Unable to read shortcodes [{dir:?}]"))?,
+            );
+        }
+        for entry in entries {
+            let path = entry?.path();
+            let (Some(name), Some(file)) = (
+                path.file_stem().and_then(|stem| stem.to_str()),
+                path.file_name().and_then(|file| file.to_str()),
+            ) else {
+                continue;
+            };
+            // The site's shortcodes replace the theme's of the same name
+            let known = names.iter().any(|known| known == name);
+            if !path.is_file() || name.starts_with('.') || known {
+                continue;
+            }
+
+            let template = format!("{SHORTCODES_DIR}/{file}");
+            let globals = context!(build => build, ..context!(config));
+            env.add_function(
+                name.to_owned(),
+                move |state: &State, kwargs: Kwargs| -> Result<Value, Error> {
+                    let args = kwargs
+                        .args()
+                        .map(|key| Ok((key.to_owned(), kwargs.get::<Value>(key)?)))
+                        .collect::<Result<BTreeMap<String, Value>, Error>>()?;
+                    let rendered = state
+                        .env()
+                        .get_template(&template)?
+                        .render(context!(..Value::from(args), ..globals.clone()))?;
+                    Ok(Value::from_safe_string(rendered))
+                },
+            );
+            names.push(name.to_owned());
+        }
+
+        Ok(Self {
+            env: Some(env),
+            names,
+        })
+    }
+
+    /// Replace the shortcode calls of `markdown`, leaving code blocks and code
+    /// spans as they are
+    pub(crate) fn expand<'a>(&self, markdown: &'a str) -> Result<Cow<'a, str>> {
+        let Some(env) = &self.env else {
+            return Ok(Cow::Borrowed(markdown));
+        };
+        if !markdown.contains("{{") {
+            return Ok(Cow::Borrowed(markdown));
+        }
+
+        let mut expanded = String::with_capacity(markdown.len());
+        let mut fence: Option<&str> = None;
+        for line in markdown.split_inclusive('\n') {
+            let trimmed = line.trim_start();
+            match fence {
+                Some(marker) => {
+                    if trimmed.starts_with(marker) {
+                        fence = None;
+                    }
+                    expanded.push_str(line);
+                }
+                None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                    fence = Some(&trimmed[..3]);
+                    expanded.push_str(line);
+                }
+                None => {
+                    // Every other piece between backticks is a code span
+                    for (index, piece) in line.split('`').enumerate() {
+                        if index > 0 {
+                            expanded.push('`');
+                        }
+                        match index % 2 {
+                            0 => self.expand_calls(env, piece, &mut expanded)?,
+                            _ => expanded.push_str(piece),
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Cow::Owned(expanded))
+    }
+
+    fn expand_calls(&self, env: &Environment, text: &str, expanded: &mut String) -> Result<()> {
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let Some(length) = rest[start..].find("}}") else {
+                break;
+            };
+            let call = &rest[start..start + length + 2];
+            let expression = call[2..call.len() - 2].trim();
+            expanded.push_str(&rest[..start]);
+            if self.is_call(expression) {
+                let value = env
+                    .compile_expression(expression)
+                    .and_then(|expression| expression.eval(context!()))
+                    .with_context(|| anyhow!("Unable to expand the shortcode [{call}]"))?;
+                expanded.push_str(&value.to_string());
+            } else {
+                expanded.push_str(call);
+            }
+            rest = &rest[start + call.len()..];
+        }
+        expanded.push_str(rest);
+        Ok(())
+    }
+
+    /// Whether the expression calls a shortcode, other `{{ }}` stay as written
+    fn is_call(&self, expression: &str) -> bool {
+        expression.ends_with(')')
+            && expression
+                .split_once('(')
+                .is_some_and(|(name, _)| self.names.iter().any(|known| known == name.trim()))
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{bail, Result};
+
+use crate::{
+    config::Config,
+    feed::escape_xml,
+    output::Output,
+    types::{AvailableContent, PostDate},
+};
+
+const SITEMAP_XML: &str = "sitemap.xml";
+
+/// Page of the sitemap, with when it last changed for posts
+struct Entry {
+    loc: String,
+    lastmod: Option<String>,
+}
+
+/// Queue `sitemap.xml` with the `.html` render templates, the section indexes
+/// and the published posts, leaving out posts with `sitemap: false` in their
+/// front matter
+pub(crate) fn write(content: &AvailableContent, config: &Config, output: &Output) -> Result<()> {
+    if !config.sitemap {
+        return Ok(());
+    }
+    if config.base_url.is_none() {
+        bail!("The sitemap needs `base_url` to list pages with absolute URLs");
+    }
+
+    let mut entries: Vec<Entry> = Vec::new();
+    for entry in &config.render {
+        let template = entry.template();
+        if !template.ends_with(".html") {
+            continue;
+        }
+        entries.push(Entry {
+            loc: config.absolute_url(&entry.href(config)),
+            lastmod: None,
+        });
+    }
+    for section in content.sections.iter().filter(|section| section.index) {
+        entries.push(Entry {
+            loc: config.absolute_url(&section.href),
+            lastmod: None,
+        });
+    }
+    for content in content.content.iter().filter(|content| {
+        content.publish
+            && !content.draft
+            && !content.bare
+            && content.post.metadata.extra_bool("sitemap") != Some(false)
+    }) {
+        let metadata = &content.post.metadata;
+        let updated = metadata
+            .extra_str("updated")
+            .and_then(|updated| PostDate::parse(updated, &config.date_formats).ok());
+        let date = updated.as_ref().unwrap_or(&metadata.date);
+        entries.push(Entry {
+            loc: content.location.canonical.clone(),
+            lastmod: Some(date.utc().to_rfc3339()),
+        });
+    }
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for entry in entries {
+        xml.push_str(&format!("<url><loc>{}</loc>", escape_xml(&entry.loc)));
+        if let Some(lastmod) = entry.lastmod {
+            xml.push_str(&format!("<lastmod>{lastmod}</lastmod>"));
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    output.add(config.output.join(SITEMAP_XML), xml)
+}
@@ -0,0 +1,130 @@
+use std::{fmt::Display, path::PathBuf};
+
+use anyhow::Result;
+use pulldown_cmark_escape::escape_html;
+use serde::Serialize;
+
+use crate::{collect_content, config::Config, find_post, types::Content};
+
+/// Metadata a page presents when its link is shared, as it ends up in
+/// OpenGraph and Twitter card tags
+#[derive(Debug, Serialize)]
+pub struct SocialCard {
+    pub(crate) title: String,
+    pub(crate) description: Option<String>,
+    pub(crate) image: Option<String>,
+    pub(crate) url: String,
+    pub(crate) site_name: Option<String>,
+    /// Output file name of the page, a checked `slug.html`
+    #[serde(skip)]
+    pub(crate) filename: String,
+}
+
+impl SocialCard {
+    pub(crate) fn for_content(content: &Content, config: &Config) -> Self {
+        let metadata = &content.post.metadata;
+        let base = config
+            .base_url
+            .as_deref()
+            .unwrap_or("")
+            .trim_end_matches('/');
+        let absolute = |path: &str| match path.contains("://") {
+            true => path.to_owned(),
+            false => format!("{base}/{}", path.trim_start_matches('/')),
+        };
+        Self {
+            title: content.post.metadata.title.clone(),
+            description: metadata.extra_str("description").map(str::to_owned),
+            image: metadata
+                .extra_str("image")
+                .or_else(|| metadata.extra_str("cover_image"))
+                .map(absolute),
+            url: content.location.canonical.clone(),
+            site_name: config
+                .site
+                .get("title")
+                .and_then(|title| title.as_str())
+                .map(str::to_owned),
+            filename: content.location.filename.clone(),
+        }
+    }
+
+    /// Temporary file `to_html` is previewed from, named after the page
+    pub fn preview_path(&self) -> PathBuf {
+        std::env::temp_dir().join(format!("mub-preview-{}", self.filename))
+    }
+
+    /// A standalone page mocking up how the link unfurls
+    pub fn to_html(&self) -> String {
+        let escape = |text: &str| {
+            let mut escaped = String::new();
+            let _ = escape_html(&mut escaped, text);
+            escaped
+        };
+        let image = self
+            .image
+            .as_deref()
+            .map(|src| format!("<img src=\"{}\" style=\"width:100%\">", escape(src)))
+            .unwrap_or_default();
+        format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><title>Preview: {title}</title></head>\
+             <body style=\"font-family:sans-serif;background:#eee\">\
+             <div style=\"max-width:500px;margin:2em auto;background:#fff;border:1px solid #ccc;border-radius:8px;overflow:hidden\">\
+             {image}<div style=\"padding:12px\"><div style=\"color:#666;font-size:small\">{site}</div>\
+             <div style=\"font-weight:bold\">{title}</div><div>{description}</div>\
+             <div style=\"color:#666;font-size:small\">{url}</div></div></div></body></html>",
+            title = escape(&self.title),
+            site = escape(self.site_name.as_deref().unwrap_or("")),
+            description = escape(self.description.as_deref().unwrap_or("")),
+            url = escape(&self.url),
+        )
+    }
+
+    /// Things that will make the card look broken when shared
+    pub fn problems(&self) -> Vec<&'static str> {
+        let mut problems = Vec::new();
+        if self.description.is_none() {
+            problems.push("no `description`, platforms will guess one from the page");
+        }
+        if self.image.is_none() {
+            problems.push("no `image` or `cover_image`, the card will have no picture");
+        }
+        if !self.url.contains("://") {
+            problems.push("no `base_url`, shared URLs will not be absolute");
+        }
+        problems
+    }
+}
+
+impl Display for SocialCard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let missing = "<missing>";
+        writeln!(
+            f,
+            "og:site_name   {}",
+            self.site_name.as_deref().unwrap_or(missing)
+        )?;
+        writeln!(f, "og:title       {}", self.title)?;
+        writeln!(
+            f,
+            "og:description {}",
+            self.description.as_deref().unwrap_or(missing)
+        )?;
+        writeln!(
+            f,
+            "og:image       {}",
+            self.image.as_deref().unwrap_or(missing)
+        )?;
+        writeln!(f, "og:url         {}", self.url)?;
+        for problem in self.problems() {
+            writeln!(f, "warning: {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compute the social card of a post, found by its `name` or source file name
+pub fn preview_card(config: &Config, post: &str) -> Result<SocialCard> {
+    let content = collect_content(config)?;
+    Ok(SocialCard::for_content(find_post(&content, post)?, config))
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::types::Content;
+
+/// Front matter key of the tags of a post, a list or a single tag
+const TAGS: &str = "tags";
+
+/// How the `tags` front matter of every post is cleaned up before templates
+/// and feeds see it
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TagsConfig {
+    /// Lowercase every tag, so `Rust` and `rust` are the same tag
+    #[serde(default)]
+    pub(crate) case_fold: bool,
+    /// Tags written under another name, as `{"rustlang": "rust"}`. With
+    /// `case_fold` the names match whatever their case.
+    #[serde(default)]
+    pub(crate) synonyms: HashMap<String, String>,
+}
+
+impl TagsConfig {
+    fn normalize_tag(&self, tag: &str) -> String {
+        let tag = tag.trim();
+        let tag = match self.case_fold {
+            true => tag.to_lowercase(),
+            false => tag.to_owned(),
+        };
+        let synonym = self.synonyms.get(&tag).or_else(|| match self.case_fold {
+            true => self
+                .synonyms
+                .iter()
+                .find(|(name, _)| name.to_lowercase() == tag)
+                .map(|(_, synonym)| synonym),
+            false => None,
+        });
+        match (synonym, self.case_fold) {
+            (Some(synonym), true) => synonym.to_lowercase(),
+            (Some(synonym), false) => synonym.clone(),
+            (None, _) => tag,
+        }
+    }
+
+    /// Replace the tags of front matter by their normalized names, as a list
+    /// without the repeats merging made
+    pub(crate) fn normalize(&self, extra: &mut HashMap<String, Value>) {
+        if !self.case_fold && self.synonyms.is_empty() {
+            return;
+        }
+        let tags = match extra.get(TAGS) {
+            Some(Value::String(tag)) => vec![Value::String(tag.clone())],
+            Some(Value::Array(tags)) => tags.clone(),
+            _ => return,
+        };
+        let mut normalized: Vec<Value> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = match tag {
+                Value::String(tag) => Value::String(self.normalize_tag(&tag)),
+                other => other,
+            };
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        extra.insert(TAGS.to_owned(), Value::Array(normalized));
+    }
+}
+
+/// A tag and how many posts use it
+pub(crate) type TagUses<'a> = (&'a str, usize);
+
+/// Tags of a post, a list or a single tag
+pub(crate) fn of(content: &Content) -> Vec<&str> {
+    match content.post.metadata.extra.get(TAGS) {
+        Some(Value::String(tag)) => vec![tag.as_str()],
+        Some(Value::Array(tags)) => tags.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Letters and digits of a tag, lowercased, so `Machine Learning` and
+/// `machine-learning` compare the same
+fn skeleton(tag: &str) -> String {
+    tag.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Edits turning one string into the other, stopping early past `limit`
+fn distance(a: &str, b: &str, limit: usize) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    if a.len().abs_diff(b.len()) > limit {
+        return limit + 1;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, y) in b.iter().enumerate() {
+            current[j + 1] = (previous[j] + usize::from(x != y))
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Whether two different tags are likely the same one written differently:
+/// the same but for case and punctuation, a plural of the other, or a typo away
+fn is_near_duplicate(a: &str, b: &str) -> bool {
+    let (a, b) = (skeleton(a), skeleton(b));
+    let plural = |one: &str, other: &str| {
+        one.strip_prefix(other)
+            .is_some_and(|end| end == "s" || end == "es")
+    };
+    a == b
+        || plural(&a, &b)
+        || plural(&b, &a)
+        || (a.chars().count() >= 5 && distance(&a, &b, 1) <= 1)
+}
+
+/// Pairs of tags that are likely the same, each with how many posts use it,
+/// the more used tag of a pair first
+pub(crate) fn near_duplicates<'a>(contents: &[&'a Content]) -> Vec<(TagUses<'a>, TagUses<'a>)> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for content in contents {
+        for tag in of(content) {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let counts: Vec<TagUses> = counts.into_iter().collect();
+    let mut pairs = Vec::new();
+    for (i, &a) in counts.iter().enumerate() {
+        for &b in &counts[i + 1..] {
+            if is_near_duplicate(a.0, b.0) {
+                pairs.push(match a.1 >= b.1 {
+                    true => (a, b),
+                    false => (b, a),
+                });
+            }
+        }
+    }
+    pairs
+}
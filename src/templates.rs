@@ -0,0 +1,427 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use minijinja::{
+    context, syntax::SyntaxConfig, AutoEscape, Environment, Error, ErrorKind, Output, State, Value,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache, collation::Collation, config::Config, feed, filters, icons, manifest::digest,
+    media::MediaStore, plugins::Plugins, scripts::Scripts, types::BuildInfo,
+};
+
+/// Directory of the cloned template packages, inside the cache directory
+pub(crate) const PACKAGES_DIR: &str = "packages";
+
+/// Template engine settings
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+    /// Remove the first newline after a block tag
+    #[serde(default)]
+    pub(crate) trim_blocks: bool,
+    /// Strip spaces and tabs from the start of a line up to a block tag
+    #[serde(default)]
+    pub(crate) lstrip_blocks: bool,
+    /// Keep the final newline of a template when rendering it
+    #[serde(default)]
+    pub(crate) keep_trailing_newline: bool,
+    /// Start and end of block tags, `["{%", "%}"]` by default
+    #[serde(default)]
+    pub(crate) block_delimiters: Option<(String, String)>,
+    /// Start and end of variable tags, `["{{", "}}"]` by default. Changing them
+    /// lets templates emit literal `{{` for client-side frameworks.
+    #[serde(default)]
+    pub(crate) variable_delimiters: Option<(String, String)>,
+    /// Start and end of comments, `["{#", "#}"]` by default
+    #[serde(default)]
+    pub(crate) comment_delimiters: Option<(String, String)>,
+    /// Escaping of values by template extension, e.g. `{"rss": "xml"}`, on top
+    /// of the defaults: html for `html`/`htm`, xml for `xml`/`rss`/`atom`/`svg`,
+    /// json for `json`, none for anything else
+    #[serde(default)]
+    pub(crate) autoescape: HashMap<String, Escape>,
+    /// Instructions a single render may run before it fails with `engine ran
+    /// out of fuel`, so a runaway template stops the build instead of hanging
+    /// it. Unlimited by default.
+    #[serde(default)]
+    pub(crate) fuel: Option<u64>,
+    /// How deep includes, macros and nested blocks may go before rendering
+    /// fails, 500 by default and at most
+    #[serde(default)]
+    pub(crate) recursion_limit: Option<usize>,
+}
+
+/// Templates shared between sites, from a directory or a git repository, loaded
+/// under a prefix as in `{% include "components/card.html" %}`. The site's own
+/// templates at the same path take precedence.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TemplatePackage {
+    /// Directory relative to the input directory, or the URL of a git
+    /// repository, cloned into the cache once until `mub cache clean`
+    pub(crate) source: String,
+    /// Branch or tag of a git repository, its default branch otherwise
+    #[serde(default)]
+    pub(crate) rev: Option<String>,
+    /// Namespace of the templates, the last part of `source` by default
+    #[serde(default)]
+    pub(crate) prefix: Option<String>,
+}
+
+impl TemplatePackage {
+    fn is_git(&self) -> bool {
+        self.source.contains("://")
+            || self.source.starts_with("git@")
+            || self.source.ends_with(".git")
+    }
+
+    fn prefix(&self) -> String {
+        self.prefix.clone().unwrap_or_else(|| {
+            let source = self.source.trim_end_matches('/');
+            let name = source.rsplit(['/', ':']).next().unwrap_or(source);
+            name.trim_end_matches(".git").to_owned()
+        })
+    }
+
+    /// Directory holding the templates, cloning a git repository the first
+    /// time it is needed
+    fn dir(&self, config: &Config) -> anyhow::Result<PathBuf> {
+        if !self.is_git() {
+            let dir = config.input.join(&self.source);
+            if !dir.is_dir() {
+                bail!("Template package [{dir:?}] is not a directory");
+            }
+            return Ok(dir);
+        }
+
+        let key = format!(
+            "{}#{}",
+            self.source,
+            self.rev.as_deref().unwrap_or_default()
+        );
+        let dir = cache::dir(config)
+            .join(PACKAGES_DIR)
+            .join(&digest(key.as_bytes())[..16]);
+        if dir.is_dir() {
+            return Ok(dir);
+        }
+        // Cloned aside and renamed so an interrupted clone is never used
+        let partial = dir.with_extension(format!("{}.partial", std::process::id()));
+        let mut clone = Command::new("git");
+        clone.args(["clone", "--quiet", "--depth", "1"]);
+        if let Some(rev) = &self.rev {
+            clone.args(["--branch", rev]);
+        }
+        let output = clone
+            .arg(&self.source)
+            .arg(&partial)
+            .output()
+            .with_context(|| anyhow!("Unable to run git to clone [{}]", self.source))?;
+        if !output.status.success() {
+            let _ = std::fs::remove_dir_all(&partial);
+            bail!(
+                "Unable to clone template package [{}]: {}",
+                self.source,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        std::fs::rename(&partial, &dir)
+            .with_context(|| anyhow!("Unable to move template package into [{dir:?}]"))?;
+        Ok(dir)
+    }
+}
+
+/// How values are escaped when printed into a template
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Escape {
+    /// HTML entities, `/` included
+    Html,
+    /// The five XML entities
+    Xml,
+    /// Values printed as JSON
+    Json,
+    /// Values printed as they are
+    None,
+}
+
+/// Name minijinja carries for the XML escaping handled by [`xml_formatter`]
+const XML: AutoEscape = AutoEscape::Custom("xml");
+
+impl Escape {
+    /// Default escaping of a template extension
+    fn for_extension(extension: &str) -> Self {
+        match extension {
+            "html" | "htm" => Escape::Html,
+            "xml" | "rss" | "atom" | "svg" => Escape::Xml,
+            "json" => Escape::Json,
+            _ => Escape::None,
+        }
+    }
+
+    fn auto_escape(self) -> AutoEscape {
+        match self {
+            Escape::Html => AutoEscape::Html,
+            Escape::Xml => XML,
+            Escape::Json => AutoEscape::Json,
+            Escape::None => AutoEscape::None,
+        }
+    }
+}
+
+/// A value escaped for XML text and attributes
+fn escape_xml(value: &Value) -> String {
+    feed::escape_xml(&value.to_string())
+}
+
+/// The default formatter, extended with XML escaping
+fn xml_formatter(out: &mut Output, state: &State, value: &Value) -> Result<(), Error> {
+    if state.auto_escape() == XML && !value.is_safe() {
+        return out.write_str(&escape_xml(value)).map_err(Error::from);
+    }
+    minijinja::escape_formatter(out, state, value)
+}
+
+/// The builtin `escape` filter, extended with XML escaping
+fn escape_filter(state: &State, value: &Value) -> Result<Value, Error> {
+    if state.auto_escape() == XML && !value.is_safe() {
+        return Ok(Value::from_safe_string(escape_xml(value)));
+    }
+    minijinja::filters::escape(state, value)
+}
+
+impl TemplateConfig {
+    /// Apply the settings to a fresh environment
+    fn apply(&self, env: &mut Environment) -> anyhow::Result<()> {
+        env.set_trim_blocks(self.trim_blocks);
+        env.set_lstrip_blocks(self.lstrip_blocks);
+        env.set_keep_trailing_newline(self.keep_trailing_newline);
+        env.set_fuel(self.fuel);
+        if let Some(limit) = self.recursion_limit {
+            env.set_recursion_limit(limit);
+        }
+
+        let autoescape = self.autoescape.clone();
+        env.set_auto_escape_callback(move |name| {
+            let extension = name.rsplit('.').next().unwrap_or_default();
+            autoescape
+                .get(extension)
+                .copied()
+                .unwrap_or_else(|| Escape::for_extension(extension))
+                .auto_escape()
+        });
+        env.set_formatter(xml_formatter);
+        env.add_filter("escape", escape_filter);
+        env.add_filter("e", escape_filter);
+
+        let mut syntax = SyntaxConfig::builder();
+        if let Some((start, end)) = &self.block_delimiters {
+            syntax.block_delimiters(start.clone(), end.clone());
+        }
+        if let Some((start, end)) = &self.variable_delimiters {
+            syntax.variable_delimiters(start.clone(), end.clone());
+        }
+        if let Some((start, end)) = &self.comment_delimiters {
+            syntax.comment_delimiters(start.clone(), end.clone());
+        }
+        env.set_syntax(
+            syntax
+                .build()
+                .map_err(|e| anyhow::anyhow!("Invalid template delimiters: {e}"))?,
+        );
+        Ok(())
+    }
+}
+
+/// Build the template environment with mub's functions and filters registered
+pub(crate) fn environment(
+    config: &Config,
+    build: &BuildInfo,
+    media: &MediaStore,
+    plugins: &Plugins,
+    scripts: &Scripts,
+) -> anyhow::Result<Environment<'static>> {
+    let mut env = Environment::new();
+    config.templates.apply(&mut env)?;
+    filters::register(
+        &mut env,
+        config,
+        Collation::new(config.collation.as_deref())?,
+    );
+    plugins.register(&mut env);
+    scripts.register(&mut env);
+    // Templates of the site take precedence over the theme's
+    let loaders: Vec<_> = config
+        .layered_dirs("templates")
+        .into_iter()
+        .map(minijinja::path_loader)
+        .collect();
+    let packages = config
+        .template_packages
+        .iter()
+        .map(|package| {
+            Ok((
+                package.prefix(),
+                minijinja::path_loader(package.dir(config)?),
+            ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    env.set_loader(move |name| {
+        for loader in &loaders {
+            if let Some(source) = loader(name)? {
+                return Ok(Some(source));
+            }
+        }
+        let Some((prefix, name)) = name.split_once('/') else {
+            return Ok(None);
+        };
+        for (_, loader) in packages.iter().filter(|(known, _)| known == prefix) {
+            if let Some(source) = loader(name)? {
+                return Ok(Some(source));
+            }
+        }
+        Ok(None)
+    });
+
+    // Partials only see site-wide values so their output can be shared between pages
+    let globals = context!(build => build, ..context!(config));
+    let cache: Arc<Mutex<HashMap<String, Value>>> = Default::default();
+    env.add_function("partial", move |state: &State, name: String| {
+        partial(state, &name, &globals, &cache)
+    });
+
+    let input = config.input.clone();
+    env.add_function("read_file", move |path: String| -> Result<String, Error> {
+        read_input_file(&input, &path)
+    });
+    let input = config.input.clone();
+    env.add_function("inline", move |path: String| -> Result<Value, Error> {
+        read_input_file(&input, &path).map(Value::from_safe_string)
+    });
+
+    let site = config.site_url("");
+    let site_url = move |path: &str| format!("{site}{}", path.trim_start_matches('/'));
+    let url = site_url.clone();
+    env.add_function("site_url", move |path: String| url(&path));
+    let url = site_url.clone();
+    env.add_function("relative_url", move |path: String| url(&path));
+    // Full URLs, for canonical links and social tags, of paths in the site
+    let url = site_url.clone();
+    let origin = config.absolute_url("");
+    env.add_function("absolute_url", move |path: String| {
+        match path.contains("://") || path.starts_with("//") {
+            true => path,
+            false => format!("{origin}{}", url(&path)),
+        }
+    });
+
+    env.add_function("current_url", |state: &State| -> Result<Value, Error> {
+        state
+            .lookup("page")
+            .and_then(|page| page.get_attr("url").ok())
+            .filter(|url| !url.is_undefined() && !url.is_none())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidOperation,
+                    "current_url() needs `page.url`, render data that isn't a mapping has none",
+                )
+            })
+    });
+
+    // Files in the media store are served from their content hash path instead
+    let media = media.clone();
+    env.add_function("asset_url", move |path: String| {
+        let relative = Path::new(path.trim_start_matches('/'));
+        media
+            .url(relative)
+            .map_or_else(|| site_url(&path), str::to_owned)
+    });
+
+    let available = icons::names(config)?;
+    let markup: HashMap<String, String> = available
+        .iter()
+        .filter_map(|name| Some((name.clone(), icons::reference(config, name)?)))
+        .collect();
+    env.add_function("icon", move |name: String| -> Result<Value, Error> {
+        markup
+            .get(&name)
+            .cloned()
+            .map(Value::from_safe_string)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("unknown icon [{name}], available: {}", available.join(", ")),
+                )
+            })
+    });
+
+    Ok(env)
+}
+
+/// Find a file by a path relative to the input directory, or to `include/`
+/// inside it, refusing anything that resolves outside of the input tree
+fn resolve_input_file(input: &Path, path: &str) -> Result<PathBuf, Error> {
+    let not_found = |detail: String| Error::new(ErrorKind::InvalidOperation, detail);
+    let root = input
+        .canonicalize()
+        .map_err(|e| not_found(format!("unable to resolve the input directory: {e}")))?;
+
+    let candidate = [input.join(path), input.join("include").join(path)]
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| {
+            not_found(format!(
+                "no file [{path}] in the input or include directory"
+            ))
+        })?;
+    let resolved = candidate
+        .canonicalize()
+        .map_err(|e| not_found(format!("unable to resolve [{path}]: {e}")))?;
+
+    if !resolved.starts_with(&root) {
+        return Err(not_found(format!(
+            "[{path}] is outside of the input directory"
+        )));
+    }
+    Ok(resolved)
+}
+
+fn read_input_file(input: &Path, path: &str) -> Result<String, Error> {
+    let resolved = resolve_input_file(input, path)?;
+    std::fs::read_to_string(&resolved).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("unable to read [{}]: {e}", resolved.display()),
+        )
+    })
+}
+
+/// Render a template once with the site-wide context and reuse the output for
+/// every following call with the same name
+fn partial(
+    state: &State,
+    name: &str,
+    globals: &Value,
+    cache: &Mutex<HashMap<String, Value>>,
+) -> Result<Value, Error> {
+    let lock_error = || Error::new(ErrorKind::InvalidOperation, "partial cache is poisoned");
+    if let Some(rendered) = cache.lock().map_err(|_| lock_error())?.get(name) {
+        return Ok(rendered.clone());
+    }
+
+    let rendered = Value::from_safe_string(state.env().get_template(name)?.render(globals)?);
+    cache
+        .lock()
+        .map_err(|_| lock_error())?
+        .insert(name.to_owned(), rendered.clone());
+    Ok(rendered)
+}
@@ -0,0 +1,254 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use glob::Pattern;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{cache, config::Config, manifest};
+
+/// What happens to the include files matching a pattern
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IncludeRule {
+    /// Glob relative to the include directory, e.g. `photos/**`
+    pub(crate) pattern: String,
+    /// Program and arguments producing the output file, `{src}` and `{dst}` are
+    /// replaced with the paths of the include file and of its output, e.g.
+    /// `["magick", "{src}", "-resize", "2000x2000>", "-strip", "{dst}"]`.
+    /// Without a command matching files are copied untouched.
+    #[serde(default)]
+    pub(crate) command: Option<Vec<String>>,
+    /// Directory the command runs in, relative to the input directory. `{src}`
+    /// and `{dst}` are absolute paths when it is set.
+    #[serde(default)]
+    pub(crate) cwd: Option<PathBuf>,
+    /// Environment variables passed on to the command, all of them when not given
+    #[serde(default)]
+    pub(crate) env: Option<Vec<String>>,
+    /// Seconds the command may run for before it is killed and the build fails
+    #[serde(default)]
+    pub(crate) timeout: Option<u64>,
+    /// Leave matching files out of the output
+    #[serde(default)]
+    pub(crate) exclude: bool,
+}
+
+/// The first include rule matching the include file at `included`
+fn rule<'c>(included: &Path, config: &'c Config) -> Result<Option<&'c IncludeRule>> {
+    for rule in &config.include_rules {
+        let pattern = Pattern::new(&rule.pattern)
+            .with_context(|| anyhow!("Invalid include rule pattern [{}]", rule.pattern))?;
+        if pattern.matches_path(included) {
+            return Ok(Some(rule));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether an include rule leaves the include file at `included` out of the output
+pub(crate) fn is_excluded(included: &Path, config: &Config) -> Result<bool> {
+    Ok(rule(included, config)?.is_some_and(|rule| rule.exclude))
+}
+
+/// How many include rule outputs came out of the cache and how many had to be made
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} cached, {} processed", self.hits, self.misses)
+    }
+}
+
+/// What an include rule command wrote to stdout and stderr while it made the
+/// output of `file`
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandOutput {
+    pub program: String,
+    pub file: PathBuf,
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(serialize_with = "crate::seconds")]
+    pub elapsed: Duration,
+}
+
+/// Outputs of include rule commands kept between builds, by a hash of the
+/// source, the command and the output extension, so rebuilds only process new
+/// or changed files
+#[derive(Debug)]
+pub(crate) struct VariantCache {
+    dir: PathBuf,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    /// Commands run for this build that had something to say
+    outputs: Mutex<Vec<CommandOutput>>,
+}
+
+impl VariantCache {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            dir: cache::dir(config).join("variants"),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            outputs: Mutex::default(),
+        }
+    }
+
+    /// Cache file of the output of `command` run on `src`
+    fn entry(&self, src: &Path, command: &[String], dst: &Path) -> Result<PathBuf> {
+        let mut key =
+            std::fs::read(src).with_context(|| anyhow!("Unable to read include file [{src:?}]"))?;
+        for part in command {
+            key.push(0);
+            key.extend_from_slice(part.as_bytes());
+        }
+        let mut entry = self.dir.join(manifest::digest(&key));
+        if let Some(ext) = dst.extension() {
+            entry.set_extension(ext);
+        }
+        Ok(entry)
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// What the commands run so far wrote, by file
+    pub(crate) fn command_outputs(&self) -> Vec<CommandOutput> {
+        let mut outputs = self
+            .outputs
+            .lock()
+            .map(|outputs| outputs.clone())
+            .unwrap_or_default();
+        outputs.sort_by(|a, b| a.file.cmp(&b.file));
+        outputs
+    }
+}
+
+/// Put the include file `src`, found at `included` inside the include directory,
+/// into the output as `dst` using the first rule matching it. The directory of
+/// `dst` has to exist.
+pub(crate) fn install(
+    src: &Path,
+    included: &Path,
+    dst: &Path,
+    config: &Config,
+    cache: &VariantCache,
+) -> Result<()> {
+    let started = Instant::now();
+    let how = run_rule(src, included, dst, config, cache)?;
+    tracing::debug!(src = %src.display(), dst = %dst.display(), how, elapsed = ?started.elapsed(), "Installed file");
+    Ok(())
+}
+
+/// Copy or transform `src` into `dst`, saying which of the two it did, or
+/// whether the transformed file came from the cache
+fn run_rule(
+    src: &Path,
+    included: &Path,
+    dst: &Path,
+    config: &Config,
+    cache: &VariantCache,
+) -> Result<&'static str> {
+    let rule = rule(included, config)?
+        .and_then(|rule| Some((rule, rule.command.as_ref()?)))
+        .filter(|(_, command)| !command.is_empty());
+
+    let Some((rule, command)) = rule else {
+        std::fs::copy(src, dst).with_context(|| {
+            anyhow!("Unable to copy include file [{src:?}] into output directory as [{dst:?}]")
+        })?;
+        return Ok("copied");
+    };
+
+    let (program, args) = (&command[0], &command[1..]);
+
+    let entry = cache.entry(src, command, dst)?;
+    if entry.is_file() {
+        cache::touch(&entry);
+        std::fs::copy(&entry, dst).with_context(|| {
+            anyhow!("Unable to copy cached [{entry:?}] into output as [{dst:?}]")
+        })?;
+        cache.hits.fetch_add(1, Ordering::Relaxed);
+        return Ok("cached");
+    }
+
+    let mut run = Command::new(program);
+    let (src_arg, dst_arg) = match &rule.cwd {
+        Some(cwd) => {
+            run.current_dir(config.input.join(cwd));
+            (std::path::absolute(src)?, std::path::absolute(dst)?)
+        }
+        None => (src.to_path_buf(), dst.to_path_buf()),
+    };
+    if let Some(names) = &rule.env {
+        run.env_clear().envs(
+            names
+                .iter()
+                .filter_map(|name| Some((name, std::env::var_os(name)?))),
+        );
+    }
+    run.args(args.iter().map(|arg| {
+        arg.replace("{src}", &src_arg.to_string_lossy())
+            .replace("{dst}", &dst_arg.to_string_lossy())
+    }));
+
+    let started = Instant::now();
+    let output = config
+        .cancel
+        .output(&mut run, rule.timeout.map(Duration::from_secs))
+        .with_context(|| anyhow!("Unable to run include rule command [{program}] for [{src:?}]"))?;
+    if !output.status.success() {
+        bail!(
+            "Include rule command [{program}] failed for [{src:?}]: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    cache.misses.fetch_add(1, Ordering::Relaxed);
+
+    let (stdout, stderr) = (
+        String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+        String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+    );
+    if !stderr.is_empty() {
+        tracing::warn!(program, file = %src.display(), stderr, "Include rule command wrote to stderr");
+    }
+    if !stdout.is_empty() || !stderr.is_empty() {
+        cache
+            .outputs
+            .lock()
+            .map_err(|_| anyhow!("Command output lock is poisoned"))?
+            .push(CommandOutput {
+                program: program.clone(),
+                file: src.to_path_buf(),
+                stdout,
+                stderr,
+                elapsed: started.elapsed(),
+            });
+    }
+
+    // Written aside and renamed so parallel builds never see half a file
+    std::fs::create_dir_all(&cache.dir)
+        .with_context(|| anyhow!("Unable to create cache directory [{:?}]", cache.dir))?;
+    let partial = entry.with_extension(format!("{}.partial", std::process::id()));
+    std::fs::copy(dst, &partial)
+        .and_then(|_| std::fs::rename(&partial, &entry))
+        .with_context(|| anyhow!("Unable to cache [{dst:?}] as [{entry:?}]"))?;
+    Ok("transformed")
+}
@@ -1,112 +1,415 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::{Path, PathBuf}, process::Command};
 
-use anyhow::{anyhow, Context, Error, Result};
-use chrono::{DateTime, Utc};
-use serde::Serialize;
+use anyhow::{anyhow, bail, Context, Error, Result};
+use chrono::{format::{Item, StrftimeItems}, DateTime, NaiveDate, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-use crate::POSTS_DIR;
+use crate::{captions::Cue, media::MediaStore, plugins::Plugins, podcast::Enclosure, scripts::Scripts, sections::Section, POSTS_DIR};
 
+/// Date of a post, with its time of day when the front matter gives one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PostDate {
+    at: NaiveDateTime,
+    /// Whether `at` has a time of day rather than being midnight of a plain date
+    timed: bool,
+}
+
+impl PostDate {
+    /// Read a date in RFC 3339 or in one of `formats`, which hold a time of day or not
+    pub(crate) fn parse(text: &str, formats: &[String]) -> Result<Self> {
+        if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+            return Ok(Self { at: at.naive_utc(), timed: true });
+        }
+        for format in formats {
+            if let Ok(at) = NaiveDateTime::parse_from_str(text, format) {
+                return Ok(Self { at, timed: true });
+            }
+            if let Ok(day) = NaiveDate::parse_from_str(text, format) {
+                return Ok(Self { at: day.and_time(chrono::NaiveTime::MIN), timed: false });
+            }
+        }
+        Err(anyhow!("Unable to read the date [{text}], expected RFC 3339 or one of {formats:?}"))
+    }
+
+    pub fn day(&self) -> NaiveDate {
+        self.at.date()
+    }
+
+    /// The date as a UTC timestamp, midnight for plain dates
+    pub fn utc(&self) -> DateTime<Utc> {
+        self.at.and_utc()
+    }
+
+    /// Whether the front matter gave a time of day, not just a date
+    pub fn is_timed(&self) -> bool {
+        self.timed
+    }
+
+    /// The date written with a `strftime` style format
+    pub(crate) fn format(&self, format: &str) -> Result<String> {
+        let items: Vec<Item> = StrftimeItems::new(format).collect();
+        if items.contains(&Item::Error) {
+            return Err(anyhow!("Invalid date format [{format}]"));
+        }
+        Ok(self.at.format_with_items(items.into_iter()).to_string())
+    }
+}
+
+impl std::fmt::Display for PostDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.timed {
+            true => write!(f, "{}", self.at.format("%Y-%m-%dT%H:%M:%S")),
+            false => write!(f, "{}", self.at.format("%Y-%m-%d")),
+        }
+    }
+}
+
+impl Serialize for PostDate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Front matter of a post, the keys mub knows about picked out of it
 #[derive(Debug, Serialize, Clone)]
-pub(crate) struct Metadata {
+pub struct Metadata {
     pub(crate) name: String,
     pub(crate) title: String,
     pub(crate) template: String,
-    pub(crate) date: String,
+    pub(crate) date: PostDate,
     pub(crate) publish: bool,
     pub(crate) bare: bool,
-    pub(crate) extra: HashMap<String, String>,
-}
-
-// TODO: this should be a deserialize implementation
-// TODO: tie lifetimes here with &str
-impl TryFrom<&str> for Metadata {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let parse_line = |line: &str| -> Option<Result<(String, String)>> {
-            if line == "---" || line.is_empty() {
-                return None;
-            };
-            Some(
-                line.split_once(":")
-                    .ok_or_else(|| {
-                        anyhow::anyhow!("Unable to find `:` in the front matter line: [{line}]")
-                    })
-                    .map(|(k, v)| (k.trim().to_owned(), v.trim().to_owned())),
-            )
+    pub(crate) extra: HashMap<String, serde_json::Value>,
+}
+
+impl Metadata {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Template the post is rendered with, `post.html` unless it says otherwise
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn date(&self) -> PostDate {
+        self.date
+    }
+
+    /// Whether the front matter says to publish the post, dates aside
+    pub fn is_published(&self) -> bool {
+        self.publish
+    }
+
+    /// Whether the post is only data for templates, without a page of its own
+    pub fn is_bare(&self) -> bool {
+        self.bare
+    }
+
+    /// The whole front matter, known keys included
+    pub fn extra(&self) -> &HashMap<String, serde_json::Value> {
+        &self.extra
+    }
+
+    /// Metadata from `+++` delimited TOML front matter, as used by Hugo and Zola,
+    /// on top of `defaults`
+    pub(crate) fn from_toml(front_matter: &str, defaults: &HashMap<String, serde_json::Value>, date_formats: &[String]) -> Result<Self> {
+        let table: toml::Table = toml::from_str(front_matter).context("Front matter is not valid TOML")?;
+        let extra = table.into_iter().map(|(key, value)| (key, toml_to_json(value)));
+        Self::from_extra(defaults.clone().into_iter().chain(extra).collect(), date_formats)
+    }
+
+    /// Front matter is read as YAML, so it can hold nested values and lists, and
+    /// falls back to the simple `key: value` format for what YAML rejects. Keys
+    /// it doesn't have are taken from `defaults`
+    // TODO: this should be a deserialize implementation
+    pub(crate) fn from_yaml(front_matter: &str, defaults: &HashMap<String, serde_json::Value>, date_formats: &[String]) -> Result<Self> {
+        let extra = match serde_yaml::from_str::<HashMap<String, serde_json::Value>>(front_matter) {
+            Ok(extra) => extra,
+            Err(_) => parse_simple(front_matter)?,
+        };
+        Self::from_extra(defaults.clone().into_iter().chain(extra).collect(), date_formats)
+    }
+
+    /// Pick the well known keys out of the parsed front matter
+    pub(crate) fn from_extra(extra: HashMap<String, serde_json::Value>, date_formats: &[String]) -> Result<Self> {
+        let text = |key: &str| extra.get(key).and_then(scalar_text);
+        let flag = |key: &str| match extra.get(key) {
+            Some(serde_json::Value::Bool(flag)) => *flag,
+            Some(serde_json::Value::String(text)) => text.parse().unwrap_or(false),
+            _ => false,
         };
-        let extra = value
-            .lines()
-            .filter_map(parse_line)
-            .collect::<Result<HashMap<String, String>>>()?;
 
         Ok(Self {
-            name: extra
-                .get("name")
-                .cloned()
-                .ok_or_else(|| anyhow!("Unable to find name in metadata"))?,
-            title: extra
-                .get("title")
-                .cloned()
-                .ok_or_else(|| anyhow!("Unable to find title in metadata"))?,
-            template: extra
-                .get("template")
-                .cloned()
-                .unwrap_or_else(|| String::from("post.html")),
-            date: extra
-                .get("date")
-                .cloned()
-                .ok_or_else(|| anyhow!("Unable to find date in metadata"))?,
-            publish: extra
-                .get("publish")
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(false),
-            bare: extra
-                .get("bare")
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(false),
+            name: text("name").ok_or_else(|| anyhow!("Unable to find name in metadata"))?,
+            title: text("title").ok_or_else(|| anyhow!("Unable to find title in metadata"))?,
+            template: text("template").unwrap_or_else(|| String::from("post.html")),
+            date: PostDate::parse(&text("date").ok_or_else(|| anyhow!("Unable to find date in metadata"))?, date_formats)?,
+            publish: flag("publish"),
+            bare: flag("bare"),
             extra,
         })
     }
+
+    /// A front matter value that is a string
+    pub(crate) fn extra_str(&self, key: &str) -> Option<&str> {
+        self.extra.get(key).and_then(|value| value.as_str())
+    }
+
+    /// A front matter flag, given as a boolean or as `true`/`false` text
+    pub(crate) fn extra_bool(&self, key: &str) -> Option<bool> {
+        match self.extra.get(key)? {
+            serde_json::Value::Bool(flag) => Some(*flag),
+            serde_json::Value::String(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Whether the post is dated after now, or after today for plain dates, UTC
+    pub(crate) fn is_future(&self) -> bool {
+        match self.date.timed {
+            true => self.date.utc() > Utc::now(),
+            false => self.date.day() > Utc::now().date_naive(),
+        }
+    }
 }
 
+/// Text of a scalar front matter value, numbers and flags included
+fn scalar_text(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(text) => Some(text.clone()),
+        serde_json::Value::Number(number) => Some(number.to_string()),
+        serde_json::Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// TOML value as JSON, with dates and times kept as their TOML text
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(text) => text.into(),
+        toml::Value::Integer(number) => number.into(),
+        toml::Value::Float(number) => number.into(),
+        toml::Value::Boolean(flag) => flag.into(),
+        toml::Value::Datetime(datetime) => datetime.to_string().into(),
+        toml::Value::Array(values) => values.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect(),
+    }
+}
+
+/// Value of a simple front matter line, typed as YAML would: booleans, numbers,
+/// quoted text and `[a, b]` lists, anything else as text
+fn simple_value(text: &str) -> serde_json::Value {
+    if let Some(list) = text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        return list.split(',').map(str::trim).filter(|item| !item.is_empty()).map(simple_value).collect();
+    }
+    for quote in ['"', '\''] {
+        if let Some(quoted) = text.strip_prefix(quote).and_then(|rest| rest.strip_suffix(quote)) {
+            return quoted.into();
+        }
+    }
+    match text {
+        "true" => true.into(),
+        "false" => false.into(),
+        // Only numbers written the way they print, so `007` stays text
+        _ => match (text.parse::<i64>(), text.parse::<f64>()) {
+            (Ok(number), _) if number.to_string() == text => number.into(),
+            (_, Ok(number)) if number.to_string() == text => number.into(),
+            _ => text.into(),
+        },
+    }
+}
+
+/// The original front matter format: one flat `key: value` pair per line
+fn parse_simple(value: &str) -> Result<HashMap<String, serde_json::Value>> {
+    let parse_line = |line: &str| -> Option<Result<(String, serde_json::Value)>> {
+        if line == "---" || line.is_empty() {
+            return None;
+        };
+        Some(
+            line.split_once(":")
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Unable to find `:` in the front matter line: [{line}]")
+                })
+                .map(|(k, v)| (k.trim().to_owned(), simple_value(v.trim()))),
+        )
+    };
+    value.lines().filter_map(parse_line).collect()
+}
+
+/// A parsed content file
 #[derive(Debug, Serialize, Clone)]
-pub(crate) struct Post {
+pub struct Post {
     pub(crate) metadata: Metadata,
+    /// Front matter block as written, without its delimiters
+    pub(crate) front_matter: String,
+    /// Modification time of the source file, `location.src`
+    pub(crate) modified: DateTime<Utc>,
     pub(crate) raw: String,
     pub(crate) html: String,
+    /// HTML of the post up to its `<!-- more -->` marker, or its first
+    /// `summary_words` words
+    pub(crate) summary_html: Option<String>,
     pub(crate) text: Option<String>,
+    /// Local files referenced by the post (images, downloads, cover image)
+    pub(crate) assets: Vec<String>,
+    pub(crate) stats: PostStats,
+    /// Word counts of the post and of each of its sections
+    pub(crate) reading: Reading,
+    /// Headings of the post in order, with the ids they are rendered with
+    pub(crate) toc: Vec<TocEntry>,
+    /// Media file from the `audio`, `video` or `enclosure` front matter, for feeds
+    pub(crate) enclosure: Option<Enclosure>,
+    /// Cues of the `transcript` sidecar of the media file, if any
+    pub(crate) transcript: Vec<Cue>,
+    /// Cues of the `chapters` sidecar of the media file, if any
+    pub(crate) chapters: Vec<Cue>,
 }
 
-#[derive(Debug, Serialize, Clone)]
-pub(crate) struct SearchableDoc {
-    path: PathBuf,
-    title: String,
-    date: String,
-    text: String,
+impl Post {
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Front matter block as written, without its delimiters
+    pub fn front_matter(&self) -> &str {
+        &self.front_matter
+    }
+
+    /// Modification time of the source file
+    pub fn modified(&self) -> DateTime<Utc> {
+        self.modified
+    }
+
+    /// Body of the file after plugins ran on it, before it is converted
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    /// The HTML of the post to change, from the build hooks
+    pub fn html_mut(&mut self) -> &mut String {
+        &mut self.html
+    }
+
+    /// HTML of the post up to its `<!-- more -->` marker, or its first
+    /// `summary_words` words, for index pages and feeds
+    pub fn summary_html(&self) -> Option<&str> {
+        self.summary_html.as_deref()
+    }
+
+    /// Plain text of the post as it goes into the search index, markdown posts only
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    /// Local files the post references, as written in it
+    pub fn assets(&self) -> &[String] {
+        &self.assets
+    }
+
+    pub fn words(&self) -> usize {
+        self.reading.words
+    }
 }
 
-impl TryFrom<&Content> for SearchableDoc {
-    type Error = Error;
+/// Counts of structural elements in a post
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct PostStats {
+    pub(crate) headings: usize,
+    /// Deepest heading level used, 0 when there are no headings
+    pub(crate) max_heading_level: usize,
+    pub(crate) internal_links: usize,
+    pub(crate) external_links: usize,
+    pub(crate) images: usize,
+    pub(crate) footnotes: usize,
+}
 
-    fn try_from(content: &Content) -> Result<Self, Self::Error> {
-        Ok(Self {
-            path: content.location.dst.clone(),
-            title: content.post.metadata.title.clone(),
-            date: content.post.metadata.date.clone(),
-            text: content
-                .post
-                .text
-                .clone()
-                .unwrap_or_else(|| {
-                    content
-                        .post
-                        .text
-                        .clone() // TODO: clean these up
-                        .unwrap_or(content.post.raw.clone())
-                })
-                .clone(),
-        })
+impl PostStats {
+    pub(crate) fn record(&mut self, event: &pulldown_cmark::Event) {
+        use pulldown_cmark::{Event, Tag};
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                self.headings += 1;
+                self.max_heading_level = self.max_heading_level.max(*level as usize);
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                if crate::assets::is_external(dest_url) {
+                    self.external_links += 1;
+                } else if !dest_url.starts_with('#') {
+                    self.internal_links += 1;
+                }
+            }
+            Event::Start(Tag::Image { .. }) => self.images += 1,
+            Event::FootnoteReference(_) => self.footnotes += 1,
+            _ => {}
+        }
+    }
+}
+
+/// A heading of a post, for its table of contents
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct TocEntry {
+    pub(crate) level: usize,
+    pub(crate) text: String,
+    pub(crate) id: String,
+}
+
+/// Words of a post split at its headings, for reading progress indicators
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct Reading {
+    pub(crate) words: usize,
+    pub(crate) sections: Vec<ReadingSection>,
+    /// Inside a heading, whose text goes to the section title instead
+    #[serde(skip)]
+    in_heading: bool,
+}
+
+/// The text from one heading up to the next, or before the first heading
+/// with an empty title and level 0
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ReadingSection {
+    pub(crate) title: String,
+    pub(crate) level: usize,
+    pub(crate) words: usize,
+    /// Words of the post before the section
+    pub(crate) offset: usize,
+}
+
+impl Reading {
+    pub(crate) fn record(&mut self, event: &pulldown_cmark::Event) {
+        use pulldown_cmark::{Event, Tag, TagEnd};
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                self.in_heading = true;
+                self.sections.push(ReadingSection { title: String::new(), level: *level as usize, words: 0, offset: self.words });
+            }
+            Event::End(TagEnd::Heading(_)) => self.in_heading = false,
+            Event::Text(text) | Event::Code(text) if self.in_heading => {
+                if let Some(section) = self.sections.last_mut() {
+                    section.title.push_str(text);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                let words = text.split_whitespace().count();
+                if self.sections.is_empty() && words > 0 {
+                    self.sections.push(ReadingSection { title: String::new(), level: 0, words: 0, offset: 0 });
+                }
+                if let Some(section) = self.sections.last_mut() {
+                    section.words += words;
+                }
+                self.words += words;
+            }
+            _ => {}
+        }
     }
 }
 
@@ -128,48 +431,277 @@ impl TryFrom<&str> for PostSourceKind {
     }
 }
 
+/// Shape of the URLs pages are linked with, to match how the host canonicalises them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// `posts/name.html`
+    #[default]
+    Html,
+    /// `posts/name/`, written as `posts/name/index.html`
+    Directory,
+    /// `posts/name/index.html`
+    Index,
+}
+
+impl LinkStyle {
+    /// Output file and link URL, both relative to the output root, of the page
+    /// `name` in `dir`
+    pub(crate) fn page(self, dir: &str, name: &str) -> (PathBuf, String) {
+        match self {
+            LinkStyle::Html => (PathBuf::from(dir).join(format!("{name}.html")), format!("{dir}/{name}.html")),
+            LinkStyle::Directory => (PathBuf::from(dir).join(name).join("index.html"), format!("{dir}/{name}/")),
+            LinkStyle::Index => (PathBuf::from(dir).join(name).join("index.html"), format!("{dir}/{name}/index.html")),
+        }
+    }
+
+    /// Output file and link URL of the index page of `dir`
+    pub(crate) fn index(self, dir: &str) -> (PathBuf, String) {
+        let path = PathBuf::from(dir).join("index.html");
+        match self {
+            LinkStyle::Directory => (path, format!("{dir}/")),
+            LinkStyle::Html | LinkStyle::Index => (path, format!("{dir}/index.html")),
+        }
+    }
+}
+
+fn default_branch() -> String {
+    String::from("main")
+}
+
+/// Where the site sources are hosted, for "edit this page" links
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryConfig {
+    /// Web URL of the repository, e.g. `https://github.com/user/site`
+    pub(crate) url: String,
+    #[serde(default = "default_branch")]
+    pub(crate) branch: String,
+    /// Directory of the repository holding the input directory, when it isn't the root
+    #[serde(default)]
+    pub(crate) dir: Option<PathBuf>,
+}
+
+impl RepositoryConfig {
+    /// URL of the page editing `source`, a path relative to the input directory
+    fn edit_url(&self, source: &Path) -> String {
+        let path = match &self.dir {
+            Some(dir) => dir.join(source),
+            None => source.to_path_buf(),
+        };
+        let path = path.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        format!("{}/edit/{}/{path}", self.url.trim_end_matches('/'), self.branch)
+    }
+}
+
+/// Where a post comes from and where it is written and served
 #[derive(Debug, Serialize, Clone)]
-pub(crate) struct LocationData {
+pub struct LocationData {
    pub(crate) src: PathBuf,
+   /// Source file relative to the input directory
+   pub(crate) source: PathBuf,
+   /// Page editing the source file in the repository, when `repository` is set
+   pub(crate) edit_url: Option<String>,
    pub(crate) dst: PathBuf,
-   pub(crate) url: PathBuf,
+   /// Output file of the page, relative to the output root
+   pub(crate) path: PathBuf,
+   /// URL the page is linked with, relative to the output root and shaped by `link_style`
+   pub(crate) url: String,
+   /// Root-relative URL the page is served at, including the deployment prefix
+   pub(crate) href: String,
+   /// Absolute URL of the page when `base_url` is set, `href` otherwise
+   pub(crate) canonical: String,
    pub(crate) filename: String,
 }
 
+/// Output file and link URL of a post from a `permalink` pattern. A trailing
+/// `/` writes the post as the `index.html` of its own directory
+fn permalink(pattern: &str, section: &str, slug: &str, date: &PostDate, style: LinkStyle) -> Result<(PathBuf, String)> {
+    let mut expanded = pattern.trim_start_matches('/').to_owned();
+    for (placeholder, format) in [(":year", "%Y"), (":month", "%m"), (":day", "%d")] {
+        if expanded.contains(placeholder) {
+            expanded = expanded.replace(placeholder, &date.format(format)?);
+        }
+    }
+    let expanded = expanded.replace(":section", section).replace(":slug", slug);
+
+    let style = match (expanded.ends_with('/'), style) {
+        (true, LinkStyle::Html) => LinkStyle::Directory,
+        (_, style) => style,
+    };
+    let trimmed = expanded.trim_end_matches('/');
+    let trimmed = trimmed.strip_suffix(".html").unwrap_or(trimmed);
+    let (dir, name) = trimmed.rsplit_once('/').unwrap_or(("", trimmed));
+    if name.is_empty() {
+        bail!("Permalink [{expanded}] does not name a page");
+    }
+    let (path, url) = style.page(dir, name);
+    // Pages at the root of the output have no directory in front of them
+    Ok((path, url.trim_start_matches('/').to_owned()))
+}
+
+/// Where the page being rendered is served, exposed to every template as
+/// `page.url`, root-relative, and `page.permalink`, absolute
+#[derive(Debug, Serialize)]
+pub(crate) struct PageUrl {
+    pub(crate) url: String,
+    pub(crate) permalink: String,
+}
+
+impl PageUrl {
+    pub(crate) fn new(href: &str, config: &crate::config::Config) -> Self {
+        Self { url: href.to_owned(), permalink: config.absolute_url(href) }
+    }
+}
+
 impl LocationData {
-    pub(crate) fn for_post(filepath: PathBuf, config: &crate::config::Config) -> Result<LocationData> {
-        let filename = filepath
-            .with_extension("html")
-            .file_name()
-            .with_context(|| {
-                anyhow::anyhow!("Unable to fetch location output filename for post: {filepath:?}")
-            })?
-            .to_string_lossy()
-            .to_string();
-
-        let url = PathBuf::from(POSTS_DIR).join(&filename);
-        let dst = config.output.join(&url);
+    /// Source file as found, under the input directory
+    pub fn src(&self) -> &Path {
+        &self.src
+    }
+
+    /// Source file relative to the input directory
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    /// Output file, under the output directory
+    pub fn dst(&self) -> &Path {
+        &self.dst
+    }
+
+    /// Output file relative to the output directory
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Root-relative URL the page is served at
+    pub fn href(&self) -> &str {
+        &self.href
+    }
+
+    /// Absolute URL of the page when `base_url` is set, `href` otherwise
+    pub fn canonical(&self) -> &str {
+        &self.canonical
+    }
+
+    /// Page editing the source file in the repository, when `repository` is set
+    pub fn edit_url(&self) -> Option<&str> {
+        self.edit_url.as_deref()
+    }
+
+    /// A post, written where the `permalink` pattern says, by default under its
+    /// section or under `posts/` when it is directly in `content/`. Its `slug`
+    /// front matter names it instead of its file name
+    pub(crate) fn for_post(filepath: PathBuf, section: Option<&str>, metadata: &Metadata, config: &crate::config::Config) -> Result<LocationData> {
+        let slug = match metadata.extra.get("slug").and_then(scalar_text) {
+            Some(slug) if slug.is_empty() || slug.contains(['/', '\\']) || slug == ".." => {
+                bail!("Slug [{slug}] of [{filepath:?}] is not a valid file name")
+            }
+            Some(slug) => slug,
+            None => crate::decrypt::plain_path(&filepath)
+                .file_stem()
+                .with_context(|| anyhow!("Unable to fetch location output filename for post: {filepath:?}"))?
+                .to_string_lossy()
+                .to_string(),
+        };
+        let filename = format!("{slug}.html");
+
+        let section = section.unwrap_or(POSTS_DIR);
+        let (path, url) = match &config.permalink {
+            Some(pattern) => permalink(pattern, section, &slug, &metadata.date, config.link_style())
+                .with_context(|| anyhow!("Unable to apply the permalink pattern [{pattern}] to [{filepath:?}]"))?,
+            None => config.link_style().page(section, &slug),
+        };
+        let dst = config.output.join(&path);
+        let href = config.site_url(&url);
+        let canonical = config.absolute_url(&href);
+        let (source, edit_url) = Self::source_of(&filepath, config);
 
         Ok(Self {
             src: filepath,
+            source,
+            edit_url,
             dst,
+            path,
             url,
+            href,
+            canonical,
             filename,
         })
     }
+
+    /// Source file relative to the input directory, and the URL to edit it at
+    fn source_of(filepath: &Path, config: &crate::config::Config) -> (PathBuf, Option<String>) {
+        let source = filepath.strip_prefix(&config.input).unwrap_or(filepath).to_path_buf();
+        let edit_url = config.repository.as_ref().map(|repository| repository.edit_url(&source));
+        (source, edit_url)
+    }
+
+    /// The homepage, which the `index.html` render template writes
+    pub(crate) fn for_home(filepath: PathBuf, config: &crate::config::Config) -> LocationData {
+        let path = PathBuf::from("index.html");
+        let (source, edit_url) = Self::source_of(&filepath, config);
+        Self {
+            src: filepath,
+            source,
+            edit_url,
+            dst: config.output.join(&path),
+            path,
+            url: String::new(),
+            href: config.site_url(""),
+            canonical: config.absolute_url(&config.site_url("")),
+            filename: String::from("index.html"),
+        }
+    }
 }
 
+/// A post and its place in the site
 #[derive(Debug, Serialize)]
-pub(crate) struct Content {
+pub struct Content {
     /// Whether any copying has to happen for this content or is it just
     /// virtualised and presented in the context
     pub(crate) bare: bool,
     /// Whether this content should be visible at all
     pub(crate) publish: bool,
+    /// Built only because drafts are, it is not published or held back until its date
+    pub(crate) draft: bool,
+    /// Subdirectory of `content/` the file is in, if any
+    pub(crate) section: Option<String>,
     pub(crate) location: LocationData,
     pub(crate) post: Post,
 }
 
+impl Content {
+    pub fn post(&self) -> &Post {
+        &self.post
+    }
+
+    pub fn location(&self) -> &LocationData {
+        &self.location
+    }
+
+    /// Subdirectory of `content/` the file is in, if any
+    pub fn section(&self) -> Option<&str> {
+        self.section.as_deref()
+    }
+
+    /// Whether the build writes the post, drafts included when they are built
+    pub fn is_published(&self) -> bool {
+        self.publish
+    }
+
+    /// Whether the post is only built because drafts are
+    pub fn is_draft(&self) -> bool {
+        self.draft
+    }
+
+    /// Whether the post is only data for templates, without a page of its own
+    pub fn is_bare(&self) -> bool {
+        self.bare
+    }
+}
+
 impl std::fmt::Display for Content {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "[{:?}]", self.post.metadata.name,)
@@ -178,9 +710,38 @@ impl std::fmt::Display for Content {
 
 /// The description for the whole page.
 #[derive(Debug, Serialize)]
-pub(crate) struct AvailableContent {
+pub struct AvailableContent {
     pub(crate) at: DateTime<Utc>,
     pub(crate) content: Vec<Content>,
+    /// Front matter and body of `content/_index.md` (or `home.md`) for the homepage
+    pub(crate) home: Option<Content>,
+    /// Subdirectories of `content/` holding posts
+    pub(crate) sections: Vec<Section>,
+    /// Include files served from content hash paths
+    #[serde(skip)]
+    pub(crate) media: MediaStore,
+    #[serde(skip)]
+    pub(crate) plugins: Plugins,
+    #[serde(skip)]
+    pub(crate) scripts: Scripts,
+}
+
+impl AvailableContent {
+    /// Every post, unpublished ones included, newest first and those of the
+    /// same date by name in the order of the site's `collation`
+    pub fn posts(&self) -> &[Content] {
+        &self.content
+    }
+
+    /// The homepage, from `content/_index.md` or `home.md`
+    pub fn home(&self) -> Option<&Content> {
+        self.home.as_ref()
+    }
+
+    /// When the content was collected
+    pub fn collected_at(&self) -> DateTime<Utc> {
+        self.at
+    }
 }
 
 impl Default for AvailableContent {
@@ -188,6 +749,100 @@ impl Default for AvailableContent {
         Self {
             at: Utc::now(),
             content: Default::default(),
+            home: None,
+            sections: Vec::new(),
+            media: Default::default(),
+            plugins: Default::default(),
+            scripts: Default::default(),
+        }
+    }
+}
+
+/// What was built, from what and when. Exposed to templates as `build`.
+#[derive(Debug, Serialize)]
+pub(crate) struct BuildInfo {
+    pub(crate) version: &'static str,
+    /// Commit of the site repository, if the input is a git checkout
+    pub(crate) commit: Option<String>,
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) profile: Option<String>,
+    /// Public key the manifest of the build is signed with, for integrity badges
+    pub(crate) signed_by: Option<String>,
+}
+
+impl BuildInfo {
+    pub(crate) fn collect(config: &crate::config::Config) -> Self {
+        let commit = Command::new("git")
+            .arg("-C")
+            .arg(&config.input)
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|commit| commit.trim().to_owned());
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            commit,
+            at: Utc::now(),
+            profile: config.profile.clone(),
+            // A broken key fails the build once the manifest is signed
+            signed_by: config.signing.as_ref().and_then(|signing| signing.public_key(config).ok()),
+        }
+    }
+
+    /// HTML comment describing the build, appended to pages when stamping is on
+    pub(crate) fn stamp(&self) -> String {
+        format!(
+            "\n<!-- mub {} | commit {} | profile {} | built {} -->\n",
+            self.version,
+            self.commit.as_deref().unwrap_or("unknown"),
+            self.profile.as_deref().unwrap_or("default"),
+            self.at.to_rfc3339()
+        )
+    }
+}
+
+#[cfg(test)]
+impl Content {
+    /// A post with only what lists of posts look at, dated as `YYYY-MM-DD`
+    /// and written to `name.html`
+    pub(crate) fn stub(name: &str, date: &str, publish: bool) -> Self {
+        let date = PostDate::parse(date, &[String::from("%Y-%m-%d")]).expect("a YYYY-MM-DD date");
+        let file = format!("{name}.html");
+        Self {
+            bare: false,
+            publish,
+            draft: false,
+            section: None,
+            location: LocationData {
+                src: PathBuf::from(format!("content/{name}.md")),
+                source: PathBuf::from(format!("{name}.md")),
+                edit_url: None,
+                dst: PathBuf::from(format!("dist/{file}")),
+                path: PathBuf::from(&file),
+                url: file.clone(),
+                href: format!("/{file}"),
+                canonical: format!("/{file}"),
+                filename: file,
+            },
+            post: Post {
+                metadata: Metadata { name: name.to_owned(), title: name.to_owned(), template: String::from("post.html"), date, publish, bare: false, extra: HashMap::new() },
+                front_matter: String::new(),
+                modified: Utc::now(),
+                raw: String::new(),
+                html: String::new(),
+                summary_html: None,
+                text: None,
+                assets: Vec::new(),
+                stats: PostStats::default(),
+                reading: Reading::default(),
+                toc: Vec::new(),
+                enclosure: None,
+                transcript: Vec::new(),
+                chapters: Vec::new(),
+            },
         }
     }
 }
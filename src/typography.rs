@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, types::Metadata};
+
+/// Typographic clean up applied to markdown content
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TypographyConfig {
+    /// Curly quotes, en/em dashes and ellipses
+    #[serde(default)]
+    pub(crate) smart_punctuation: bool,
+    /// Glue the last word of a paragraph to the previous one with a
+    /// non-breaking space when it is at most this many characters long
+    #[serde(default)]
+    pub(crate) widows: Option<usize>,
+    /// Overrides for posts in a given language, keyed by the `lang` front matter
+    /// value (falling back to `site.lang`)
+    #[serde(default)]
+    pub(crate) languages: HashMap<String, Typography>,
+}
+
+/// Per-language typography settings, unset values fall back to the site-wide ones
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Typography {
+    #[serde(default)]
+    pub(crate) smart_punctuation: Option<bool>,
+    #[serde(default)]
+    pub(crate) widows: Option<usize>,
+}
+
+impl Typography {
+    /// Settings for a post, with its language overrides applied
+    pub(crate) fn for_post(config: &Config, metadata: &Metadata) -> Self {
+        let settings = &config.typography;
+        let lang = metadata
+            .extra_str("lang")
+            .or_else(|| config.site.get("lang").and_then(|lang| lang.as_str()));
+        let overrides = lang
+            .and_then(|lang| settings.languages.get(lang))
+            .cloned()
+            .unwrap_or_default();
+        Self {
+            smart_punctuation: Some(
+                overrides
+                    .smart_punctuation
+                    .unwrap_or(settings.smart_punctuation),
+            ),
+            widows: overrides.widows.or(settings.widows),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    path::Path,
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{anyhow, Context, Result};
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{check::Diagnostic, process::code_block_info, types::Content};
+
+/// Flag marking a fenced code block as a sample that must compile
+const VERIFY_FLAG: &str = "verify";
+
+/// Distinguishes the scratch directories of samples verified in one process
+static SAMPLE: AtomicUsize = AtomicUsize::new(0);
+
+/// How to verify code samples of one language
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyCommand {
+    /// Program and arguments, `{file}` is replaced with the path of the sample,
+    /// e.g. `["rustc", "--edition", "2021", "--emit=metadata", "--crate-type=lib", "{file}"]`
+    pub(crate) command: Vec<String>,
+    /// Extension the sample file is written with
+    pub(crate) extension: String,
+}
+
+/// Fenced code blocks flagged `verify` in a markdown source, with their language
+fn samples(markdown: &str) -> Vec<(String, String)> {
+    let mut samples = Vec::new();
+    let mut current: Option<(String, String)> = None;
+    for event in pulldown_cmark::Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let (language, flags) = code_block_info(&info);
+                if flags.contains(&VERIFY_FLAG) {
+                    current = Some((language.to_owned(), String::new()));
+                }
+            }
+            Event::Text(text) => {
+                if let Some((_, code)) = current.as_mut() {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => samples.extend(current.take()),
+            _ => {}
+        }
+    }
+    samples
+}
+
+/// Run one sample through its command in a scratch directory
+fn run(command: &VerifyCommand, code: &str) -> Result<Option<String>> {
+    let (program, args) = command
+        .command
+        .split_first()
+        .context("Verify command is empty")?;
+    let scratch = std::env::temp_dir().join(format!(
+        "mub-verify-{}-{}",
+        std::process::id(),
+        SAMPLE.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&scratch)
+        .with_context(|| anyhow!("Unable to create scratch directory [{}]", scratch.display()))?;
+    let file = scratch.join(format!("sample.{}", command.extension));
+    std::fs::write(&file, code)
+        .with_context(|| anyhow!("Unable to write code sample [{}]", file.display()))?;
+
+    let output = Command::new(program)
+        .args(
+            args.iter()
+                .map(|arg| arg.replace("{file}", &file.to_string_lossy())),
+        )
+        .current_dir(&scratch)
+        .output()
+        .with_context(|| anyhow!("Unable to run verify command [{program}]"));
+    let _ = std::fs::remove_dir_all(&scratch);
+
+    let output = output?;
+    Ok((!output.status.success()).then(|| {
+        let mut message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        if message.is_empty() {
+            message = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        }
+        message
+    }))
+}
+
+/// Check every `verify` sample of a post, returning a diagnostic per failure
+pub(crate) fn verify(
+    content: &Content,
+    commands: &std::collections::HashMap<String, VerifyCommand>,
+) -> Result<Vec<Diagnostic>> {
+    let source: &Path = &content.location.src;
+    let mut failures = Vec::new();
+    for (index, (language, code)) in samples(&content.post.raw).into_iter().enumerate() {
+        let Some(command) = commands.get(&language) else {
+            failures.push(Diagnostic {
+                source: source.to_path_buf(),
+                message: format!(
+                    "sample #{} is flagged `verify` but no command is configured for [{language}]",
+                    index + 1
+                ),
+            });
+            continue;
+        };
+        if let Some(error) = run(command, &code)? {
+            failures.push(Diagnostic {
+                source: source.to_path_buf(),
+                message: format!("{language} sample #{} does not verify:\n{error}", index + 1),
+            });
+        }
+    }
+    Ok(failures)
+}